@@ -0,0 +1,263 @@
+/// Represents a bit-level writer
+///
+/// FLAC packs most of its fields MSBit-first without regard to byte
+/// boundaries. This writer keeps track of the number of bits written so far
+/// and appends new bits right after the last written bit.
+pub struct BitstreamWriter {
+    buffer: Vec <u8>,
+    bit_len: u64,
+}
+
+/// Represents a bit-level reader
+///
+/// This reader is the counterpart of `BitstreamWriter` and reads
+/// fields MSBit-first from a slice of bytes.
+pub struct BitstreamReader <'a> {
+    data: &'a [u8],
+    bit_pos: u64,
+}
+
+impl BitstreamWriter {
+    /// Create a new empty bitstream
+    pub fn new() -> Self {
+        BitstreamWriter {
+            buffer: Vec::new(),
+            bit_len: 0,
+        }
+    }
+
+    /// Get the number of extra unused bits at the last byte of the stream
+    pub fn extra_bits_len(&self) -> u8 {
+        ((8 - self.bit_len % 8) % 8) as u8
+    }
+
+    /// Get the total number of bits written to the stream
+    pub fn bit_len(&self) -> u64 {
+        self.bit_len
+    }
+
+    /// Write the `num_bits` LSBits of `value`, MSBit first
+    pub fn write_bits(&mut self, value: u64, num_bits: u8) {
+        let mut remaining = num_bits;
+        while remaining > 0 {
+            let mut free = self.extra_bits_len();
+            if free == 0 {
+                self.buffer.push(0);
+                free = 8;
+            }
+
+            let take = free.min(remaining);
+            let chunk = (value >> (remaining - take)) & ((1u64 << take) - 1);
+            if let Some(last) = self.buffer.last_mut() {
+                *last |= (chunk << (free - take)) as u8;
+            }
+
+            remaining -= take;
+            self.bit_len += take as u64;
+        }
+    }
+
+    /// Write a signed value as a `num_bits`-bit two's complement number
+    pub fn write_signed(&mut self, value: i64, num_bits: u8) {
+        if num_bits == 0 {
+            return;
+        }
+        let mask = if num_bits >= 64 { u64::MAX } else { (1u64 << num_bits) - 1 };
+        self.write_bits(value as u64 & mask, num_bits);
+    }
+
+    /// Write a number in unary, i.e. `quotient` zeros followed by a single one
+    pub fn write_unary(&mut self, mut quotient: u64) {
+        while quotient >= 32 {
+            self.write_bits(0, 32);
+            quotient -= 32;
+        }
+        self.write_bits(1, quotient as u8 + 1);
+    }
+
+    /// Write whole bytes to the stream
+    pub fn write_bytes(&mut self, bytes: &[u8]) {
+        if self.extra_bits_len() == 0 {
+            self.buffer.extend_from_slice(bytes);
+            self.bit_len += 8 * bytes.len() as u64;
+        }
+        else {
+            for byte in bytes.iter() {
+                self.write_bits(*byte as u64, 8);
+            }
+        }
+    }
+
+    /// Write a stream that is not necessarily byte-aligned
+    ///
+    /// The `extra_bits_len` LSBits of the last byte of `bytes` are
+    /// not part of the stream and are skipped.
+    pub fn write_partial_bytes(&mut self, bytes: &[u8], extra_bits_len: u8) {
+        if let Some((last, head)) = bytes.split_last() {
+            self.write_bytes(head);
+            self.write_bits((*last >> extra_bits_len) as u64, 8 - extra_bits_len);
+        }
+    }
+
+    /// Pad the stream with "0" bits until it is byte-aligned
+    pub fn align(&mut self) {
+        self.bit_len += self.extra_bits_len() as u64;
+    }
+
+    /// Get the bytes written so far
+    pub fn bytes(&self) -> &Vec <u8> {
+        &self.buffer
+    }
+
+    /// Consume the stream and get its bytes
+    ///
+    /// Any unused bits at the last byte are set to "0".
+    pub fn into_bytes(self) -> Vec <u8> {
+        self.buffer
+    }
+
+    /// Consume the stream and get its bytes and the number of extra
+    /// unused bits at the last byte, respectively
+    pub fn into_partial_bytes(self) -> (Vec <u8>, u8) {
+        let extra_bits_len = self.extra_bits_len();
+        (self.buffer, extra_bits_len)
+    }
+}
+
+impl Default for BitstreamWriter {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl <'a> BitstreamReader <'a> {
+    /// Create a reader starting at the first bit of `data`
+    pub fn new(data: &'a [u8]) -> Self {
+        BitstreamReader {
+            data,
+            bit_pos: 0,
+        }
+    }
+
+    /// Get the position of the reader in bytes, rounded down
+    pub fn byte_pos(&self) -> usize {
+        (self.bit_pos / 8) as usize
+    }
+
+    /// Check whether the reader is at a byte boundary
+    pub fn is_aligned(&self) -> bool {
+        self.bit_pos.is_multiple_of(8)
+    }
+
+    /// Skip bits until the reader is at a byte boundary
+    pub fn align(&mut self) {
+        self.bit_pos = self.bit_pos.div_ceil(8) * 8;
+    }
+
+    /// Read a `num_bits`-bit unsigned number
+    ///
+    /// # Errors
+    /// Returns `None` if the stream does not have enough bits left.
+    pub fn read_bits(&mut self, num_bits: u8) -> Option <u64> {
+        if self.bit_pos + num_bits as u64 > 8 * self.data.len() as u64 {
+            return None;
+        }
+
+        let mut value: u64 = 0;
+        let mut remaining = num_bits;
+        while remaining > 0 {
+            let byte = self.data[(self.bit_pos / 8) as usize];
+            let avail = 8 - (self.bit_pos % 8) as u8;
+            let take = avail.min(remaining);
+            let chunk = (byte >> (avail - take)) as u64 & ((1u64 << take) - 1);
+
+            value = (value << take) | chunk;
+            remaining -= take;
+            self.bit_pos += take as u64;
+        }
+
+        Some(value)
+    }
+
+    /// Read a `num_bits`-bit two's complement number
+    ///
+    /// # Errors
+    /// Returns `None` if the stream does not have enough bits left.
+    pub fn read_signed(&mut self, num_bits: u8) -> Option <i64> {
+        if num_bits == 0 {
+            return Some(0);
+        }
+        let value = self.read_bits(num_bits)?;
+        let shift = 64 - num_bits as u32;
+        Some(((value << shift) as i64) >> shift)
+    }
+
+    /// Read a unary-coded number, i.e. the number of zeros before a one
+    ///
+    /// # Errors
+    /// Returns `None` if the stream ends before the terminating one.
+    pub fn read_unary(&mut self) -> Option <u64> {
+        let mut count = 0;
+        while self.read_bits(1)? == 0 {
+            count += 1;
+        }
+        Some(count)
+    }
+
+    /// Read `len` whole bytes from a byte-aligned position
+    ///
+    /// # Errors
+    /// Returns `None` if the reader is not byte-aligned or the stream
+    /// does not have enough bytes left.
+    pub fn read_bytes(&mut self, len: usize) -> Option <&'a [u8]> {
+        if !self.is_aligned() {
+            return None;
+        }
+        let start = self.byte_pos();
+        let bytes = self.data.get(start..start + len)?;
+        self.bit_pos += 8 * len as u64;
+        Some(bytes)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn write_unaligned_fields() {
+        let mut writer = BitstreamWriter::new();
+        writer.write_bits(0b0, 1);
+        writer.write_bits(0b000001, 6);
+        writer.write_bits(0b1, 1);
+        writer.write_unary(1);
+        writer.write_signed(0x63f4 >> 2, 14);
+
+        assert_eq!(writer.bit_len(), 24);
+        assert_eq!(writer.into_bytes(), vec![0x03, 0x58, 0xfd]);
+    }
+
+    #[test]
+    fn write_partial_bytes() {
+        let mut writer = BitstreamWriter::new();
+        writer.write_bits(0b101, 3);
+        writer.write_partial_bytes(&[0xff, 0xe0], 5);
+        writer.align();
+
+        assert_eq!(writer.extra_bits_len(), 0);
+        assert_eq!(writer.into_bytes(), vec![0xbf, 0xfc]);
+    }
+
+    #[test]
+    fn read_back() {
+        let data = [0x03, 0x58, 0xfd];
+        let mut reader = BitstreamReader::new(&data);
+
+        assert_eq!(reader.read_bits(1), Some(0));
+        assert_eq!(reader.read_bits(6), Some(1));
+        assert_eq!(reader.read_bits(1), Some(1));
+        assert_eq!(reader.read_unary(), Some(1));
+        assert_eq!(reader.read_signed(14), Some(0x63f4 >> 2));
+        assert_eq!(reader.read_bits(1), None);
+    }
+}
@@ -0,0 +1,651 @@
+use super::{FlacChannelAssignment, FlacWriterError};
+use super::lpc::window::ApodizationWindow;
+use super::metadata::MAX_BLOCK_LENGTH;
+use super::metadata::cuesheet::CueSheet;
+use super::metadata::picture::{FlacPicture, PICTURE_TYPE_FILE_ICON, PICTURE_TYPE_OTHER_FILE_ICON};
+use super::metadata::seektable::SeekPointSpacing;
+use super::metadata::vorbis_comment::VorbisComment;
+
+/// Highest compression preset, mirroring the reference encoder's `-8`
+const MAX_PRESET: u8 = 8;
+
+/// Preset used when no preset is selected, mirroring the reference encoder
+const DEFAULT_PRESET: u8 = 5;
+
+/// Size of the PADDING block in bytes unless set, mirroring the reference encoder
+const DEFAULT_PADDING_LEN: u32 = 8192;
+
+/// Represents how the two channels of a stereo stream are coded
+///
+/// `Independent` codes the left and right channels as they are. `MidSide` tries
+/// every channel assignment on each frame and keeps the smallest one, while
+/// `AdaptiveMidSide` picks the assignment from a cheaper estimate. `Forced` codes
+/// every frame with the same channel assignment. Streams that do not have exactly
+/// two channels are always coded independently.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum StereoMode {
+    Independent,
+    AdaptiveMidSide,
+    MidSide,
+    Forced(FlacChannelAssignment),
+}
+
+/// Represents which QLP coefficient precisions are tried for each LPC subframe
+///
+/// `Off` only tries the precision set in the configuration, or the one picked
+/// from the bit depth and block size. `Around(n)` also tries the `n` precisions
+/// below and above it, and `All` tries every precision from 5 to 15.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum QlpPrecisionSearch {
+    Off,
+    Around(u8),
+    All,
+}
+
+/// Represents the size of the PADDING block written after the other metadata blocks
+///
+/// `Bytes(n)` reserves `n` bytes. `ExpectedTags(n)` reserves enough bytes for the
+/// VORBIS_COMMENT and PICTURE blocks to grow to `n` bytes in total, including their
+/// headers, without rewriting the file. No PADDING block is written if they already
+/// take up that much, or with `None`.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Padding {
+    None,
+    Bytes(u32),
+    ExpectedTags(u32),
+}
+
+/// Represents the settings of the FLAC encoder
+///
+/// A configuration is created through `EncoderConfigBuilder`, which starts from one
+/// of the compression presets 0 to 8 and lets each setting be overridden. The
+/// presets follow the compression levels of the reference encoder, where higher
+/// levels trade encoding speed for smaller files.
+///
+/// | Preset | Block size | Max LPC order | Partition orders |      Stereo mode |
+/// |--------|------------|---------------|------------------|------------------|
+/// |      0 |       1152 |             0 |           0 to 3 |      Independent |
+/// |      1 |       1152 |             0 |           0 to 3 |  AdaptiveMidSide |
+/// |      2 |       1152 |             0 |           0 to 3 |          MidSide |
+/// |      3 |       4096 |             6 |           0 to 4 |      Independent |
+/// |      4 |       4096 |             8 |           0 to 4 |  AdaptiveMidSide |
+/// |      5 |       4096 |             8 |           0 to 5 |          MidSide |
+/// |      6 |       4096 |             8 |           0 to 6 |          MidSide |
+/// |      7 |       4096 |            12 |           0 to 6 |          MidSide |
+/// |      8 |       4096 |            12 |           0 to 6 |          MidSide |
+///
+/// The apodization windows are `tukey(5e-1)` up to preset 5,
+/// `tukey(5e-1);partial_tukey(2)` for presets 6 and 7, and
+/// `tukey(5e-1);partial_tukey(2);punchout_tukey(3)` for preset 8.
+///
+/// None of the presets set a QLP coefficient precision, so the precision is picked
+/// from the bit depth and block size. None of them search for the precision, do an
+/// exhaustive model search, use variable block sizes, reduce the bit depth, verify
+/// the frames or write a SEEKTABLE, and all of them encode on a single thread without
+/// any tags, cue sheet, pictures or foreign metadata and reserve a PADDING block of 8 KiB.
+#[derive(Clone, Debug, PartialEq)]
+pub struct EncoderConfig {
+    block_size: u16,
+    max_lpc_order: u8,
+    qlp_precision: Option <u8>,
+    qlp_precision_search: QlpPrecisionSearch,
+    min_partition_order: u8,
+    max_partition_order: u8,
+    exhaustive_model_search: bool,
+    stereo_mode: StereoMode,
+    variable_block_size: bool,
+    apodization: Vec <ApodizationWindow>,
+    reduce_bit_depth: bool,
+    threads: usize,
+    max_frames_in_flight: Option <usize>,
+    verify: bool,
+    seektable: Option <SeekPointSpacing>,
+    vorbis_comment: Option <VorbisComment>,
+    cuesheet: Option <CueSheet>,
+    pictures: Vec <FlacPicture>,
+    keep_foreign_metadata: bool,
+    padding: Padding,
+}
+
+/// Represents a builder of an `EncoderConfig`
+///
+/// Overrides are applied on top of the selected preset regardless of the order
+/// in which the methods are called.
+#[derive(Clone, Debug, Default)]
+pub struct EncoderConfigBuilder {
+    preset: Option <u8>,
+    block_size: Option <u16>,
+    max_lpc_order: Option <u8>,
+    qlp_precision: Option <u8>,
+    qlp_precision_search: Option <QlpPrecisionSearch>,
+    min_partition_order: Option <u8>,
+    max_partition_order: Option <u8>,
+    exhaustive_model_search: Option <bool>,
+    stereo_mode: Option <StereoMode>,
+    variable_block_size: Option <bool>,
+    apodization: Option <String>,
+    reduce_bit_depth: Option <bool>,
+    threads: Option <usize>,
+    max_frames_in_flight: Option <usize>,
+    verify: Option <bool>,
+    seektable: Option <SeekPointSpacing>,
+    vorbis_comment: Option <VorbisComment>,
+    cuesheet: Option <CueSheet>,
+    pictures: Vec <FlacPicture>,
+    keep_foreign_metadata: Option <bool>,
+    padding: Option <Padding>,
+}
+
+impl EncoderConfig {
+    /// Create a builder starting from the default preset
+    pub fn builder() -> EncoderConfigBuilder {
+        EncoderConfigBuilder::new()
+    }
+
+    /// Create the configuration of a compression preset without any overrides
+    ///
+    /// # Errors
+    /// Returns `FlacWriterError::InvalidConfigError` if `level` is larger than 8.
+    pub fn preset(level: u8) -> Result <Self, FlacWriterError> {
+        EncoderConfigBuilder::new().preset(level).build()
+    }
+
+    /// Get the settings of a compression preset
+    fn from_preset(level: u8) -> Option <Self> {
+        let (block_size, max_lpc_order, max_partition_order, stereo_mode) = match level {
+            0 => (1152, 0, 3, StereoMode::Independent),
+            1 => (1152, 0, 3, StereoMode::AdaptiveMidSide),
+            2 => (1152, 0, 3, StereoMode::MidSide),
+            3 => (4096, 6, 4, StereoMode::Independent),
+            4 => (4096, 8, 4, StereoMode::AdaptiveMidSide),
+            5 => (4096, 8, 5, StereoMode::MidSide),
+            6 => (4096, 8, 6, StereoMode::MidSide),
+            7 => (4096, 12, 6, StereoMode::MidSide),
+            8 => (4096, 12, 6, StereoMode::MidSide),
+            _ => return None,
+        };
+        let apodization = match level {
+            0..=5 => "tukey(5e-1)",
+            6 | 7 => "tukey(5e-1);partial_tukey(2)",
+            _ => "tukey(5e-1);partial_tukey(2);punchout_tukey(3)",
+        };
+
+        Some(EncoderConfig {
+            block_size,
+            max_lpc_order,
+            qlp_precision: None,
+            qlp_precision_search: QlpPrecisionSearch::Off,
+            min_partition_order: 0,
+            max_partition_order,
+            exhaustive_model_search: false,
+            stereo_mode,
+            variable_block_size: false,
+            apodization: ApodizationWindow::parse_spec(apodization)?,
+            reduce_bit_depth: false,
+            threads: 1,
+            max_frames_in_flight: None,
+            verify: false,
+            seektable: None,
+            vorbis_comment: None,
+            cuesheet: None,
+            pictures: Vec::new(),
+            keep_foreign_metadata: false,
+            padding: Padding::Bytes(DEFAULT_PADDING_LEN),
+        })
+    }
+
+    /// Get the number of inter-channel samples in each frame
+    pub fn block_size(&self) -> u16 {
+        self.block_size
+    }
+
+    /// Get the largest LPC order tried. LPC subframes are not used if this is zero.
+    pub fn max_lpc_order(&self) -> u8 {
+        self.max_lpc_order
+    }
+
+    /// Get the QLP coefficient precision in bits, or `None` if it is picked
+    /// from the bit depth and block size
+    pub fn qlp_precision(&self) -> Option <u8> {
+        self.qlp_precision
+    }
+
+    /// Get which QLP coefficient precisions are tried for each LPC subframe
+    pub fn qlp_precision_search(&self) -> QlpPrecisionSearch {
+        self.qlp_precision_search
+    }
+
+    /// Get the smallest Rice partition order tried
+    pub fn min_partition_order(&self) -> u8 {
+        self.min_partition_order
+    }
+
+    /// Get the largest Rice partition order tried
+    pub fn max_partition_order(&self) -> u8 {
+        self.max_partition_order
+    }
+
+    /// Check whether every subframe type and predictor order is compared by its
+    /// exact size instead of only the estimated best ones
+    pub fn exhaustive_model_search(&self) -> bool {
+        self.exhaustive_model_search
+    }
+
+    /// Get how the channels of a stereo stream are coded
+    pub fn stereo_mode(&self) -> StereoMode {
+        self.stereo_mode
+    }
+
+    /// Check whether each block of `block_size()` samples may be split into shorter
+    /// frames. The frame headers of such a stream store the sample number instead of
+    /// the frame number.
+    pub fn variable_block_size(&self) -> bool {
+        self.variable_block_size
+    }
+
+    /// Get the windows applied to each block before its LPC analysis
+    pub fn apodization(&self) -> &Vec <ApodizationWindow> {
+        &self.apodization
+    }
+
+    /// Check whether the stream is encoded with the effective bit depth of the
+    /// Wave file instead of its nominal bit depth. The unused low-order bits of
+    /// every sample are then dropped, so the encoding stays lossless.
+    pub fn reduce_bit_depth(&self) -> bool {
+        self.reduce_bit_depth
+    }
+
+    /// Get the number of threads encoding the frames. The output does not
+    /// depend on the number of threads.
+    pub fn threads(&self) -> usize {
+        self.threads
+    }
+
+    /// Get the largest number of blocks being encoded or waiting to be written
+    /// at any time when encoding on several threads. This is twice the number
+    /// of threads unless set.
+    pub fn max_frames_in_flight(&self) -> usize {
+        self.max_frames_in_flight.unwrap_or(2 * self.threads)
+    }
+
+    /// Check whether each frame is decoded right after it is built and compared
+    /// with the samples it was built from
+    pub fn verify(&self) -> bool {
+        self.verify
+    }
+
+    /// Get where the points of the SEEKTABLE are placed, if one is written
+    pub fn seektable(&self) -> Option <&SeekPointSpacing> {
+        self.seektable.as_ref()
+    }
+
+    /// Get the tags written in a VORBIS_COMMENT block, if any
+    pub fn vorbis_comment(&self) -> Option <&VorbisComment> {
+        self.vorbis_comment.as_ref()
+    }
+
+    /// Get the cue sheet written in a CUESHEET block, if any
+    pub fn cuesheet(&self) -> Option <&CueSheet> {
+        self.cuesheet.as_ref()
+    }
+
+    /// Get the pictures written in PICTURE blocks, in order
+    pub fn pictures(&self) -> &Vec <FlacPicture> {
+        &self.pictures
+    }
+
+    /// Check whether the RIFF header and the chunks of the WAV file other than
+    /// its samples are kept in APPLICATION blocks
+    pub fn keep_foreign_metadata(&self) -> bool {
+        self.keep_foreign_metadata
+    }
+
+    /// Get the size of the PADDING block
+    pub fn padding(&self) -> Padding {
+        self.padding
+    }
+}
+
+impl Default for EncoderConfig {
+    fn default() -> Self {
+        Self::from_preset(DEFAULT_PRESET).unwrap()
+    }
+}
+
+impl EncoderConfigBuilder {
+    /// Create a builder starting from the default preset 5
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Select the compression preset from 0 (fastest) to 8 (smallest)
+    pub fn preset(mut self, level: u8) -> Self {
+        self.preset = Some(level);
+        self
+    }
+
+    /// Set the number of inter-channel samples in each frame
+    pub fn block_size(mut self, block_size: u16) -> Self {
+        self.block_size = Some(block_size);
+        self
+    }
+
+    /// Set the largest LPC order tried, from 0 to 32
+    pub fn max_lpc_order(mut self, max_lpc_order: u8) -> Self {
+        self.max_lpc_order = Some(max_lpc_order);
+        self
+    }
+
+    /// Set the QLP coefficient precision in bits, from 5 to 15
+    pub fn qlp_precision(mut self, qlp_precision: u8) -> Self {
+        self.qlp_precision = Some(qlp_precision);
+        self
+    }
+
+    /// Set which QLP coefficient precisions are tried for each LPC subframe
+    pub fn qlp_precision_search(mut self, qlp_precision_search: QlpPrecisionSearch) -> Self {
+        self.qlp_precision_search = Some(qlp_precision_search);
+        self
+    }
+
+    /// Set the smallest Rice partition order tried, from 0 to 15
+    pub fn min_partition_order(mut self, min_partition_order: u8) -> Self {
+        self.min_partition_order = Some(min_partition_order);
+        self
+    }
+
+    /// Set the largest Rice partition order tried, from 0 to 15
+    pub fn max_partition_order(mut self, max_partition_order: u8) -> Self {
+        self.max_partition_order = Some(max_partition_order);
+        self
+    }
+
+    /// Enable or disable the exhaustive model search
+    pub fn exhaustive_model_search(mut self, exhaustive_model_search: bool) -> Self {
+        self.exhaustive_model_search = Some(exhaustive_model_search);
+        self
+    }
+
+    /// Set how the channels of a stereo stream are coded
+    pub fn stereo_mode(mut self, stereo_mode: StereoMode) -> Self {
+        self.stereo_mode = Some(stereo_mode);
+        self
+    }
+
+    /// Enable or disable variable block sizes
+    pub fn variable_block_size(mut self, variable_block_size: bool) -> Self {
+        self.variable_block_size = Some(variable_block_size);
+        self
+    }
+
+    /// Set the apodization windows with a list such as `tukey(0.5);partial_tukey(2)`
+    /// 
+    /// See `ApodizationWindow::parse_spec` for the syntax.
+    pub fn apodization(mut self, spec: &str) -> Self {
+        self.apodization = Some(spec.to_string());
+        self
+    }
+
+    /// Enable or disable encoding with the effective bit depth of the Wave file
+    pub fn reduce_bit_depth(mut self, reduce_bit_depth: bool) -> Self {
+        self.reduce_bit_depth = Some(reduce_bit_depth);
+        self
+    }
+
+    /// Set the number of threads encoding the frames, at least 1
+    pub fn threads(mut self, threads: usize) -> Self {
+        self.threads = Some(threads);
+        self
+    }
+
+    /// Set the largest number of blocks being encoded or waiting to be written
+    /// at any time when encoding on several threads, at least 1
+    pub fn max_frames_in_flight(mut self, max_frames_in_flight: usize) -> Self {
+        self.max_frames_in_flight = Some(max_frames_in_flight);
+        self
+    }
+
+    /// Enable or disable decoding and checking each frame right after it is built
+    pub fn verify(mut self, verify: bool) -> Self {
+        self.verify = Some(verify);
+        self
+    }
+
+    /// Write a SEEKTABLE with points placed according to `spacing`
+    pub fn seektable(mut self, spacing: SeekPointSpacing) -> Self {
+        self.seektable = Some(spacing);
+        self
+    }
+
+    /// Write the tags of `vorbis_comment` in a VORBIS_COMMENT block
+    pub fn vorbis_comment(mut self, vorbis_comment: VorbisComment) -> Self {
+        self.vorbis_comment = Some(vorbis_comment);
+        self
+    }
+
+    /// Write the tracks of `cuesheet` in a CUESHEET block
+    pub fn cuesheet(mut self, cuesheet: CueSheet) -> Self {
+        self.cuesheet = Some(cuesheet);
+        self
+    }
+
+    /// Add a picture written in a PICTURE block after the ones added before
+    pub fn picture(mut self, picture: FlacPicture) -> Self {
+        self.pictures.push(picture);
+        self
+    }
+
+    /// Keep the RIFF header and the chunks of the WAV file other than its samples
+    /// in APPLICATION blocks, so that the WAV file can be restored byte for byte
+    pub fn keep_foreign_metadata(mut self, keep_foreign_metadata: bool) -> Self {
+        self.keep_foreign_metadata = Some(keep_foreign_metadata);
+        self
+    }
+
+    /// Set the size of the PADDING block
+    pub fn padding(mut self, padding: Padding) -> Self {
+        self.padding = Some(padding);
+        self
+    }
+
+    /// Create the configuration from the selected preset and overrides
+    ///
+    /// # Errors
+    /// Returns `FlacWriterError::InvalidConfigError` if the preset is larger than 8,
+    /// the block size is smaller than 16, the maximum LPC order is larger than 32,
+    /// the QLP coefficient precision is outside 5 to 15, the partition orders are
+    /// larger than 15 or out of order, the apodization windows cannot be parsed, the
+    /// number of threads or frames in flight is zero, the seek point spacing is zero,
+    /// there is more than one picture of type 1 or more than one of type 2, which
+    /// are the file icons, or the padding does not fit in a metadata block.
+    pub fn build(self) -> Result <EncoderConfig, FlacWriterError> {
+        let preset = self.preset.unwrap_or(DEFAULT_PRESET);
+        if preset > MAX_PRESET {
+            return Err(FlacWriterError::InvalidConfigError);
+        }
+
+        let mut config = EncoderConfig::from_preset(preset)
+            .ok_or(FlacWriterError::InvalidConfigError)?;
+        config.block_size = self.block_size.unwrap_or(config.block_size);
+        config.max_lpc_order = self.max_lpc_order.unwrap_or(config.max_lpc_order);
+        config.qlp_precision = self.qlp_precision.or(config.qlp_precision);
+        config.qlp_precision_search = self.qlp_precision_search.unwrap_or(config.qlp_precision_search);
+        config.min_partition_order = self.min_partition_order.unwrap_or(config.min_partition_order);
+        config.max_partition_order = self.max_partition_order.unwrap_or(config.max_partition_order);
+        config.exhaustive_model_search = self.exhaustive_model_search.unwrap_or(config.exhaustive_model_search);
+        config.stereo_mode = self.stereo_mode.unwrap_or(config.stereo_mode);
+        config.variable_block_size = self.variable_block_size.unwrap_or(config.variable_block_size);
+        config.reduce_bit_depth = self.reduce_bit_depth.unwrap_or(config.reduce_bit_depth);
+        config.threads = self.threads.unwrap_or(config.threads);
+        config.max_frames_in_flight = self.max_frames_in_flight.or(config.max_frames_in_flight);
+        config.verify = self.verify.unwrap_or(config.verify);
+        config.seektable = self.seektable.or(config.seektable);
+        config.vorbis_comment = self.vorbis_comment.or(config.vorbis_comment);
+        config.cuesheet = self.cuesheet.or(config.cuesheet);
+        config.pictures = self.pictures;
+        config.keep_foreign_metadata = self.keep_foreign_metadata.unwrap_or(config.keep_foreign_metadata);
+        config.padding = self.padding.unwrap_or(config.padding);
+        if let Some(spec) = &self.apodization {
+            config.apodization = ApodizationWindow::parse_spec(spec)
+                .ok_or(FlacWriterError::InvalidConfigError)?;
+        }
+
+        let num_pictures_of_type = |picture_type| config.pictures.iter()
+            .filter(|picture| picture.picture_type == picture_type)
+            .count();
+        let padding_len = match config.padding {
+            Padding::Bytes(len) | Padding::ExpectedTags(len) => len,
+            Padding::None => 0,
+        };
+        let is_valid_precision = match config.qlp_precision {
+            Some(precision) => (5..=15).contains(&precision),
+            None => true,
+        };
+        if config.block_size < 16
+            || config.max_lpc_order > 32
+            || !is_valid_precision
+            || config.max_partition_order > 15
+            || config.min_partition_order > config.max_partition_order
+            || config.threads == 0
+            || config.max_frames_in_flight == Some(0)
+            || config.seektable.as_ref().is_some_and(|spacing| !spacing.is_valid())
+            || num_pictures_of_type(PICTURE_TYPE_FILE_ICON) > 1
+            || num_pictures_of_type(PICTURE_TYPE_OTHER_FILE_ICON) > 1
+            || padding_len > MAX_BLOCK_LENGTH as u32 {
+            return Err(FlacWriterError::InvalidConfigError);
+        }
+
+        Ok(config)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    macro_rules! preset_tests {
+        ($($name:ident: $value:expr,)*) => {
+        $(
+            #[test]
+            fn $name() {
+                let (level, block_size, max_lpc_order, max_partition_order, stereo_mode) = $value;
+                let config = EncoderConfig::preset(level).unwrap();
+
+                assert_eq!(config.block_size(), block_size);
+                assert_eq!(config.max_lpc_order(), max_lpc_order);
+                assert_eq!(config.qlp_precision(), None);
+                assert_eq!(config.min_partition_order(), 0);
+                assert_eq!(config.max_partition_order(), max_partition_order);
+                assert!(!config.exhaustive_model_search());
+                assert_eq!(config.stereo_mode(), stereo_mode);
+            }
+        )*
+        }
+    }
+
+    #[test]
+    fn preset_apodization() {
+        assert_eq!(EncoderConfig::preset(5).unwrap().apodization().len(), 1);
+        assert_eq!(EncoderConfig::preset(6).unwrap().apodization().len(), 3);
+        assert_eq!(EncoderConfig::preset(8).unwrap().apodization().len(), 6);
+    }
+
+    preset_tests! {
+        preset_0: (0, 1152, 0, 3, StereoMode::Independent),
+        preset_1: (1, 1152, 0, 3, StereoMode::AdaptiveMidSide),
+        preset_3: (3, 4096, 6, 4, StereoMode::Independent),
+        preset_5: (5, 4096, 8, 5, StereoMode::MidSide),
+        preset_8: (8, 4096, 12, 6, StereoMode::MidSide),
+    }
+
+    #[test]
+    fn default_is_preset_5() {
+        assert_eq!(EncoderConfig::default(), EncoderConfig::preset(5).unwrap());
+        assert_eq!(EncoderConfig::builder().build().unwrap(), EncoderConfig::preset(5).unwrap());
+    }
+
+    #[test]
+    fn overrides_apply_on_top_of_preset() {
+        let config = EncoderConfig::builder()
+            .block_size(1024)
+            .qlp_precision(12)
+            .exhaustive_model_search(true)
+            .reduce_bit_depth(true)
+            .preset(0)
+            .build()
+            .unwrap();
+
+        assert_eq!(config.block_size(), 1024);
+        assert_eq!(config.qlp_precision(), Some(12));
+        assert!(config.exhaustive_model_search());
+        assert!(config.reduce_bit_depth());
+        assert_eq!(config.max_lpc_order(), 0);
+        assert_eq!(config.stereo_mode(), StereoMode::Independent);
+    }
+
+    fn file_icon() -> FlacPicture {
+        FlacPicture {
+            picture_type: PICTURE_TYPE_FILE_ICON,
+            mime_type: "image/png".to_string(),
+            description: String::new(),
+            width: 32,
+            height: 32,
+            depth: 32,
+            num_colors: 0,
+            data: Vec::new(),
+        }
+    }
+
+    #[test]
+    fn default_padding() {
+        assert_eq!(EncoderConfig::default().padding(), Padding::Bytes(8192));
+        assert_eq!(EncoderConfig::builder().padding(Padding::None).build().unwrap().padding(), Padding::None);
+    }
+
+    #[test]
+    fn pictures_keep_order() {
+        let mut other_icon = file_icon();
+        other_icon.picture_type = PICTURE_TYPE_OTHER_FILE_ICON;
+        let config = EncoderConfig::builder()
+            .picture(file_icon())
+            .picture(other_icon.clone())
+            .build()
+            .unwrap();
+
+        assert_eq!(config.pictures(), &vec![file_icon(), other_icon]);
+    }
+
+    macro_rules! invalid_config_tests {
+        ($($name:ident: $value:expr,)*) => {
+        $(
+            #[test]
+            fn $name() {
+                let builder: EncoderConfigBuilder = $value;
+                assert!(matches!(builder.build(), Err(FlacWriterError::InvalidConfigError)));
+            }
+        )*
+        }
+    }
+
+    invalid_config_tests! {
+        invalid_preset: EncoderConfig::builder().preset(9),
+        invalid_block_size: EncoderConfig::builder().block_size(15),
+        invalid_max_lpc_order: EncoderConfig::builder().max_lpc_order(33),
+        invalid_qlp_precision: EncoderConfig::builder().qlp_precision(16),
+        invalid_partition_orders: EncoderConfig::builder().min_partition_order(4).max_partition_order(3),
+        invalid_apodization: EncoderConfig::builder().apodization("tukey(2)"),
+        invalid_threads: EncoderConfig::builder().threads(0),
+        invalid_max_frames_in_flight: EncoderConfig::builder().threads(4).max_frames_in_flight(0),
+        invalid_seektable_seconds: EncoderConfig::builder().seektable(SeekPointSpacing::Seconds(0)),
+        invalid_seektable_samples: EncoderConfig::builder().seektable(SeekPointSpacing::Samples(0)),
+        invalid_file_icons: EncoderConfig::builder().picture(file_icon()).picture(file_icon()),
+        invalid_padding: EncoderConfig::builder().padding(Padding::Bytes(1 << 24)),
+    }
+
+    #[test]
+    fn max_frames_in_flight_follows_threads() {
+        assert_eq!(EncoderConfig::default().threads(), 1);
+        assert_eq!(EncoderConfig::builder().threads(4).build().unwrap().max_frames_in_flight(), 8);
+        assert_eq!(EncoderConfig::builder().threads(4).max_frames_in_flight(5).build().unwrap().max_frames_in_flight(), 5);
+    }
+}
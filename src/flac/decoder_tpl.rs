@@ -0,0 +1,742 @@
+use core::fmt;
+use std::error;
+use std::fs::{self, File};
+use std::io::{self, BufWriter, Read, Write};
+use std::path::Path;
+
+use crate::flac::FlacStreamInfo;
+use crate::flac::bitstream::BitstreamReader;
+use crate::flac::encoder::crc::CrcOptions;
+use crate::flac::metadata::{APPLICATION_ID_RIFF, BLOCK_TYPE_APPLICATION, BLOCK_TYPE_PICTURE};
+use crate::flac::metadata::picture::FlacPicture;
+use crate::flac::ogg;
+
+/// Represents a decoded FLAC file
+pub struct FlacStream {
+    pub streaminfo: FlacStreamInfo,
+    pub metadata_blocks: Vec <FlacMetadataBlock>,
+    pub samples: Vec <Vec <i64>>,
+}
+
+/// Represents a metadata block other than STREAMINFO
+///
+/// The contents of the block are kept as-is without the 4-byte
+/// metadata block header.
+pub struct FlacMetadataBlock {
+    pub block_type: u8,
+    pub data: Vec <u8>,
+}
+
+/// Represents a decoded audio frame
+///
+/// The samples are stored per channel, i.e. `samples[ch][i]` is the `i`-th
+/// sample of channel `ch`. The `frame_index` is the frame number for fixed
+/// block size streams and the index of the first sample otherwise.
+pub struct FlacDecodedFrame {
+    pub is_variable_blocksize: bool,
+    pub frame_index: u64,
+    pub samples: Vec <Vec <i64>>,
+    pub frame_size: usize,
+}
+
+/// Represents a FLAC reader
+///
+/// The reader is mostly used to check that the output of `FlacWriter`
+/// decodes back to the original samples, including by its verify mode.
+pub struct FlacReader;
+
+/// Represents an error in the FLAC reader
+#[derive(Debug)]
+pub enum FlacReaderError {
+    NotFlacError,
+    MetadataError,
+    FrameSyncError,
+    FrameHeaderError,
+    SubframeError,
+    CrcError,
+    OggError,
+    ReadError,
+    WriteError,
+}
+
+impl FlacReader {
+    /// Open a FLAC file and decode all of its frames
+    ///
+    /// Ogg FLAC files, which start with an Ogg page instead of `fLaC`, are
+    /// demultiplexed first.
+    ///
+    /// # Errors
+    /// Returns a `FlacReaderError` with the appropriate error if something
+    /// happens.
+    pub fn open(file_path: &str) -> Result <FlacStream, FlacReaderError> {
+        let mut data = Vec::new();
+        File::open(file_path)?.read_to_end(&mut data)?;
+        if data.starts_with(b"OggS") {
+            Self::decode_ogg_bytes(&data)
+        } else {
+            Self::decode_bytes(&data)
+        }
+    }
+
+    /// Decode a whole Ogg FLAC stream held in memory
+    ///
+    /// # Errors
+    /// Returns the error returned by `demux_ogg` or `decode_bytes`.
+    pub fn decode_ogg_bytes(data: &[u8]) -> Result <FlacStream, FlacReaderError> {
+        Self::decode_bytes(&Self::demux_ogg(data)?)
+    }
+
+    /// Demultiplex the first logical stream of an Ogg FLAC file into a native FLAC stream
+    ///
+    /// The STREAMINFO block is taken from the mapping header packet, and the header
+    /// packets after it are the other metadata blocks. If the mapping header leaves their
+    /// number unknown, they are the packets up to the first frame, which starts with the
+    /// byte 0xFF unlike a metadata block header. Only the last of the metadata blocks is
+    /// marked as such. Each remaining packet is a frame.
+    ///
+    /// # Errors
+    /// Returns `FlacReaderError::OggError` if the Ogg pages are malformed,
+    /// `FlacReaderError::NotFlacError` if the first packet is not a FLAC mapping
+    /// header packet, and `FlacReaderError::MetadataError` if a header packet is missing.
+    pub fn demux_ogg(data: &[u8]) -> Result <Vec <u8>, FlacReaderError> {
+        let packets = ogg::read_packets(data).ok_or(FlacReaderError::OggError)?;
+        let (num_header_packets, streaminfo_block) = packets.first()
+            .and_then(|packet| ogg::read_mapping_packet(&packet.data))
+            .ok_or(FlacReaderError::NotFlacError)?;
+
+        let num_header_packets = match num_header_packets {
+            0 => packets[1..].iter().take_while(|packet| packet.data.first() != Some(&0xff)).count(),
+            num_header_packets => num_header_packets as usize,
+        };
+        let header_packets = packets.get(1..1 + num_header_packets).ok_or(FlacReaderError::MetadataError)?;
+
+        let mut stream = b"fLaC".to_vec();
+        let blocks = std::iter::once(streaminfo_block)
+            .chain(header_packets.iter().map(|packet| packet.data.as_slice()));
+        for (i, block) in blocks.enumerate() {
+            if block.is_empty() {
+                return Err(FlacReaderError::MetadataError);
+            }
+            let is_last = i == num_header_packets;
+            stream.push((block[0] & 0x7f) | ((is_last as u8) << 7));
+            stream.extend_from_slice(&block[1..]);
+        }
+        for packet in packets[1 + num_header_packets..].iter() {
+            stream.extend_from_slice(&packet.data);
+        }
+
+        Ok(stream)
+    }
+
+    /// Decode a whole FLAC stream held in memory
+    ///
+    /// The samples of all frames are collated into inter-channel samples, similar
+    /// to those returned by the WAV data chunk iterators.
+    ///
+    /// # Errors
+    /// Returns a `FlacReaderError` with the appropriate error if something
+    /// happens. This includes format errors and CRC mismatches.
+    pub fn decode_bytes(data: &[u8]) -> Result <FlacStream, FlacReaderError> {
+        let (streaminfo, metadata_blocks, mut pos) = Self::read_metadata(data)?;
+
+        let mut samples = Vec::new();
+        while pos < data.len() {
+            let frame = Self::decode_frame(&data[pos..], &streaminfo)?;
+            for i in 0..frame.samples[0].len() {
+                samples.push(frame.samples.iter().map(|channel| channel[i]).collect());
+            }
+            pos += frame.frame_size;
+        }
+
+        Ok(FlacStream {
+            streaminfo,
+            metadata_blocks,
+            samples,
+        })
+    }
+
+    /// Read the `fLaC` marker and all metadata blocks of a FLAC stream
+    ///
+    /// This method returns the STREAMINFO block, the other metadata blocks and
+    /// the position of the first audio frame, respectively.
+    ///
+    /// # Errors
+    /// Returns a `FlacReaderError` if the stream does not start with `fLaC` or
+    /// the metadata blocks are truncated.
+    pub fn read_metadata(data: &[u8]) -> Result <(FlacStreamInfo, Vec <FlacMetadataBlock>, usize), FlacReaderError> {
+        if data.len() < 4 || &data[..4] != b"fLaC" {
+            return Err(FlacReaderError::NotFlacError);
+        }
+
+        let mut pos = 4;
+        let mut streaminfo = None;
+        let mut metadata_blocks = Vec::new();
+        loop {
+            let header = data.get(pos..pos + 4).ok_or(FlacReaderError::MetadataError)?;
+            let is_last = header[0] & 0x80 != 0;
+            let block_type = header[0] & 0x7f;
+            let length = u32::from_be_bytes([0, header[1], header[2], header[3]]) as usize;
+            let contents = data.get(pos + 4..pos + 4 + length).ok_or(FlacReaderError::MetadataError)?;
+
+            if block_type == 0 {
+                streaminfo = Some(Self::read_streaminfo(contents)?);
+            }
+            else {
+                metadata_blocks.push(FlacMetadataBlock {
+                    block_type,
+                    data: contents.to_vec(),
+                });
+            }
+
+            pos += 4 + length;
+            if is_last {
+                break;
+            }
+        }
+
+        let streaminfo = streaminfo.ok_or(FlacReaderError::MetadataError)?;
+        Ok((streaminfo, metadata_blocks, pos))
+    }
+
+    /// Read the pictures of the PICTURE blocks of a FLAC stream, in order
+    ///
+    /// # Errors
+    /// Returns `FlacReaderError::MetadataError` if a PICTURE block is malformed,
+    /// or the error returned by `read_metadata`.
+    pub fn read_pictures(data: &[u8]) -> Result <Vec <FlacPicture>, FlacReaderError> {
+        let (_, metadata_blocks, _) = Self::read_metadata(data)?;
+        metadata_blocks.iter()
+            .filter(|block| block.block_type == BLOCK_TYPE_PICTURE)
+            .map(|block| FlacPicture::from_bytes(&block.data).ok_or(FlacReaderError::MetadataError))
+            .collect()
+    }
+
+    /// Write the pictures of a FLAC file into files in the directory `out_dir`
+    ///
+    /// The `n`-th picture of type `t` is written to `picture_<n>_type_<t>.<ext>`, where
+    /// `n` counts from 1 and the extension follows the MIME type of the picture. Only
+    /// the metadata blocks of the file are read. This method returns the paths of the
+    /// written files in order.
+    ///
+    /// # Errors
+    /// Returns `FlacReaderError::ReadError` if the file cannot be read,
+    /// `FlacReaderError::WriteError` if a picture cannot be written, or the error
+    /// returned by `read_pictures`.
+    pub fn extract_pictures(file_path: &str, out_dir: &str) -> Result <Vec <String>, FlacReaderError> {
+        let data = Self::read_metadata_bytes(file_path)?;
+        let pictures = Self::read_pictures(&data)?;
+
+        let mut paths = Vec::with_capacity(pictures.len());
+        for (i, picture) in pictures.iter().enumerate() {
+            let file_name = format!("picture_{}_type_{}.{}", i + 1, picture.picture_type, picture.extension());
+            let path = Path::new(out_dir).join(file_name);
+            fs::write(&path, &picture.data).map_err(|_| FlacReaderError::WriteError)?;
+            paths.push(path.to_string_lossy().into_owned());
+        }
+
+        Ok(paths)
+    }
+
+    /// Decode a FLAC file into a PCM WAV file
+    ///
+    /// See `write_wave` for the layout of the WAV file.
+    ///
+    /// # Errors
+    /// Returns the error returned by `open` or `write_wave`, or
+    /// `FlacReaderError::WriteError` if the WAV file cannot be created.
+    pub fn decode_to_wave(file_path: &str, wav_path: &str, keep_foreign_metadata: bool) -> Result <(), FlacReaderError> {
+        let stream = Self::open(file_path)?;
+        let fh = File::create(wav_path).map_err(|_| FlacReaderError::WriteError)?;
+        let mut out = BufWriter::new(fh);
+        Self::write_wave(&stream, &mut out, keep_foreign_metadata)?;
+        out.flush().map_err(|_| FlacReaderError::WriteError)
+    }
+
+    /// Write a decoded FLAC stream as a PCM WAV file
+    ///
+    /// If `keep_foreign_metadata` is set, the file is rebuilt from the `riff` APPLICATION
+    /// blocks written with `EncoderConfig::keep_foreign_metadata`. The chunks are written
+    /// in order, and each data chunk header is followed by as many samples as its size
+    /// holds, which restores the original file byte for byte. Otherwise, the samples
+    /// follow a canonical 44-byte header, with the bit depth rounded up to a whole byte.
+    ///
+    /// # Errors
+    /// Returns `FlacReaderError::MetadataError` if `keep_foreign_metadata` is set but the
+    /// `riff` blocks are missing, do not start with a RIFF header, have no format chunk
+    /// matching the stream, or have data chunks too small for all samples. Returns
+    /// `FlacReaderError::WriteError` if writing fails.
+    pub fn write_wave(stream: &FlacStream, out: &mut impl Write, keep_foreign_metadata: bool) -> Result <(), FlacReaderError> {
+        let streaminfo = &stream.streaminfo;
+        if !keep_foreign_metadata {
+            let bps = (streaminfo.bit_depth as usize).div_ceil(8) * 8;
+            let block_align = streaminfo.num_channels as usize * bps / 8;
+            let data_len = stream.samples.len() * block_align;
+
+            let mut bytes = Vec::with_capacity(44 + data_len + 1);
+            bytes.extend_from_slice(b"RIFF");
+            bytes.extend_from_slice(&((36 + data_len + (data_len & 1)) as u32).to_le_bytes());
+            bytes.extend_from_slice(b"WAVEfmt \x10\0\0\0\x01\0");
+            bytes.extend_from_slice(&(streaminfo.num_channels as u16).to_le_bytes());
+            bytes.extend_from_slice(&streaminfo.sample_rate.to_le_bytes());
+            bytes.extend_from_slice(&(streaminfo.sample_rate * block_align as u32).to_le_bytes());
+            bytes.extend_from_slice(&(block_align as u16).to_le_bytes());
+            bytes.extend_from_slice(&(bps as u16).to_le_bytes());
+            bytes.extend_from_slice(b"data");
+            bytes.extend_from_slice(&(data_len as u32).to_le_bytes());
+            Self::encode_wave_samples(stream.samples.iter(), bps, streaminfo.bit_depth, &mut bytes);
+            if data_len % 2 == 1 {
+                bytes.push(0);
+            }
+            return out.write_all(&bytes).map_err(|_| FlacReaderError::WriteError);
+        }
+
+        let chunks: Vec <&[u8]> = stream.metadata_blocks.iter()
+            .filter(|block| block.block_type == BLOCK_TYPE_APPLICATION && block.data.starts_with(APPLICATION_ID_RIFF))
+            .map(|block| &block.data[4..])
+            .collect();
+        let is_riff = chunks.first().is_some_and(|chunk| chunk.len() == 12 && chunk.starts_with(b"RIFF") && chunk.ends_with(b"WAVE"));
+        let (num_channels, bps) = chunks.iter()
+            .find(|chunk| chunk.len() >= 24 && chunk.starts_with(b"fmt "))
+            .map(|chunk| (u16::from_le_bytes([chunk[10], chunk[11]]), u16::from_le_bytes([chunk[22], chunk[23]]) as usize))
+            .ok_or(FlacReaderError::MetadataError)?;
+        if !is_riff || num_channels != streaminfo.num_channels as u16 || bps < streaminfo.bit_depth as usize {
+            return Err(FlacReaderError::MetadataError);
+        }
+
+        let block_align = num_channels as usize * bps.div_ceil(8);
+        let mut samples = stream.samples.iter();
+        for chunk in chunks {
+            let mut bytes = chunk.to_vec();
+            if chunk.len() == 8 && chunk.starts_with(b"data") {
+                let size = u32::from_le_bytes(chunk[4..].try_into().unwrap()) as usize;
+                let num_samples = Self::encode_wave_samples(samples.by_ref().take(size / block_align), bps, streaminfo.bit_depth, &mut bytes);
+                // A data chunk cut short by the end of the file has no padding byte
+                if size % 2 == 1 && num_samples * block_align == size {
+                    bytes.push(0);
+                }
+            }
+            out.write_all(&bytes).map_err(|_| FlacReaderError::WriteError)?;
+        }
+
+        match samples.next() {
+            Some(_) => Err(FlacReaderError::MetadataError),
+            None => Ok(()),
+        }
+    }
+
+    /// Append inter-channel samples to `bytes` as WAV samples of `bps` bits
+    ///
+    /// Samples of `bit_depth` bits are shifted to the left to fill `bps` bits, and 8-bit
+    /// samples are unsigned with 128 as the midpoint. This method returns the number of
+    /// inter-channel samples written.
+    fn encode_wave_samples <'a> (samples: impl Iterator <Item = &'a Vec <i64>>, bps: usize, bit_depth: u8, bytes: &mut Vec <u8>) -> usize {
+        let shift = bps - bit_depth as usize;
+        let bytes_per_sample = bps.div_ceil(8);
+        let mut num_samples = 0;
+        for sample in samples {
+            for value in sample.iter() {
+                let value = value << shift;
+                if bps == 8 {
+                    bytes.push((value + 128) as u8);
+                } else {
+                    bytes.extend_from_slice(&value.to_le_bytes()[..bytes_per_sample]);
+                }
+            }
+            num_samples += 1;
+        }
+        num_samples
+    }
+
+    /// Read the `fLaC` marker and the metadata blocks of a FLAC file, but no frame
+    fn read_metadata_bytes(file_path: &str) -> Result <Vec <u8>, FlacReaderError> {
+        let mut file = File::open(file_path)?;
+        let mut data = vec![0; 4];
+        file.read_exact(&mut data)?;
+        if data != b"fLaC" {
+            return Err(FlacReaderError::NotFlacError);
+        }
+        loop {
+            let mut header = [0; 4];
+            file.read_exact(&mut header)?;
+            let length = u32::from_be_bytes([0, header[1], header[2], header[3]]) as usize;
+            data.extend_from_slice(&header);
+
+            let pos = data.len();
+            data.resize(pos + length, 0);
+            file.read_exact(&mut data[pos..])?;
+            if header[0] & 0x80 != 0 {
+                return Ok(data);
+            }
+        }
+    }
+
+    /// Read the contents of a STREAMINFO block
+    fn read_streaminfo(contents: &[u8]) -> Result <FlacStreamInfo, FlacReaderError> {
+        if contents.len() < 34 {
+            return Err(FlacReaderError::MetadataError);
+        }
+
+        let mut reader = BitstreamReader::new(contents);
+        let mut read = |num_bits| reader.read_bits(num_bits).ok_or(FlacReaderError::MetadataError);
+        let min_block_size = read(16)? as u16;
+        let max_block_size = read(16)? as u16;
+        let min_frame_size = read(24)? as u32;
+        let max_frame_size = read(24)? as u32;
+        let sample_rate = read(20)? as u32;
+        let num_channels = read(3)? as u8 + 1;
+        let bit_depth = read(5)? as u8 + 1;
+        let total_samples = read(36)?;
+
+        let mut md5 = [0u8; 16];
+        md5.copy_from_slice(&contents[18..34]);
+
+        Ok(FlacStreamInfo {
+            min_block_size,
+            max_block_size,
+            min_frame_size,
+            max_frame_size,
+            sample_rate,
+            num_channels,
+            bit_depth,
+            total_samples,
+            md5,
+        })
+    }
+
+    /// Decode a single audio frame at the start of `data`
+    ///
+    /// Values that the frame header takes from STREAMINFO are read from `streaminfo`.
+    ///
+    /// # Errors
+    /// Returns a `FlacReaderError` with the appropriate error if something
+    /// happens. This includes a missing sync code and CRC mismatches.
+    pub fn decode_frame(data: &[u8], streaminfo: &FlacStreamInfo) -> Result <FlacDecodedFrame, FlacReaderError> {
+        let mut reader = BitstreamReader::new(data);
+
+        let sync = reader.read_bits(15).ok_or(FlacReaderError::FrameSyncError)?;
+        if sync != 0b111_1111_1111_1100 {
+            return Err(FlacReaderError::FrameSyncError);
+        }
+        let is_variable_blocksize = Self::read(&mut reader, 1)? == 1;
+        let block_size_type = Self::read(&mut reader, 4)?;
+        let sample_rate_type = Self::read(&mut reader, 4)?;
+        let channel_assignment = Self::read(&mut reader, 4)?;
+        let bit_depth_type = Self::read(&mut reader, 3)?;
+        Self::read(&mut reader, 1)?;
+        let frame_index = Self::read_utf8(&mut reader)?;
+
+        let block_size = match block_size_type {
+            0b0001 => 192,
+            0b0010..=0b0101 => 576 << (block_size_type - 2),
+            0b0110 => Self::read(&mut reader, 8)? + 1,
+            0b0111 => Self::read(&mut reader, 16)? + 1,
+            0b1000..=0b1111 => 256 << (block_size_type - 8),
+            _ => return Err(FlacReaderError::FrameHeaderError),
+        } as usize;
+
+        match sample_rate_type {
+            0b1100 => { Self::read(&mut reader, 8)?; },
+            0b1101 | 0b1110 => { Self::read(&mut reader, 16)?; },
+            0b1111 => return Err(FlacReaderError::FrameHeaderError),
+            _ => {},
+        }
+
+        let bit_depth = match bit_depth_type {
+            0b000 => streaminfo.bit_depth,
+            0b001 => 8,
+            0b010 => 12,
+            0b100 => 16,
+            0b101 => 20,
+            0b110 => 24,
+            0b111 => 32,
+            _ => return Err(FlacReaderError::FrameHeaderError),
+        };
+
+        let header_len = reader.byte_pos();
+        let crc8 = Self::read(&mut reader, 8)? as u8;
+        if CrcOptions::new(0b0000_0111u8, 8).build_crc8(&data[..header_len]) != crc8 {
+            return Err(FlacReaderError::CrcError);
+        }
+
+        // Channel assignments 0b1000 until 0b1010 are stereo with a side channel,
+        // which needs one extra bit of depth
+        let (num_channels, side_channel) = match channel_assignment {
+            0b0000..=0b0111 => (channel_assignment as usize + 1, None),
+            0b1000 | 0b1010 => (2, Some(1)),
+            0b1001 => (2, Some(0)),
+            _ => return Err(FlacReaderError::FrameHeaderError),
+        };
+
+        let mut samples = Vec::with_capacity(num_channels);
+        for ch in 0..num_channels {
+            let subframe_bit_depth = if side_channel == Some(ch) { bit_depth + 1 } else { bit_depth };
+            samples.push(Self::decode_subframe(&mut reader, subframe_bit_depth, block_size)?);
+        }
+
+        if let [first, second] = samples.as_mut_slice() {
+            match channel_assignment {
+                0b1000 => {
+                    // left/side
+                    for (left, side) in first.iter().zip(second.iter_mut()) {
+                        *side = *left - *side;
+                    }
+                },
+                0b1001 => {
+                    // side/right
+                    for (side, right) in first.iter_mut().zip(second.iter()) {
+                        *side += *right;
+                    }
+                },
+                0b1010 => {
+                    // mid/side
+                    for (mid, side) in first.iter_mut().zip(second.iter_mut()) {
+                        let full_mid = (*mid << 1) | (*side & 1);
+                        (*mid, *side) = ((full_mid + *side) >> 1, (full_mid - *side) >> 1);
+                    }
+                },
+                _ => {},
+            }
+        }
+
+        reader.align();
+        let frame_len = reader.byte_pos();
+        let crc16 = Self::read(&mut reader, 16)? as u16;
+        if CrcOptions::new(0b1000_0000_0000_0101u16, 16).build_crc16(&data[..frame_len]) != crc16 {
+            return Err(FlacReaderError::CrcError);
+        }
+
+        Ok(FlacDecodedFrame {
+            is_variable_blocksize,
+            frame_index,
+            samples,
+            frame_size: frame_len + 2,
+        })
+    }
+
+    /// Decode a single audio subframe
+    fn decode_subframe(reader: &mut BitstreamReader, bit_depth: u8, block_size: usize) -> Result <Vec <i64>, FlacReaderError> {
+        if Self::read(reader, 1)? != 0 {
+            return Err(FlacReaderError::SubframeError);
+        }
+        let subframe_type = Self::read(reader, 6)? as u8;
+
+        let wasted_bits = if Self::read(reader, 1)? == 1 {
+            reader.read_unary().ok_or(FlacReaderError::SubframeError)? as u8 + 1
+        } else {
+            0
+        };
+        if wasted_bits >= bit_depth {
+            return Err(FlacReaderError::SubframeError);
+        }
+        let bit_depth = bit_depth - wasted_bits;
+
+        let mut samples = match subframe_type {
+            0b000000 => {
+                let value = Self::read_signed(reader, bit_depth)?;
+                vec![value; block_size]
+            },
+            0b000001 => {
+                let mut samples = Vec::with_capacity(block_size);
+                for _ in 0..block_size {
+                    samples.push(Self::read_signed(reader, bit_depth)?);
+                }
+                samples
+            },
+            0b001000..=0b001100 => {
+                let order = (subframe_type & 0b111) as usize;
+                let mut samples = Self::read_warmup(reader, bit_depth, order, block_size)?;
+                let residuals = Self::read_residuals(reader, order, block_size)?;
+                for (i, residual) in (order..block_size).zip(residuals) {
+                    let prediction = match order {
+                        0 => 0,
+                        1 => samples[i - 1],
+                        2 => 2 * samples[i - 1] - samples[i - 2],
+                        3 => 3 * samples[i - 1] - 3 * samples[i - 2] + samples[i - 3],
+                        _ => 4 * samples[i - 1] - 6 * samples[i - 2] + 4 * samples[i - 3] - samples[i - 4],
+                    };
+                    samples.push(prediction + residual);
+                }
+                samples
+            },
+            0b100000..=0b111111 => {
+                let order = (subframe_type & 0b11111) as usize + 1;
+                let mut samples = Self::read_warmup(reader, bit_depth, order, block_size)?;
+                let precision = Self::read(reader, 4)? as u8 + 1;
+                let shift = Self::read_signed(reader, 5)?;
+                if precision == 16 || shift < 0 {
+                    return Err(FlacReaderError::SubframeError);
+                }
+                let mut qlp_coefs = Vec::with_capacity(order);
+                for _ in 0..order {
+                    qlp_coefs.push(Self::read_signed(reader, precision)?);
+                }
+                let residuals = Self::read_residuals(reader, order, block_size)?;
+                for (i, residual) in (order..block_size).zip(residuals) {
+                    let mut prediction: i64 = 0;
+                    for j in 0..order {
+                        prediction += qlp_coefs[j] * samples[i - j - 1];
+                    }
+                    samples.push((prediction >> shift) + residual);
+                }
+                samples
+            },
+            _ => return Err(FlacReaderError::SubframeError),
+        };
+
+        if wasted_bits > 0 {
+            for sample in samples.iter_mut() {
+                *sample <<= wasted_bits;
+            }
+        }
+
+        Ok(samples)
+    }
+
+    /// Read the unencoded warm-up samples of a FIXED or LPC audio subframe
+    fn read_warmup(reader: &mut BitstreamReader, bit_depth: u8, order: usize, block_size: usize) -> Result <Vec <i64>, FlacReaderError> {
+        if order > block_size {
+            return Err(FlacReaderError::SubframeError);
+        }
+        let mut samples = Vec::with_capacity(block_size);
+        for _ in 0..order {
+            samples.push(Self::read_signed(reader, bit_depth)?);
+        }
+        Ok(samples)
+    }
+
+    /// Read the Rice-encoded residual section of a FIXED or LPC audio subframe
+    ///
+    /// Both the 4-bit and 5-bit Rice parameter coding methods are supported, including
+    /// escaped partitions whose residuals are stored as fixed-width numbers.
+    fn read_residuals(reader: &mut BitstreamReader, order: usize, block_size: usize) -> Result <Vec <i64>, FlacReaderError> {
+        let (param_len, escape_param) = match Self::read(reader, 2)? {
+            0b00 => (4, 0b1111),
+            0b01 => (5, 0b11111),
+            _ => return Err(FlacReaderError::SubframeError),
+        };
+        let partition_order = Self::read(reader, 4)?;
+        let partition_len = block_size >> partition_order;
+        if partition_len << partition_order != block_size || partition_len < order {
+            return Err(FlacReaderError::SubframeError);
+        }
+
+        let mut residuals = Vec::with_capacity(block_size - order);
+        for partition in 0..(1usize << partition_order) {
+            let len = if partition == 0 { partition_len - order } else { partition_len };
+            let param = Self::read(reader, param_len)? as u8;
+
+            if param == escape_param {
+                let raw_len = Self::read(reader, 5)? as u8;
+                for _ in 0..len {
+                    residuals.push(Self::read_signed(reader, raw_len)?);
+                }
+            }
+            else {
+                for _ in 0..len {
+                    let quotient = reader.read_unary().ok_or(FlacReaderError::SubframeError)?;
+                    let remainder = Self::read(reader, param)?;
+                    let folded = (quotient << param) | remainder;
+                    residuals.push((folded >> 1) as i64 ^ -((folded & 1) as i64));
+                }
+            }
+        }
+
+        Ok(residuals)
+    }
+
+    /// Read a UTF-8 coded number from a frame header
+    fn read_utf8(reader: &mut BitstreamReader) -> Result <u64, FlacReaderError> {
+        let first = Self::read(reader, 8)?;
+        let num_bytes = (first as u8).leading_ones();
+        if num_bytes == 0 {
+            return Ok(first);
+        }
+        if num_bytes == 1 || num_bytes > 7 {
+            return Err(FlacReaderError::FrameHeaderError);
+        }
+
+        let mut num = first & (0x7f >> num_bytes);
+        for _ in 1..num_bytes {
+            let next = Self::read(reader, 8)?;
+            if next & 0xc0 != 0x80 {
+                return Err(FlacReaderError::FrameHeaderError);
+            }
+            num = (num << 6) | (next & 0x3f);
+        }
+        Ok(num)
+    }
+
+    fn read(reader: &mut BitstreamReader, num_bits: u8) -> Result <u64, FlacReaderError> {
+        reader.read_bits(num_bits).ok_or(FlacReaderError::SubframeError)
+    }
+
+    fn read_signed(reader: &mut BitstreamReader, num_bits: u8) -> Result <i64, FlacReaderError> {
+        reader.read_signed(num_bits).ok_or(FlacReaderError::SubframeError)
+    }
+}
+
+impl error::Error for FlacReaderError {}
+
+impl fmt::Display for FlacReaderError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            FlacReaderError::NotFlacError => write!(f, "Not a FLAC file"),
+            FlacReaderError::MetadataError => write!(f, "Malformed metadata block"),
+            FlacReaderError::FrameSyncError => write!(f, "Frame sync code not found"),
+            FlacReaderError::FrameHeaderError => write!(f, "Malformed frame header"),
+            FlacReaderError::SubframeError => write!(f, "Malformed subframe"),
+            FlacReaderError::CrcError => write!(f, "CRC mismatch"),
+            FlacReaderError::OggError => write!(f, "Malformed Ogg stream"),
+            FlacReaderError::ReadError => write!(f, "Error reading file"),
+            FlacReaderError::WriteError => write!(f, "Error writing file"),
+        }
+    }
+}
+
+impl From <io::Error> for FlacReaderError {
+    fn from(_: io::Error) -> Self {
+        FlacReaderError::ReadError
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn decode_ietf_01() -> Result <(), FlacReaderError> {
+        let stream = FlacReader::open("sample_audio/ietf/ietf_01.flac")?;
+
+        assert_eq!(stream.streaminfo.sample_rate, 44100);
+        assert_eq!(stream.streaminfo.num_channels, 2);
+        assert_eq!(stream.streaminfo.bit_depth, 16);
+        assert_eq!(stream.samples, vec![vec![0x63f4, 0x28b0]]);
+
+        Ok(())
+    }
+
+    #[test]
+    fn decode_reference_var() -> Result <(), FlacReaderError> {
+        let stream = FlacReader::open("sample_audio/m_3s/music_3s_flac_var.flac")?;
+
+        assert_eq!(stream.streaminfo.total_samples, stream.samples.len() as u64);
+        assert_eq!(stream.samples[0], vec![-1934, -1934]);
+
+        Ok(())
+    }
+
+    #[test]
+    fn canonical_wave() -> Result <(), FlacReaderError> {
+        let stream = FlacReader::open("sample_audio/ietf/ietf_01.flac")?;
+        let mut out = Vec::new();
+        FlacReader::write_wave(&stream, &mut out, false)?;
+
+        // The WAV file has a single sample but a data chunk size of 64 bytes
+        let mut expected = std::fs::read("sample_audio/ietf/ietf_01.wav")?;
+        expected[40] = 4;
+        assert_eq!(out, expected);
+        assert!(matches!(FlacReader::write_wave(&stream, &mut Vec::new(), true), Err(FlacReaderError::MetadataError)));
+
+        Ok(())
+    }
+}
@@ -17,8 +17,8 @@ impl <T> CrcOptions <T> {
     /// Create a builder to the CRC encoder
     pub fn new(poly: T, poly_len: T) -> Self {
         CrcOptions {
-            poly: poly,
-            poly_len: poly_len,
+            poly,
+            poly_len,
         }
     }
 }
@@ -27,55 +27,25 @@ impl CrcOptions <u8> {
     /// Encode data using CRC8 encoding
     /// 
     /// This method is available only if `CrcOptions` is of type `u8`.
-    pub fn build_crc8(&self, data: &Vec <u8>) -> u8 {
-        //create divisor for XOR ops type int for easie operations
-        let divisor :i64 = ((0b1 << self.poly_len) + self.poly as i64);
-
-        //combine all data in VEC as single number
-        let mut comb_vec : i128= 0;
-        
-        for i in 0..data.len(){
-            if i == 0{
-                comb_vec = data[i] as i128;
-            }
-            else{
-                comb_vec = comb_vec + (data[i] as i128);
-            }
-            comb_vec = comb_vec << self.poly_len;
-        }
-        //count amount of bits then shift the data by poly.len number of zeroes 
-        let mut counter :u32 = comb_vec.ilog2() as u32;
-        comb_vec = comb_vec << self.poly_len;
-        //make a dividend base on comb_vec with poly_len number of bits
-        let mut dividend :i64 = (comb_vec >> (counter)) as i64;
-
-        //Divide or XOR continously 
-        while counter != 0 {
-            if dividend.ilog2() >= (self.poly_len as u32){
-                //if bits of dividend is equal to bits of divisor proceed with XOR
-                dividend = divisor ^ dividend;
-            }
-            else{
-                //else append a bit from temp to the divisor 
-                dividend = dividend << 1;//shift 1 to append 1 trailling bit 
-                let append :i64 = ((comb_vec  & (0b1 <<(counter - 1))) >> counter -1) as i64; //get the nearest trailing bit by getting the counter-1 bit
-                //combine 
-                dividend = dividend + append;
-                //take count of the added bit 
-                counter = counter - 1;
+    pub fn build_crc8(&self, data: &[u8]) -> u8 {
+        let mut crc: u8 = 0;
+
+        for byte in data.iter() {
+            //feed the next byte into the top of the register
+            crc ^= *byte;
+
+            //long division by the generator polynomial, one bit at a time
+            for _ in 0..8 {
+                if crc & 0x80 != 0 {
+                    crc = (crc << 1) ^ self.poly;
+                }
+                else {
+                    crc <<= 1;
+                }
             }
         }
-        // at last append possibility that divident and divisor bits are still equal 
-
-        if dividend.ilog2() >= (self.poly_len as u32){
-            //if bits of dividend is equal to bits of divisor proceed with XOR
-            dividend = divisor ^ dividend;
-            return (dividend as u8)
-        }
-        else{
-            return (dividend as u8)
-        }
 
+        crc
     }
 }
 
@@ -83,56 +53,55 @@ impl CrcOptions <u16> {
     /// Encode data using CRC16 encoding
     /// 
     /// This method is available only if `CrcOptions` is of type `u16`.
-    pub fn build_crc16(&self, data: &Vec <u16>) -> u16 {
-        let divisor :i64 = ((0b1 << self.poly_len) + self.poly as i64);
-
-        //combine all data in VEC as single number
-        let mut comb_vec : i128= 0;
-        
-        for i in 0..data.len(){
-            if i == 0{
-                comb_vec = data[i] as i128;
-            }
-            else{
-                comb_vec = comb_vec + (data[i] as i128);
-            }
-            comb_vec = comb_vec << self.poly_len;
-        }
-        //count amount of bits then shift the data by poly.len number of zeroes 
-        let mut counter :u32 = comb_vec.ilog2() as u32;
-        comb_vec = comb_vec << self.poly_len;
-        //make a dividend base on comb_vec with poly_len number of bits
-        let mut dividend :i64 = (comb_vec >> (counter )) as i64;
-
-        //Divide or XOR continously 
-        while counter != 0 {
-            if dividend.ilog2() >= (self.poly_len as u32){
-                //if bits of dividend is equal to bits of divisor proceed with XOR
-                dividend = divisor ^ dividend;
-            }
-            else{
-                //else append a bit from temp to the divisor 
-                dividend = dividend << 1;//shift 1 to append 1 trailling bit 
-                let append :i64 = ((comb_vec  & (0b1 <<counter - 1)) >> counter -1) as i64; //get the nearest trailing bit by getting the counter-1 bit
-                //combine 
-                dividend = dividend + append;
-                //take count of the added bit 
-                counter = counter - 1;
+    pub fn build_crc16(&self, data: &[u8]) -> u16 {
+        let mut crc: u16 = 0;
+        let top_bit = 1u16 << (self.poly_len - 1);
+
+        for byte in data.iter() {
+            //feed the next byte into the top of the register
+            crc ^= (*byte as u16) << (self.poly_len - 8);
+
+            //long division by the generator polynomial, one bit at a time
+            for _ in 0..8 {
+                if crc & top_bit != 0 {
+                    crc = (crc << 1) ^ self.poly;
+                }
+                else {
+                    crc <<= 1;
+                }
             }
         }
-        // at last append possibility that divident and divisor bits are still equal 
 
-        if dividend.ilog2() >= (self.poly_len as u32){
-            //if bits of dividend is equal to bits of divisor proceed with XOR
-            dividend = divisor ^ dividend;
-            return (dividend as u16)
-        }
-        else{
-            return (dividend as u16)
+        crc
+    }
+}
+
+impl CrcOptions <u32> {
+    /// Encode data using CRC32 encoding
+    /// 
+    /// This method is available only if `CrcOptions` is of type `u32`. The
+    /// register starts at zero and the result is not inverted, as used by Ogg.
+    pub fn build_crc32(&self, data: &[u8]) -> u32 {
+        let mut crc: u32 = 0;
+        let top_bit = 1u32 << (self.poly_len - 1);
+
+        for byte in data.iter() {
+            //feed the next byte into the top of the register
+            crc ^= (*byte as u32) << (self.poly_len - 8);
+
+            //long division by the generator polynomial, one bit at a time
+            for _ in 0..8 {
+                if crc & top_bit != 0 {
+                    crc = (crc << 1) ^ self.poly;
+                }
+                else {
+                    crc <<= 1;
+                }
+            }
         }
 
+        crc
     }
-    
 }
 
 #[cfg(test)]
@@ -186,4 +155,12 @@ mod tests {
 
         assert_eq!(ans, 0xaa9a);
     }
+
+    #[test]
+    fn sample_crc32_check() {
+        let ans = CrcOptions::new(0x04c1_1db7u32, 32)
+            .build_crc32(b"123456789");
+
+        assert_eq!(ans, 0x89a1_897f);
+    }
 }    
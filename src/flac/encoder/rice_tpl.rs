@@ -1,39 +1,138 @@
-use std::ops::{Shl, Shr};
-//use crate::flac::bitstream;
+use crate::flac::bitstream::BitstreamWriter;
 
 /// Represents a Rice encoder
 ///
 /// This encoder is expected to encode `num_samples` residuals from a predictor of
 /// order `predictor_order`. Note that Rice encoding in FLAC is only available
-/// for LPC and FIXED audio subframes.
+/// for LPC and FIXED audio subframes. Only partition orders from `min_partition_order`
+/// to `max_partition_order` inclusive are tried. If `is_exact` is set, the partition
+/// orders and parameters are compared by their exact sizes instead of estimates.
 pub struct RiceEncoderOptions {
     num_samples: u64,
     predictor_order: u8,
+    min_partition_order: u8,
+    max_partition_order: u8,
+    is_exact: bool,
 }
 
 /// Represents a Rice-encoded stream
 ///
 /// Rice encoding is _not necessarily_ byte-aligned. The `extra_bits_len`
 /// value denotes the number of LSBits in the last byte of the `stream`
-/// that are _not_ part of the encoding.
+/// that are _not_ part of the encoding. If `escape_bits` is set, the
+/// partition is escaped and its residuals are stored in the `stream` as
+/// `escape_bits`-bit two's complement numbers instead, ignoring `param`.
 #[derive(Debug)]
 pub struct RiceEncodedStream {
     pub stream: Vec <u8>,
     pub param: u8,
+    pub escape_bits: Option <u8>,
     pub extra_bits_len: u8,
 }
 
+/// Represents the residual coding method of a FIXED or LPC audio subframe
+///
+/// Both methods partition the residuals in the same way. `Rice` uses 4-bit
+/// Rice parameters while `Rice2` uses 5-bit Rice parameters, which are only
+/// needed by partitions of large residuals such as those of 24-bit audio.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum RiceCodingMethod {
+    Rice,
+    Rice2,
+}
+
+/// Represents how a single partition of the residuals is coded
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum PartitionCoding {
+    Rice(u8),
+    Escape(u8),
+}
+
+impl RiceCodingMethod {
+    /// Get the 2-bit residual coding method field of a subframe
+    pub fn method_bits(&self) -> u64 {
+
+        match self {
+            RiceCodingMethod::Rice => 0b00,
+            RiceCodingMethod::Rice2 => 0b01,
+        }
+
+    }
+
+    /// Get the length of the Rice parameter field of each partition in bits
+    pub fn param_len(&self) -> u8 {
+
+        match self {
+            RiceCodingMethod::Rice => 4,
+            RiceCodingMethod::Rice2 => 5,
+        }
+
+    }
+
+    /// Get the Rice parameter value reserved as an escape code, i.e. all ones
+    pub fn escape_param(&self) -> u64 {
+
+        (1 << self.param_len()) - 1
+
+    }
+
+    /// Get the largest Rice parameter that fits the parameter field
+    /// 
+    /// The largest value of the field is reserved as an escape code, hence the
+    /// largest usable parameter is 14 for `Rice` and 30 for `Rice2`.
+    pub fn max_param(&self) -> u8 {
+
+        self.escape_param() as u8 - 1
+
+    }
+}
+
 impl RiceEncoderOptions {
     /// Create a builder to the Rice encoder
     pub fn new(num_samples: u64, predictor_order: u8) -> Self {
 
         Self {
-            num_samples: num_samples,
-            predictor_order: predictor_order
+            num_samples,
+            predictor_order,
+            min_partition_order: Self::min_rice_partition_order(),
+            max_partition_order: Self::max_rice_partition_order(num_samples),
+            is_exact: false,
         }
 
     }
 
+    /// Compare partition orders and parameters by their exact sizes
+    /// 
+    /// This is slower as every residual is visited for each parameter tried,
+    /// but `estimate_bits` then returns the exact size of the encoding.
+    pub fn exact(mut self, is_exact: bool) -> Self {
+
+        self.is_exact = is_exact;
+        self
+
+    }
+
+    /// Limit the partition orders tried by the encoder
+    /// 
+    /// Orders beyond what the block size allows are never tried. If none of the
+    /// orders in the range can be used, partition order 0 is used instead.
+    pub fn partition_orders(mut self, min_partition_order: u8, max_partition_order: u8) -> Self {
+
+        self.min_partition_order = min_partition_order;
+        self.max_partition_order = max_partition_order.min(Self::max_rice_partition_order(self.num_samples));
+        self
+
+    }
+
+    /// Get the largest bit width of escaped residuals
+    /// 
+    /// The width of an escaped partition is stored in a 5-bit field.
+    fn max_escape_bits() -> u8 {
+
+        31
+
+    }
+
     /// Get the minimum partition order
     /// 
     /// The default minimum partition order is zero
@@ -49,47 +148,90 @@ impl RiceEncoderOptions {
     /// that makes up the block size, or the index of the least significant
     /// 1 bit in the block size. Note that odd-sized block sizes can only
     /// have a partition order of 0 as the number of partitions should be
-    /// a power of two.
-    fn max_rice_partition_order(mut block_size: u64) -> u8 {
+    /// a power of two. The partition order field is four bits long, so the
+    /// order is capped at 15.
+    fn max_rice_partition_order(block_size: u64) -> u8 {
 
-        if block_size & 2 == 2 {
-            return 2;
+        if block_size == 0 {
+            return 0;
         }
 
-        if block_size & 4 == 4 {
-            return 4;
-        }
+        (block_size.trailing_zeros() as u8).min(15)
 
-        if block_size & 8 == 8 {
-            return 8;
-        }
+    }
 
-        if block_size & 16 == 16 {
-            return 16;
-        }
+    /// Check whether a partition order can be used for this block
+    /// 
+    /// Each partition holds `block_size >> partition_order` samples, and the first
+    /// partition additionally skips the `predictor_order` warm-up samples. Hence,
+    /// the partitions must be larger than the predictor order.
+    fn is_valid_partition_order(&self, partition_order: u8) -> bool {
 
-        if block_size & 32 == 32 {
-            return 32;
-        }
+        partition_order <= Self::max_rice_partition_order(self.num_samples)
+            && (self.num_samples >> partition_order) > self.predictor_order as u64
 
-        if block_size & 64 == 64 {
-            return 64;
-        }
+    }
 
-        if block_size & 128 == 128 {
-            return 128;
+    /// Split the residuals into `2^partition_order` partitions
+    /// 
+    /// The first partition is shorter than the rest by `predictor_order` residuals.
+    fn partitions<'a>(&self, partition_order: u8, residuals: &'a [i64]) -> Vec <&'a [i64]> {
+
+        let partition_len = (self.num_samples >> partition_order) as usize;
+        let mut partitions = Vec::with_capacity(1 << partition_order);
+        let mut start = 0;
+
+        for i in 0..(1usize << partition_order) {
+            let len = if i == 0 {
+                partition_len - self.predictor_order as usize
+            } else {
+                partition_len
+            };
+            let end = (start + len).min(residuals.len());
+            partitions.push(&residuals[start..end]);
+            start = end;
         }
 
-        return 1; // odd numbers
+        partitions
 
     }
 
     /// Compute the best partition order and best Rice parameters for each partition
     /// 
     /// The best partition order is computed based on the order that yields the minimum
-    /// total number of bits of the resulting Rice encoding.
-    fn best_partition_and_params(&self, residuals: &Vec <i64>) -> (Vec <u8>, u8) {
+    /// total number of bits of the resulting Rice encoding. This method returns the
+    /// parameters, the partition order and the total number of bits of the partitions
+    /// as given by `best_parameters`, respectively. The number of bits is `None` if no
+    /// parameters could be found for any partition order.
+    fn best_partition_and_params(&self, residuals: &[i64]) -> (Vec <PartitionCoding>, u8, Option <u64>) {
         
+        let mut best: Option <(Vec <PartitionCoding>, u64, u8)> = None;
+
+        for partition_order in self.min_partition_order..=self.max_partition_order {
+            if !self.is_valid_partition_order(partition_order) {
+                continue;
+            }
+
+            if let Some((params, bits)) = self.best_parameters(partition_order, residuals) {
+                let is_better = match &best {
+                    Some((_, best_bits, _)) => bits < *best_bits,
+                    None => true,
+                };
+                if is_better {
+                    best = Some((params, bits, partition_order));
+                }
+            }
+        }
+
+        if best.is_none() {
+            best = self.best_parameters(0, residuals)
+                .map(|(params, bits)| (params, bits, 0));
+        }
+
+        match best {
+            Some((params, bits, partition_order)) => (params, partition_order, Some(bits)),
+            None => (vec![PartitionCoding::Rice(RiceCodingMethod::Rice.max_param())], 0, None),
+        }
         
     }
 
@@ -106,91 +248,217 @@ impl RiceEncoderOptions {
     /// Note that only partition order 0 is allowed for odd-length residuals
     /// as the number of partitions should be a power of two.
     /// 
+    /// This method returns the parameter of each partition and the estimated total
+    /// number of bits of the partitions including their parameter fields, respectively.
+    /// The parameters are searched up to the 5-bit range, and the parameter fields
+    /// are counted as 5 bits only if some parameter does not fit in 4 bits.
+    /// If the encoder is exact, the neighboring parameters are tried for as long as
+    /// they get smaller and the total number of bits is exact. A partition is escaped
+    /// instead if storing its residuals as fixed-width numbers takes fewer bits.
+    /// 
     /// # Errors
     /// Returns `None` if a best parameter cannot be found for any partition. This
     /// arises usually if the predictor order is larger than the amount of residuals
     /// in a partition.
-    
-    fn best_parameters(&self, partition_order: u8, residuals: &Vec <i64>) -> Option <(Vec <u8>, u64)> {
+    fn best_parameters(&self, partition_order: u8, residuals: &[i64]) -> Option <(Vec <PartitionCoding>, u64)> {
         
-        if partition_order as usize > residuals.len() {
+        if !self.is_valid_partition_order(partition_order) {
             return None;
         }
 
-        let mut abs_r_mean: u64 = 0;
-        let mut best_partition_order: Vec<u8> = Vec::new();
-
-        for i in residuals.iter() {
-            abs_r_mean += i.abs() as u64;
-            let x: u64 = Self::zigzag(*i);
-            best_partition_order.push(Self::max_rice_partition_order(x));
+        let mut params: Vec <PartitionCoding> = Vec::new();
+        let mut total_bits: u64 = 0;
+
+        for partition in self.partitions(partition_order, residuals) {
+            let n_partition_samples = partition.len() as u64;
+            let abs_residual_sum: u64 = partition.iter()
+                .map(|r| Self::zigzag(*r))
+                .sum();
+
+            //start from the estimate lg(mean) and check the neighboring parameters
+            let estimate = if n_partition_samples == 0 || abs_residual_sum < n_partition_samples {
+                0
+            } else {
+                (abs_residual_sum / n_partition_samples).ilog2() as u8
+            };
+
+            let mut best_param = estimate.min(RiceCodingMethod::Rice2.max_param());
+            let (best_param, best_bits) = if self.is_exact {
+                let bits_of = |param| Self::bits_in_partition_exact(param, n_partition_samples, partition);
+                let mut best_bits = bits_of(best_param);
+                for step in [-1i8, 1] {
+                    let mut param = best_param;
+                    loop {
+                        let next_param = param as i8 + step;
+                        if next_param < 0 || next_param as u8 > RiceCodingMethod::Rice2.max_param() {
+                            break;
+                        }
+                        let bits = bits_of(next_param as u8);
+                        if bits >= best_bits {
+                            break;
+                        }
+                        param = next_param as u8;
+                        best_bits = bits;
+                    }
+                    best_param = param;
+                }
+                (best_param, best_bits)
+            } else {
+                let mut best_bits = Self::bits_in_partition_sums(best_param, n_partition_samples, abs_residual_sum);
+                for param in [estimate.saturating_sub(1), estimate + 1] {
+                    if param > RiceCodingMethod::Rice2.max_param() {
+                        continue;
+                    }
+                    let bits = Self::bits_in_partition_sums(param, n_partition_samples, abs_residual_sum);
+                    if bits < best_bits {
+                        best_param = param;
+                        best_bits = bits;
+                    }
+                }
+                (best_param, best_bits)
+            };
+
+            let escape_bits = Self::escape_bits(partition);
+            let escaped_bits = 5 + n_partition_samples * escape_bits as u64;
+            let coding = if escape_bits <= Self::max_escape_bits() && escaped_bits < best_bits {
+                total_bits += escaped_bits;
+                PartitionCoding::Escape(escape_bits)
+            } else {
+                total_bits += best_bits;
+                PartitionCoding::Rice(best_param)
+            };
+
+            params.push(coding);
         }
 
-        let logable_r_mean: f64 = abs_r_mean as f64;
-        let sizeof_residuals: f64 = residuals.len() as f64;
-        let parameter_M: f64 = (logable_r_mean - 1.0).log(2.0) - sizeof_residuals.log(2.0) + 1.0;
-        let returnable_M: u64 = parameter_M as u64;
+        let param_len = Self::coding_method(&params).param_len() as u64;
+        total_bits += params.len() as u64 * param_len;
 
-        return Some((best_partition_order, returnable_M));
+        Some((params, total_bits))
         
     }
 
+    /// Get the residual coding method that can hold the parameters of all partitions
+    fn coding_method(params: &[PartitionCoding]) -> RiceCodingMethod {
+
+        let needs_rice2 = params.iter().any(|coding| match coding {
+            PartitionCoding::Rice(param) => *param > RiceCodingMethod::Rice.max_param(),
+            PartitionCoding::Escape(_) => false,
+        });
+
+        if needs_rice2 { RiceCodingMethod::Rice2 } else { RiceCodingMethod::Rice }
+
+    }
+
+    /// Find the smallest bit width that can hold every residual of a partition
+    /// as a two's complement number
+    /// 
+    /// A partition of zeros needs no bits at all, so its width is 0.
+    fn escape_bits(residuals: &[i64]) -> u8 {
+
+        residuals.iter()
+            .map(|r| if *r == 0 { 0 } else { 65 - (*r ^ (*r >> 63)).leading_zeros() as u8 })
+            .max()
+            .unwrap_or(0)
+
+    }
+
     /// Find the exact total number of bits needed to represent a Rice-encoded
     /// partition of samples
     /// 
     /// A residual `r` can be represented using 1 bit for the unary stop mark,
     /// `rice_param` bits for the truncated binary part of the rice encoding, and
     /// `zigzag(r) >> rice_param` bits for the unary tally marks.
-    fn bits_in_partition_exact(rice_param: u8, n_partition_samples: u64, residuals: &Vec<i64>) -> u64 {
-        todo!()
+    fn bits_in_partition_exact(rice_param: u8, n_partition_samples: u64, residuals: &[i64]) -> u64 {
+
+        let tally_bits: u64 = residuals.iter()
+            .map(|r| Self::zigzag(*r) >> rice_param)
+            .sum();
+
+        n_partition_samples * (1 + rice_param as u64) + tally_bits
+
     }
 
     /// Find the total number of bits occupied by this encoding
     /// 
     /// Rice encoding uses `q + 1` bits for the unary-encoded quotient `q` and
-    /// `rice_param` bits for the binary remainder
+    /// `rice_param` bits for the binary remainder. Here, `abs_residual_sum` is the
+    /// sum of the zigzag-encoded residuals, so the sum of the quotients is
+    /// approximated by `abs_residual_sum >> rice_param`.
     fn bits_in_partition_sums(rice_param: u8, n_partition_samples: u64, abs_residual_sum: u64) -> u64 {
-        todo!()
+
+        n_partition_samples * (1 + rice_param as u64) + (abs_residual_sum >> rice_param)
+
+    }
+
+    /// Estimate the total number of bits of the partitioned Rice encoding of the residuals
+    ///
+    /// This includes the 4-bit partition order and the parameter field of each partition, but
+    /// not the 2-bit residual coding method. The estimate is computed from the sums of the
+    /// residuals, or exactly if the encoder is exact, and is used to compare predictors
+    /// without actually encoding them.
+    pub fn estimate_bits(&self, residuals: &[i64]) -> u64 {
+
+        match self.best_partition_and_params(residuals) {
+            (_, _, Some(bits)) => 4 + bits,
+            (_, _, None) => u64::MAX,
+        }
+
     }
 
     /// Encode residuals into Rice encoding
-    /// 
+    ///
     /// To encode a residual into its Rice encoding, it should be first processed
     /// using zigzag encoding so that all of the residuals become nonnegative numbers.
     /// Then, the Rice encoding of each residual is computed.
     /// 
     /// Note that the contents are _not_ ensured to be byte-aligned. Hence, this method returns
     /// the Rice-encoded byte vector containing the number of extra unused bits at the last element.
-    pub fn encode(rice_param: u8, residuals: &Vec <i64>) -> RiceEncodedStream {
+    pub fn encode(rice_param: u8, residuals: &[i64]) -> RiceEncodedStream {
 
-        /*
-        
-        pub struct RiceEncodedStream {
-            pub stream: Vec <u8>,
-            pub param: u8,
-            pub extra_bits_len: u8,
+        let mut writer = BitstreamWriter::new();
+        let remainder_mask = (1u64 << rice_param) - 1;
+
+        for residual in residuals.iter() {
+            // use zigzag encoding to make all residuals non-negative
+            let folded = Self::zigzag(*residual);
+
+            // the quotient is written in unary and the remainder in binary
+            writer.write_unary(folded >> rice_param);
+            writer.write_bits(folded & remainder_mask, rice_param);
         }
 
-        */
+        let (stream, extra_bits_len) = writer.into_partial_bytes();
 
-        // use zigzag encoding to make all residuals non-negative
+        RiceEncodedStream {
+            stream,
+            param: rice_param,
+            escape_bits: None,
+            extra_bits_len,
+        }
 
-        let absolute_residuals: Vec<u64> = Vec::new();
-        for i in residuals.iter() {
-            absolute_residuals.push(Self::zigzag(*i));
+    }
+
+    /// Encode residuals into an escaped partition
+    /// 
+    /// Each residual is written as an `escape_bits`-bit two's complement number,
+    /// which must be wide enough to hold all of the residuals.
+    pub fn encode_escaped(escape_bits: u8, residuals: &[i64]) -> RiceEncodedStream {
+
+        let mut writer = BitstreamWriter::new();
+
+        for residual in residuals.iter() {
+            writer.write_signed(*residual, escape_bits);
         }
 
-        /// S = residual[i]
-        /// M = Rice Parameter
-        /// log_2 (M) = K bits needed to represent B
-        /// Evaluate U = S >> K and save result as unary
-        /// B = S & (M - 1) and represent in binary padded to the left with zeros until length K
-        /// Rice(S) = (U << K) | B, or U and B concatenated together;
-        
-        // Step 1: Get Rice Parameter M
+        let (stream, extra_bits_len) = writer.into_partial_bytes();
 
-        let data_store = Self::best_parameters(Self::RiceEncoderOptions,0, residuals);
-        let rice_param: u64 = data_store[1];
+        RiceEncodedStream {
+            stream,
+            param: 0,
+            escape_bits: Some(escape_bits),
+            extra_bits_len,
+        }
 
     }
 
@@ -200,15 +468,29 @@ impl RiceEncoderOptions {
     /// the residual into groups. Each group is then found its best Rice parameter and
     /// each residual in the group is then encoded using the parameter.
     /// 
-    /// The method returns each Rice-encoded group in chronological order and the partition order,
-    /// respectively. The number of elemenets in the vector of Rice-encoded groups should be less than
-    /// or equal to `2^partition order`.
+    /// The method returns each Rice-encoded group in chronological order, the partition order and
+    /// the residual coding method, respectively. The number of elemenets in the vector of Rice-encoded
+    /// groups should be less than or equal to `2^partition order`. The `Rice2` method is used only if
+    /// some parameter does not fit in the 4-bit parameter field.
     /// 
-    /// Note that each of the contents are _not_ ensured to be byte-aligned. Hence, this method
-    /// returns the Rice-encoded byte stream and the number of extra unused bits at the last byte
-    /// of the stream, respectively.
-    pub fn encode_by_partition(&self, residuals: &Vec <i64>)  -> (Vec <RiceEncodedStream>, u8) {
-        todo!()
+    /// Note that each of the contents are _not_ ensured to be byte-aligned. Hence, the
+    /// number of extra unused bits at the last byte is kept in each `RiceEncodedStream`.
+    pub fn encode_by_partition(&self, residuals: &[i64])  -> (Vec <RiceEncodedStream>, u8, RiceCodingMethod) {
+
+        let (params, partition_order, _) = self.best_partition_and_params(residuals);
+        let method = Self::coding_method(&params);
+
+        let streams = self.partitions(partition_order, residuals)
+            .into_iter()
+            .zip(params)
+            .map(|(partition, coding)| match coding {
+                PartitionCoding::Rice(param) => Self::encode(param, partition),
+                PartitionCoding::Escape(escape_bits) => Self::encode_escaped(escape_bits, partition),
+            })
+            .collect();
+
+        (streams, partition_order, method)
+
     }
 
     /// Convert an integer into its zigzag encoding. With this encoding, all
@@ -216,7 +498,7 @@ impl RiceEncoderOptions {
     pub fn zigzag(num: i64) -> u64 { // followed the formula over at https://docs.rs/residua-zigzag/latest/zigzag/
         
         let q = (num >> 63) ^ (num << 1); 
-        return q as u64;
+        q as u64
 
     }
 }
@@ -265,8 +547,94 @@ mod tests {
         assert_eq!(rice_enc_stream.stream, out_vec_ans);
         assert_eq!(rice_enc_stream.extra_bits_len, 1);
     }
-}
 
-fn main() {
+    #[test]
+    fn exact_bits_match_encoding() {
+        let residuals: Vec <i64> = (0..1022)
+            .map(|i: i64| if i % 200 < 20 { (i * 7919) % 4001 - 2000 } else { (i * 31) % 17 - 8 })
+            .collect();
+        let options = RiceEncoderOptions::new(1024, 2).exact(true);
+
+        let (streams, _, method) = options.encode_by_partition(&residuals);
+        let stream_bits: u64 = streams.iter()
+            .map(|stream| method.param_len() as u64 + stream.escape_bits.map_or(0, |_| 5) + 8 * stream.stream.len() as u64 - stream.extra_bits_len as u64)
+            .sum();
+
+        assert_eq!(options.estimate_bits(&residuals), 4 + stream_bits);
+        assert!(options.estimate_bits(&residuals) <= RiceEncoderOptions::new(1024, 2).estimate_bits(&residuals));
+    }
+
+    #[test]
+    fn escape_bits_width() {
+        assert_eq!(RiceEncoderOptions::escape_bits(&[0, 0, 0]), 0);
+        assert_eq!(RiceEncoderOptions::escape_bits(&[0, -1]), 1);
+        assert_eq!(RiceEncoderOptions::escape_bits(&[1, -2]), 2);
+        assert_eq!(RiceEncoderOptions::escape_bits(&[127, -128]), 8);
+        assert_eq!(RiceEncoderOptions::escape_bits(&[128]), 9);
+    }
 
+    #[test]
+    fn escape_uniform_noise() {
+        // uniform noise is cheaper to store as fixed-width numbers
+        let mut seed: u64 = 164;
+        let mut residuals: Vec <i64> = (0..4096)
+            .map(|_| {
+                seed = seed.wrapping_mul(6364136223846793005).wrapping_add(1442695040888963407);
+                (seed >> 52) as i64 - 2048
+            })
+            .collect();
+        // followed by silence
+        residuals.extend(std::iter::repeat_n(0, 4096));
+        let options = RiceEncoderOptions::new(8192, 0).partition_orders(1, 1);
+
+        let (streams, partition_order, method) = options.encode_by_partition(&residuals);
+
+        assert_eq!(partition_order, 1);
+        assert_eq!(method, RiceCodingMethod::Rice);
+        assert_eq!(streams[0].escape_bits, Some(12));
+        assert_eq!(streams[0].stream.len(), 4096 * 12 / 8);
+        assert_eq!(streams[1].escape_bits, Some(0));
+        assert!(streams[1].stream.is_empty());
+        assert_eq!(options.estimate_bits(&residuals), 4 + 2 * (4 + 5) + 4096 * 12);
+    }
+
+    #[test]
+    fn escape_encoding() {
+        let rice_enc_stream = RiceEncoderOptions::encode_escaped(5, &[3, -1, -13]);
+
+        assert_eq!(rice_enc_stream.stream, vec![0x1f, 0xe6]);
+        assert_eq!(rice_enc_stream.extra_bits_len, 1);
+        assert_eq!(rice_enc_stream.escape_bits, Some(5));
+    }
+
+    #[test]
+    fn rice2_large_residuals() {
+        // residuals of mixed scales up to 2^20 need parameters beyond 14
+        let mut seed: u64 = 164;
+        let residuals: Vec <i64> = (0..4096)
+            .map(|_| {
+                seed = seed.wrapping_mul(6364136223846793005).wrapping_add(1442695040888963407);
+                let magnitude = ((seed >> 44) as i64) >> ((seed >> 8) % 4);
+                if seed & 1 == 0 { magnitude } else { -magnitude }
+            })
+            .collect();
+        let options = RiceEncoderOptions::new(4096, 0).exact(true);
+
+        let (streams, _, method) = options.encode_by_partition(&residuals);
+        let stream_bits: u64 = streams.iter()
+            .map(|stream| 5 + stream.escape_bits.map_or(0, |_| 5) + 8 * stream.stream.len() as u64 - stream.extra_bits_len as u64)
+            .sum();
+
+        assert_eq!(method, RiceCodingMethod::Rice2);
+        assert!(streams.iter().any(|stream| stream.escape_bits.is_none() && stream.param > 14));
+        assert_eq!(options.estimate_bits(&residuals), 4 + stream_bits);
+    }
+
+    #[test]
+    fn coding_method_fields() {
+        assert_eq!(RiceCodingMethod::Rice.escape_param(), 0b1111);
+        assert_eq!(RiceCodingMethod::Rice.max_param(), 14);
+        assert_eq!(RiceCodingMethod::Rice2.escape_param(), 0b11111);
+        assert_eq!(RiceCodingMethod::Rice2.max_param(), 30);
+    }
 }
@@ -5,91 +5,35 @@ impl Utf8Encoder {
     /// 
     /// Although UTF-8 encoding is for characters, characters are
     /// mapped to certain numbers.
-    pub fn encode(mut num: u64) -> Vec <u8> {
-        //create vec to handle the encoded and a variable for the header 
+    pub fn encode(num: u64) -> Vec <u8> {
+        //create vec to handle the encoded bytes
         let mut data_store = Vec::<u8>::new();
-        let mut header = 0;
 
-        //create a template to determine the number of bytes and maximum number of bits 
-        let mut num_bytes =0;
-        let mut max_bits =0;
-
-        if num < 65{
-            num_bytes = 0;
-            max_bits = 7;
-        }
-        else if num < 2049 {
-            num_bytes = 2;
-            max_bits = 11
-        }
-        else if num < 65_537 {
-            num_bytes = 3;
-            max_bits = 16
-        }
-        else if num < 2_097_153{
-            num_bytes = 4;
-            max_bits = 21;
-        }
-        else if num < 67_108_865{
-            num_bytes = 5;
-            max_bits = 26;
-        }
-        else if num < 2_147_483_649{
-            num_bytes = 6;
-            max_bits = 31
-        }
-        else if num <1_099_511_627_777{
-            num_bytes = 7;
-            max_bits = 40;
-        }
-        //set up first header 
-        if num_bytes != 0 {
-            for i in 0..(num_bytes-1){
-                header = header + 1;
-                header = header << 1;
-            }
-        }
-        //set up the rest of first byte if num_bytes not equal to 7 
-        //conditional should be 7 - #header bits
-        if num_bytes != 7{ 
-            for i in 1..(7-num_bytes){
-                let append = (num & (0b1 << (max_bits - i ))) >> (max_bits - i ); //one bit only
-                header = header << 1;
-                header = header + append;
-            }
-        }
-        data_store.push(header as u8);
-
-        if num_bytes == 0 {
+        //plain ASCII range fits in a single byte
+        if num < 0x80 {
+            data_store.push(num as u8);
             return data_store;
         }
 
-        let mut nex_byte = 0;
-        let mut reset = 0;
-        //take note for preceeding bytes the first two bit is always 2b10 
-        //every 6 counts we need to push the byte then reset all 
-        //do this for max_bits number of times given start at first 8 bits minus the number of bytes (this is first byte)
+        //determine the number of bytes needed, each continuation byte
+        //carries 6 bits and the first byte carries (7 - num_bytes) bits
+        let mut num_bytes = 2;
+        while num_bytes < 7 && num >= (1u64 << (5 * num_bytes + 1)) {
+            num_bytes += 1;
+        }
 
-        for i in (8-num_bytes)..=max_bits{
-            let append = (num & (0b1 << (max_bits - i ))) >> (max_bits - i ); //fetch the nth bit from the front of th whole number then shift to first bit
-            nex_byte = nex_byte + append;
-            reset = reset + 1;
+        //set up first byte: num_bytes leading ones, a zero, then the MSBits
+        let header: u64 = (0xff00 >> num_bytes) & 0xff;
+        let first_bits = 6 * (num_bytes - 1);
+        data_store.push((header | (num >> first_bits)) as u8);
 
-            //check if reset else shift next byte
-            if reset == 6 {
-                let add_ten = 0b1 << 7; //append 10 in the first two bits 
-                nex_byte = nex_byte + add_ten;
-                data_store.push(nex_byte as u8);
-                nex_byte = 0;
-                reset = 0;
-            }
-            else {
-                nex_byte = nex_byte << 1;
-            }
+        //take note for succeeding bytes the first two bits are always 0b10
+        for i in (0..(num_bytes - 1)).rev() {
+            let next_byte = 0x80 | ((num >> (6 * i)) & 0x3f);
+            data_store.push(next_byte as u8);
         }
-         
 
-        return data_store;
+        data_store
     }
 }
 
@@ -114,4 +58,12 @@ mod tests {
 
         assert_eq!(out_val_ans, out_val);
     }
+
+    #[test]
+    fn sample_03() {
+        assert_eq!(Utf8Encoder::encode(0x7f), vec![0x7f]);
+        assert_eq!(Utf8Encoder::encode(0x80), vec![0xc2, 0x80]);
+        assert_eq!(Utf8Encoder::encode(0xfffff), vec![0xf3, 0xbf, 0xbf, 0xbf]);
+        assert_eq!(Utf8Encoder::encode(0xf_ffff_ffff), vec![0xfe, 0xbf, 0xbf, 0xbf, 0xbf, 0xbf, 0xbf]);
+    }
 }
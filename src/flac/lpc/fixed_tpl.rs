@@ -1,3 +1,5 @@
+use crate::flac::lpc::is_residual_in_range;
+
 pub struct FixedPredictor;
 
 impl FixedPredictor {
@@ -6,45 +8,50 @@ impl FixedPredictor {
     /// The predictor orders are from 0 to 4 inclusive and is retrieved
     /// by finding the predictor that yields the *minimum* absolute
     /// sum of residuals for the given `data` and derived predictor.
-    pub fn best_predictor_order(data: &Vec <i64>) -> Option <u8> {
+    /// Orders whose residuals cannot be computed are skipped, so a lower
+    /// order is returned if the residuals of a higher order overflow.
+    /// 
+    /// # Errors
+    /// Returns `None` if the residuals of no order can be computed.
+    pub fn best_predictor_order(data: &[i64]) -> Option <u8> {
         
         let mut resids: Vec<i64> = Vec::new();
 
         for i in 0..=4 {
             
             let value = Self::get_residuals(data, i);
-            let value_arr: Vec<i64>;
-
-            match value {
-                Some(some_vec) => {
-                    value_arr = some_vec;
-                },
+            let value_arr: Vec<i64> = match value {
+                Some(some_vec) => some_vec,
                 _ => {
-                    return None;
+                    resids.push(i64::MAX);
+                    continue;
                 }
-            }
+            };
 
             let mut abs_sum: i64 = 0;
 
             for entry in value_arr {
-                abs_sum += entry;
+                abs_sum += entry.abs();
             }
 
-            abs_sum = abs_sum.abs();
             resids.push(abs_sum);
         }
 
         let mut min = resids[0];
         let mut min_index: usize = 0;
 
-        for q in 1..resids.len() {
-            if resids[q] < min {
-                min = resids[q];
+        for (q, &resid) in resids.iter().enumerate().skip(1) {
+            if resid < min {
+                min = resid;
                 min_index = q;
             }
         }
 
-        return Some(min_index as u8);
+        if min == i64::MAX {
+            return None;
+        }
+
+        Some(min_index as u8)
 
     }
 
@@ -60,94 +67,46 @@ impl FixedPredictor {
     /// 3: r[i] = 3 * data[i - 1] - 3 * data[i - 2] + data[i - 3]
     /// 4: r[i] = 4 * data[i - 1] - 6 * data[i - 2] + 4 data[i - 3] - data[i - 4]
     /// 
-    /// This function returns a vector with each element containing data[i] - r[i].
+    /// This function returns a vector with each element containing data[i] - r[i]
+    /// starting from `i = predictor_order`, as the first `predictor_order` samples
+    /// are stored as-is as warm-up samples.
     /// 
     /// # Errors
     /// `None` is returned if an error occurs in the function. This includes whether
-    /// the predictor order provided is not within 0 and 4 inclusive and whether the
-    /// size of `data` is less than the predictor order.
-    pub fn get_residuals(data: &Vec <i64>, predictor_order: u8) -> Option <Vec <i64>> {
-
-        if data.len() < predictor_order as usize {
-            return None;
-        }
+    /// the predictor order provided is not within 0 and 4 inclusive, whether the
+    /// size of `data` is not larger than the predictor order and whether some
+    /// residual does not fit the range allowed by FLAC.
+    pub fn get_residuals(data: &[i64], predictor_order: u8) -> Option <Vec <i64>> {
 
         if predictor_order > 4 {
             return None;
         } 
 
-        let mut return_data = data.clone();
-        match predictor_order {
-
-            0 => {
-                return Some(return_data); // x - 0 = x
-            },
-
-            1 => {
-
-                if data.len() < 2 {
-                    return None;
-                }
-
-                return_data[0] = 0;
-                for i in 1..return_data.len() {
-
-                    return_data[i] -= return_data[i - 1];
-
-                }
-
-            },
-            2 => {
-                if data.len() < 3 {
-                    return None;
-                }
-                //return_data[0] = 0;
-                //return_data[1] = 0;
-                for i in 2..return_data.len() {
-
-                    let r = 2 * return_data[i - 1] - return_data[i - 2];
-                    return_data[i] -= r;
-                }
-
-            },
-            3 => {
-                if data.len() < 4 {
-                    return None;
-                }
-
-                //return_data[0] = 0;
-                //return_data[1] = 0;
-                //return_data[2] = 0;
-                for i in 3..return_data.len() {
-
-                    let r = 3 * return_data[i - 1] - 3 * data[i - 2] + data[i - 3];
-                    return_data[i] -= r;
-                }
-
-            },
-            4 => {
-                if data.len() < 5 {
-                    return None;
-                }
-                //return_data[0] = 0;
-                //return_data[2] = 0;
-                //return_data[3] = 0;
-                //return_data[4] = 0;
-                for i in 4..return_data.len() {
-
-                    let r = 4 * data[i - 1] - 6 * data[i - 2] + 4 * data[i - 3] - data[i - 4];
-                    return_data[i] -= r;
-                }
-
-            }
+        let order = predictor_order as usize;
+        if data.len() <= order {
+            return None;
+        }
 
-            _ => {
+        // the first `order` samples are warm-up samples and have no residuals
+        let mut return_data = Vec::with_capacity(data.len() - order);
+        for i in order..data.len() {
+
+            let r = match predictor_order {
+                0 => 0,
+                1 => data[i - 1],
+                2 => 2 * data[i - 1] - data[i - 2],
+                3 => 3 * data[i - 1] - 3 * data[i - 2] + data[i - 3],
+                _ => 4 * data[i - 1] - 6 * data[i - 2] + 4 * data[i - 3] - data[i - 4],
+            };
+            let residual = data[i] - r;
+            if !is_residual_in_range(residual) {
                 return None;
             }
+            return_data.push(residual);
 
         }
 
-        return Some(return_data);
+        Some(return_data)
 
     }
 }
@@ -177,8 +136,37 @@ mod tests {
         assert!(ans.is_some());
         assert_eq!(ans.unwrap(), out_vec_ans);
     }
-}
 
-fn main() {
+    #[test]
+    fn best_order_alternating() {
+        // The order 1 residuals alternate in sign and cancel out when summed
+        let in_vec: Vec <i64> = (0..64).map(|i| if i % 2 == 0 { 0 } else { 100 }).collect();
 
+        assert_eq!(FixedPredictor::best_predictor_order(&in_vec), Some(0));
+    }
+
+    #[test]
+    fn residuals_out_of_range() {
+        let in_vec: Vec <i64> = vec![i32::MAX as i64, i32::MIN as i64 + 1, i32::MAX as i64, 0];
+
+        assert_eq!(FixedPredictor::get_residuals(&in_vec, 0), Some(in_vec[..].to_vec()));
+        assert_eq!(FixedPredictor::get_residuals(&in_vec, 1), None);
+        assert_eq!(FixedPredictor::get_residuals(&[i32::MIN as i64, 0], 0), None);
+    }
+
+    #[test]
+    fn best_order_falls_back() {
+        // A full-scale square wave only has residuals in range at order 0
+        let in_vec: Vec <i64> = (0..64).map(|i| if i % 8 < 4 { i32::MAX as i64 } else { -i32::MAX as i64 }).collect();
+
+        assert_eq!(FixedPredictor::best_predictor_order(&in_vec), Some(0));
+        assert_eq!(FixedPredictor::best_predictor_order(&[i32::MIN as i64; 8]), Some(1));
+    }
+
+    #[test]
+    fn best_order_ramp() {
+        let in_vec: Vec <i64> = (0..64).map(|i| 3 * i - 50).collect();
+
+        assert_eq!(FixedPredictor::best_predictor_order(&in_vec), Some(2));
+    }
 }
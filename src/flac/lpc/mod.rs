@@ -1,2 +1,13 @@
 pub mod fixed;
-pub mod var;
\ No newline at end of file
+pub mod var;
+pub mod window;
+
+/// Check whether a residual fits the range allowed by the FLAC format
+///
+/// Residuals should be representable as 32-bit two's complement numbers,
+/// excluding the most negative one. Predicting 32-bit samples or the
+/// 33-bit side channel of 32-bit audio may give residuals outside this
+/// range, in which case the predictor cannot be used.
+pub fn is_residual_in_range(residual: i64) -> bool {
+    residual > i32::MIN as i64 && residual <= i32::MAX as i64
+}
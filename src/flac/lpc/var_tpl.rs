@@ -1,3 +1,5 @@
+use crate::flac::lpc::is_residual_in_range;
+
 pub struct VarPredictor;
 
 impl VarPredictor {
@@ -7,22 +9,38 @@ impl VarPredictor {
     /// provided vector of data. 
     /// The function computes the autocorrelations of the provided vector of
     /// data from `R[0]` until `R[max_lag]`. For example, if `max_lag` is 2, then
-    /// the output contains three elements corresponding to R[0] until R[3],
+    /// the output contains three elements corresponding to R[0] until R[2],
     /// respectively
-    pub fn get_autocorrelation(data: &Vec <i32>, lag: u32) -> Vec <f64> {
-        let max_lag = lag as usize;
-        let data_store = vec![0.0; max_lag + 1];
-        if data.len() <= 1 {
+    pub fn get_autocorrelation(data: &[i64], lag: u32) -> Vec <f64> {
+        let samples: Vec <f64> = data.iter().map(|x| *x as f64).collect();
+        Self::autocorrelation_of(&samples, lag)
+    }
+
+    /// Get the autocorrelation of a vector of data after applying a window
+    ///
+    /// Each sample is multiplied by the corresponding weight in `window` before
+    /// computing the autocorrelations from `R[0]` until `R[lag]`. The window should
+    /// be as long as the data.
+    pub fn get_windowed_autocorrelation(data: &[i64], window: &[f64], lag: u32) -> Vec <f64> {
+        let samples: Vec <f64> = data.iter()
+            .zip(window.iter())
+            .map(|(x, w)| *x as f64 * w)
+            .collect();
+        Self::autocorrelation_of(&samples, lag)
+    }
+
+    /// Compute the autocorrelations from `R[0]` until `R[lag]` of some samples
+    fn autocorrelation_of(samples: &[f64], lag: u32) -> Vec <f64> {
+        let mut data_store = vec![0.0; lag as usize + 1];
+        if samples.len() <= 1 {
             return data_store;
         }
-        else {   
-            for i in 0..=lag{
-                let mut sum = 0.0;
-                for x in 0..(data.len() - i ){
-                    sum += data[x] * data[x + i];
-                }
-                data_store[i] = sum / (data.len() - i) as f64;
-            }
+
+        for (i, value) in data_store.iter_mut().enumerate().take(samples.len()) {
+            *value = samples.iter()
+                .zip(samples[i..].iter())
+                .map(|(x, y)| x * y)
+                .sum();
         }
 
         data_store
@@ -31,72 +49,52 @@ impl VarPredictor {
     /// Get the predictor coefficients
     /// 
     /// The coefficients are computed using the Levinson-Durbin algorithm.
-    pub fn get_predictor_coeffs(autoc: &Vec <f64>, predictor_order: u32) -> Vec <f64> {
-        let mut data_store = Vec::<f64>::new();
-        //base case 
-        let base_case = autoc[1]/autoc[0];
-        data_store.push(base_case);
-        //compute for coefficients successively, starting at i=0 until i=prediction order  - 1
-        for i in 0..=(predictor_order-1){
-            //Create reverse versions of the vectors data_store(coefficients) and auto correlations
-            let mut a_rev = data_store.clone();
-            a_rev.reverse(); 
-            let mut r_ss = Vec::<f64>::new();
-            for x in 0..=i{
-                let y = x as usize; 
-                r_ss.push(autoc[y+1])
-            }
-            let mut r_rev = r_ss.reverse();
-            //Compute for the correction term ki+1 using a slice of the autocorrelation values and currently computed coefficients
-            //dotproduct for knum ki+1,num = R(i+2) - dot(Rrev,ss[i,1], A[0, i))
+    /// The `i`-th coefficient is multiplied to the sample `i + 1` positions
+    /// before the predicted sample.
+    pub fn get_predictor_coeffs(autoc: &[f64], predictor_order: u32) -> Vec <f64> {
+        let order = predictor_order as usize;
+        let mut data_store = vec![0.0; order];
 
-            let mut dotprod = 0.0;
-            let mut dotfacA = r_rev.clone();
-            let mut dotfacB =data_store.clone();
-            for x in 0..dotfacB.len(){
-                //let y = x as usize;
-                dotprod += &dotfacA[x] * &dotfacB[x];
+        //a silent block cannot be predicted from anything
+        let mut error = autoc[0];
+        if error <= 0.0 {
+            return data_store;
+        }
+
+        //compute for coefficients successively, starting at i=0 until i=prediction order - 1
+        for i in 0..order {
+            //compute the correction (reflection) term k_{i+1}
+            let mut k_num = autoc[i + 1];
+            for x in 0..i {
+                k_num -= data_store[x] * autoc[i - x];
             }
-            let z = i as usize; 
-            let k_num = autoc[z+2] - dotprod;
+            let k = k_num / error;
 
-            //dot product for ki+1,den = R(0) - dot(Rss[1, i], A[0, i))
-            dotprod = 0.0;
-            dotfacB =data_store.clone();
-            let mut dotfacAB =Vec::<f64>::new();
-            if i !=0{
-                for x in 0..i{
-                    let y = x as usize;
-                    dotfacAB.push(r_ss[y+1]);
-                }
-                for x in 0..dotfacAB.len(){
-                    //let y = x as usize;
-                    dotprod += &dotfacAB[x] * &dotfacB[x];
-                }
+            //compute for updated coefficients using the reversed previous coefficients
+            let a_prev = data_store.clone();
+            for x in 0..i {
+                data_store[x] = a_prev[x] - k * a_prev[i - 1 - x];
             }
-            let k_den = autoc[0] - dotprod;
-            let k = k_num/k_den;
-            //compute for updated coefficients
-            let a_prime =  Vec::<f64>::new();
-            for x in 0..=a_rev.len(){
-                //let y = x as usize;
-                a_prime.push(data_store[x]- (k*a_rev[x]));
+            //append k_{i+1} at the end
+            data_store[i] = k;
+
+            error *= 1.0 - k * k;
+            if error <= 0.0 {
+                break;
             }
-            //append kI+1 at the end 
-            a_prime.push(k);
-            data_store = a_prime;
         }
+
         data_store
     }
 
     /// Quantize the predictor coefficients and find their shift factor
     /// 
     /// The shift factor `S` is computed from the maximum absolute value of a coefficient
-    /// `L_max`. This value is computed as `precision - lg(L_max)` or to
-    /// the maximum shift value of 1 << 5 = 31, whichever is smaller. Note that it is
-    /// possible for this shift factor to be negative. In that case, the shift value
-    /// will still be used in quantizing the coefficients but its effective value
-    /// will be zero.
+    /// `L_max`. This value is computed as `precision - 1 - (floor(lg(L_max)) + 1)` or to
+    /// the maximum shift value of 15 representable by the signed 5-bit shift field,
+    /// whichever is smaller. Note that it is possible for this shift factor to be negative.
+    /// In that case, the shift value will still be used in quantizing the coefficients
+    /// but its effective value will be zero.
     /// 
     /// Quantization involves converting the provided floating-point coefficients
     /// into integers. Each of the values are rounded up or down depending on
@@ -105,55 +103,77 @@ impl VarPredictor {
     /// or divided (for negative shift) by `1 << abs(S)` to get the raw value `L_i_r + \epsilon`.
     /// Then, `L_i_r + \epsilon` is rounded away from zero to get the quantized coefficient.
     /// The new rounding error `\epsilon = L_i_r + \epsilon - round(L_i_r)` is then updated for the
-    /// next coefficient.
-    pub fn quantize_coeffs(lpc_coefs: &Vec <f64>, mut precision: u32) -> (Vec <u32>, u32) {
+    /// next coefficient. The quantized coefficients are clamped to fit in `precision` bits.
+    pub fn quantize_coeffs(lpc_coefs: &[f64], precision: u32) -> (Vec <i64>, u32) {
         //compute for shift factor first 
         //we get the maximum absolute value of coefficient 
-        //iterate through lpc_coeffs and get absolute value each 
         let abs_val = lpc_coefs.iter().fold(0.0_f64, |num1, &num2| num1.max(num2.abs())); //use .fold since they are floating point
-        //floor(lg(max(|L|)) + 1) - 1 = max bits
-        let max_p1 = (abs_val.log2() + 1.0).floor() as u32;
-        let max_bits = max_p1 -1 ;
-        //compute for SF from formula sf = max(floor(pb - 1 - floor(lg(max(|L|))) ), N_SHIFT_BITS)
-        let mut sf = (((precision - 1) - max_bits) as i32);
+        if abs_val <= 0.0 {
+            return (vec![0; lpc_coefs.len()], 0);
+        }
+
+        //floor(lg(max(|L|))) + 1 = number of integer bits of the largest coefficient
+        let max_bits = abs_val.log2().floor() as i32 + 1;
+        //compute for SF from formula sf = min(pb - 1 - max_bits, 15)
+        let sf = (precision as i32 - 1 - max_bits).min(15);
+
+        let q_max = (1i64 << (precision - 1)) - 1;
+        let q_min = -(1i64 << (precision - 1));
 
-        //compute new quantized lpc
         //Initialize a rounding error variable e to zero
-        let mut rounding_error = 0.0 ;
-        let mut quantized = Vec::new();
+        let mut rounding_error = 0.0;
+        let mut quantized = Vec::with_capacity(lpc_coefs.len());
         //Compute the quantized coefficient Lraw':
         //● If sf is negative, Lraw' = L / (1 << |S|)
         //● Otherwise, if sf is positive, Lraw' = L * (1 << |S|)
-        for &num_coeff in lpc_coefs.iter(){
-            if sf < 0 {
-                let l_raw =coef / (1 << sf.abs());
-            } 
-            else {
-                let l_raw =coef * (1 << sf);
-            }
+        for &coef in lpc_coefs.iter() {
+            let l_raw = if sf < 0 {
+                coef / (1i64 << sf.abs()) as f64
+            } else {
+                coef * (1i64 << sf) as f64
+            };
             //Compute the true quantized LPC L' with rounding error factored in L' = round(Lraw' + e)
-            let l_quantized = (l_raw + e).round();
+            let l_quantized = (l_raw + rounding_error).round();
             //update the rounding error 
-            e = e + (l_raw - l_quantized);
-            //push into vec as u32 since output is u32 
-            quantized.push(l_quantized as u32);
+            rounding_error += l_raw - l_quantized;
 
+            quantized.push((l_quantized as i64).clamp(q_min, q_max));
         }
 
         //If sf is negative, set the LPC shift to zero. Otherwise, leave sf as is.
-        if sf < 0 {
-            sf = 0;
-        }
-
-        return (quantized, sf as u32);
+        (quantized, sf.max(0) as u32)
     }
 
     /// Compute the residuals from a given linear predictor
     /// 
     /// The residuals are computed with the provided quantized coefficients
-    /// `qlp_coefs` and shift factor `qlp_shift`.
-    pub fn get_residuals(data: &Vec <i32>, qlp_coefs: &Vec <u32>, predictor_order: u32, qlp_shift: u32) -> Option <Vec <i32>> {
-        todo!()
+    /// `qlp_coefs` and shift factor `qlp_shift`. Similar to the fixed predictors,
+    /// the first `predictor_order` samples are warm-up samples and have no residuals.
+    /// 
+    /// # Errors
+    /// Returns `None` if there are fewer coefficients than the predictor order,
+    /// if `data` is not longer than the predictor order or if some residual does
+    /// not fit the range allowed by FLAC.
+    pub fn get_residuals(data: &[i64], qlp_coefs: &[i64], predictor_order: u32, qlp_shift: u32) -> Option <Vec <i64>> {
+        let order = predictor_order as usize;
+        if qlp_coefs.len() < order || data.len() <= order {
+            return None;
+        }
+
+        let mut residuals = Vec::with_capacity(data.len() - order);
+        for i in order..data.len() {
+            let mut prediction: i64 = 0;
+            for j in 0..order {
+                prediction += qlp_coefs[j] * data[i - j - 1];
+            }
+            let residual = data[i] - (prediction >> qlp_shift);
+            if !is_residual_in_range(residual) {
+                return None;
+            }
+            residuals.push(residual);
+        }
+
+        Some(residuals)
     }
 
     /// Get the best coefficient precision
@@ -176,58 +196,28 @@ impl VarPredictor {
     /// |   > 16    |    1152    |          13             |
     /// |   > 16    |     any    |          14             |
     pub fn get_best_precision(bps: u32, block_size: u32) -> u32 {
-        
         //bps == bit depth 
-       if bps < 16 {
-            if (2+ (bps/2)) > 1{
-                return (2 + (bps/2)) as u32;
-            }
-            else{
-                return 1 as u32;
-            }
-       }
-       else if bps == 16{
-            match block_size{
-                192 => {
-                    return 7 as u32;
-                }
-                384 => {
-                    return 8 as u32;
-                }
-                576 =>{
-                    return 9 as u32;
-                }
-                1152 =>{
-                    return 10 as u32;
-                }
-                2304 =>{
-                    return 11 as u32;
-                }
-                4608 =>{
-                    return 12 as u32;
-                }
-                _anyval =>{
-                    return 13 as u32;
-                }
+        if bps < 16 {
+            (2 + bps / 2).max(1)
+        }
+        else if bps == 16 {
+            match block_size {
+                192 => 7,
+                384 => 8,
+                576 => 9,
+                1152 => 10,
+                2304 => 11,
+                4608 => 12,
+                _ => 13,
             }
-       }
-       else if bps > 16{
-            match block_size{
-                384 => {
-                    return 12 as u32;
-                }
-                1152 => {
-                    return 13 as u32;
-                }
-                _anyval => {
-                    return 14 as u32;
-                }
-
+        }
+        else {
+            match block_size {
+                384 => 12,
+                1152 => 13,
+                _ => 14,
             }
-       }
-       else { //invalud
-            return 16 as u32;
-       }
+        }
     }
 }
 
@@ -239,7 +229,7 @@ mod tests {
     fn sample_01() {
         //let in_val = 0;
         let out_val_ans = 12;
-        let out_val = get_best_precision(17, 384);
+        let out_val = VarPredictor::get_best_precision(17, 384);
 
         assert_eq!(out_val_ans, out_val);
     }
@@ -248,7 +238,7 @@ mod tests {
     fn sample_02() {
         //let in_val = 0x164;
         let out_val_ans = 6;
-        let out_val = get_best_precision(8, 1152);
+        let out_val = VarPredictor::get_best_precision(8, 1152);
 
         assert_eq!(out_val_ans, out_val);
     }
@@ -257,7 +247,7 @@ mod tests {
     fn sample_03() {
         //let in_val = 0x164;
         let out_val_ans = 11;
-        let out_val = get_best_precision(16, 2304);
+        let out_val = VarPredictor::get_best_precision(16, 2304);
 
         assert_eq!(out_val_ans, out_val);
     }
@@ -265,12 +255,12 @@ mod tests {
     #[test] //quantized 
     fn sample_04() {
 
-        let mut in_val = vec!{ 1.27123, -0.85145, 0.28488};
-        let pb = 6 as u32;
-        let (out_val,sf) = quantize_coeffs(&in_val, pb);
+        let in_val = vec!{ 1.27123, -0.85145, 0.28488};
+        let pb: u32 = 6;
+        let (out_val,sf) = VarPredictor::quantize_coeffs(&in_val, pb);
         
         assert_eq!(out_val[0], 20);
-        assert_eq!(out_val[1], 13);
+        assert_eq!(out_val[1], -13);
         assert_eq!(out_val[2], 4);
         assert_eq!(sf, 4);
     }
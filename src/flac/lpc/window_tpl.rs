@@ -0,0 +1,371 @@
+use std::f64::consts::PI;
+
+/// Represents an apodization window applied to a block of samples before
+/// computing its autocorrelation
+///
+/// Tapering the edges of a block makes the LPC coefficients less sensitive to the
+/// samples at the block boundaries. Each window is defined as in the reference
+/// encoder. `PartialTukey` and `PunchoutTukey` windows only cover the part of the
+/// block from `start` until `end`, given as fractions of the block size, and are
+/// usually created in groups through `ApodizationWindow::parse_spec`.
+#[derive(Clone, Debug, PartialEq)]
+pub enum ApodizationWindow {
+    Bartlett,
+    BartlettHann,
+    Blackman,
+    BlackmanHarris4Term92Db,
+    Connes,
+    Flattop,
+    Gauss {stddev: f64},
+    Hamming,
+    Hann,
+    KaiserBessel,
+    Nuttall,
+    Rectangle,
+    Triangle,
+    Tukey {p: f64},
+    PartialTukey {p: f64, start: f64, end: f64},
+    PunchoutTukey {p: f64, start: f64, end: f64},
+    Welch,
+}
+
+impl ApodizationWindow {
+    /// Parse a list of windows separated by semicolons
+    ///
+    /// The syntax follows the `-A` option of the reference encoder, for example
+    /// `tukey(0.5);partial_tukey(2);punchout_tukey(3)`. The windows are `bartlett`,
+    /// `bartlett_hann`, `blackman`, `blackman_harris_4term_92db`, `connes`, `flattop`,
+    /// `gauss(STDDEV)`, `hamming`, `hann`, `kaiser_bessel`, `nuttall`, `rectangle`,
+    /// `triangle`, `tukey(P)`, `partial_tukey(n[/ov[/P]])`, `punchout_tukey(n[/ov[/P]])`
+    /// and `welch`.
+    ///
+    /// `partial_tukey(n)` and `punchout_tukey(n)` expand into `n` windows that each
+    /// keep, respectively remove, one of `n` overlapping parts of the block. The
+    /// overlap `ov` is a fraction of a part and defaults to 0.1 and 0.2, respectively,
+    /// while the taper `P` defaults to 0.2. A single part is the same as `tukey(P)`.
+    ///
+    /// # Errors
+    /// Returns `None` if the list is empty, a window is unknown, or a parameter
+    /// is missing or out of range.
+    pub fn parse_spec(spec: &str) -> Option <Vec <Self>> {
+        let mut windows = Vec::new();
+        for window_spec in spec.split(';').map(|s| s.trim()).filter(|s| !s.is_empty()) {
+            let (name, args) = match window_spec.find('(') {
+                Some(pos) => {
+                    let args = window_spec[pos + 1..].strip_suffix(')')?;
+                    let args: Option <Vec <f64>> = args.split('/').map(|arg| arg.trim().parse().ok()).collect();
+                    (&window_spec[..pos], args?)
+                },
+                None => (window_spec, Vec::new()),
+            };
+
+            match (name, args.as_slice()) {
+                ("bartlett", []) => windows.push(ApodizationWindow::Bartlett),
+                ("bartlett_hann", []) => windows.push(ApodizationWindow::BartlettHann),
+                ("blackman", []) => windows.push(ApodizationWindow::Blackman),
+                ("blackman_harris_4term_92db", []) => windows.push(ApodizationWindow::BlackmanHarris4Term92Db),
+                ("connes", []) => windows.push(ApodizationWindow::Connes),
+                ("flattop", []) => windows.push(ApodizationWindow::Flattop),
+                ("gauss", [stddev]) if *stddev > 0.0 && *stddev <= 0.5 => {
+                    windows.push(ApodizationWindow::Gauss {stddev: *stddev});
+                },
+                ("hamming", []) => windows.push(ApodizationWindow::Hamming),
+                ("hann", []) => windows.push(ApodizationWindow::Hann),
+                ("kaiser_bessel", []) => windows.push(ApodizationWindow::KaiserBessel),
+                ("nuttall", []) => windows.push(ApodizationWindow::Nuttall),
+                ("rectangle", []) => windows.push(ApodizationWindow::Rectangle),
+                ("triangle", []) => windows.push(ApodizationWindow::Triangle),
+                ("tukey", [p]) if (0.0..=1.0).contains(p) => {
+                    windows.push(ApodizationWindow::Tukey {p: *p});
+                },
+                ("partial_tukey", args) | ("punchout_tukey", args) if !args.is_empty() && args.len() <= 3 => {
+                    let is_partial = name == "partial_tukey";
+                    let num_parts = args[0];
+                    let overlap = args.get(1).copied().unwrap_or(if is_partial { 0.1 } else { 0.2 });
+                    let p = args.get(2).copied().unwrap_or(0.2);
+                    if !(1.0..=32.0).contains(&num_parts) || num_parts.fract() != 0.0
+                        || !(0.0..1.0).contains(&overlap) || !(0.0..=1.0).contains(&p) {
+                        return None;
+                    }
+                    windows.extend(Self::tukey_parts(num_parts as u32, overlap, p, is_partial));
+                },
+                ("welch", []) => windows.push(ApodizationWindow::Welch),
+                _ => return None,
+            }
+        }
+
+        if windows.is_empty() {
+            None
+        } else {
+            Some(windows)
+        }
+    }
+
+    /// Split a block into `num_parts` overlapping parts and create a partial or
+    /// punchout Tukey window for each part
+    fn tukey_parts(num_parts: u32, overlap: f64, p: f64, is_partial: bool) -> Vec <Self> {
+        if num_parts <= 1 {
+            return vec![ApodizationWindow::Tukey {p}];
+        }
+
+        let overlap_units = 1.0 / (1.0 - overlap) - 1.0;
+        let total_units = num_parts as f64 + overlap_units;
+        (0..num_parts)
+            .map(|m| {
+                let start = m as f64 / total_units;
+                let end = (m as f64 + 1.0 + overlap_units) / total_units;
+                if is_partial {
+                    ApodizationWindow::PartialTukey {p, start, end}
+                } else {
+                    ApodizationWindow::PunchoutTukey {p, start, end}
+                }
+            })
+            .collect()
+    }
+
+    /// Compute the weight of each of the `len` samples of a block
+    pub fn weights(&self, len: usize) -> Vec <f64> {
+        if len <= 1 {
+            return vec![1.0; len];
+        }
+
+        let n_max = (len - 1) as f64;
+        // Generalized cosine windows `a0 - a1 cos(2 pi n / N) + a2 cos(4 pi n / N) - ...`
+        let cosine_sum = |coefs: &[f64]| -> Vec <f64> {
+            (0..len)
+                .map(|n| {
+                    coefs.iter().enumerate().fold(0.0, |sum, (k, coef)| {
+                        let sign = if k % 2 == 0 { 1.0 } else { -1.0 };
+                        sum + sign * coef * (2.0 * PI * k as f64 * n as f64 / n_max).cos()
+                    })
+                })
+                .collect()
+        };
+
+        match self {
+            ApodizationWindow::Bartlett => (0..len)
+                .map(|n| {
+                    let is_rising = if len % 2 == 1 { n <= (len - 1) / 2 } else { n < len / 2 };
+                    if is_rising {
+                        2.0 * n as f64 / n_max
+                    } else {
+                        2.0 - 2.0 * n as f64 / n_max
+                    }
+                })
+                .collect(),
+            ApodizationWindow::BartlettHann => (0..len)
+                .map(|n| {
+                    let x = n as f64 / n_max;
+                    0.62 - 0.48 * (x - 0.5).abs() - 0.38 * (2.0 * PI * x).cos()
+                })
+                .collect(),
+            ApodizationWindow::Blackman => cosine_sum(&[0.42, 0.5, 0.08]),
+            ApodizationWindow::BlackmanHarris4Term92Db => cosine_sum(&[0.35875, 0.48829, 0.14128, 0.01168]),
+            ApodizationWindow::Connes => (0..len)
+                .map(|n| {
+                    let k = (n as f64 - n_max / 2.0) / (n_max / 2.0);
+                    (1.0 - k * k).powi(2)
+                })
+                .collect(),
+            ApodizationWindow::Flattop => cosine_sum(&[0.21557895, 0.41663158, 0.277263158, 0.083578947, 0.006947368]),
+            ApodizationWindow::Gauss {stddev} => (0..len)
+                .map(|n| {
+                    let k = (n as f64 - n_max / 2.0) / (stddev * n_max / 2.0);
+                    (-0.5 * k * k).exp()
+                })
+                .collect(),
+            ApodizationWindow::Hamming => cosine_sum(&[0.54, 0.46]),
+            ApodizationWindow::Hann => cosine_sum(&[0.5, 0.5]),
+            ApodizationWindow::KaiserBessel => cosine_sum(&[0.402, 0.498, 0.098, 0.001]),
+            ApodizationWindow::Nuttall => cosine_sum(&[0.3635819, 0.4891775, 0.1365995, 0.0106411]),
+            ApodizationWindow::Rectangle => vec![1.0; len],
+            ApodizationWindow::Triangle => (1..=len)
+                .map(|n| {
+                    if n <= len.div_ceil(2) {
+                        2.0 * n as f64 / (len as f64 + 1.0)
+                    } else {
+                        2.0 * (len - n + 1) as f64 / (len as f64 + 1.0)
+                    }
+                })
+                .collect(),
+            ApodizationWindow::Tukey {p} => Self::tukey_weights(len, *p),
+            ApodizationWindow::PartialTukey {p, start, end} => Self::partial_tukey_weights(len, *p, *start, *end),
+            ApodizationWindow::PunchoutTukey {p, start, end} => Self::punchout_tukey_weights(len, *p, *start, *end),
+            ApodizationWindow::Welch => (0..len)
+                .map(|n| {
+                    let k = (n as f64 - n_max / 2.0) / (n_max / 2.0);
+                    1.0 - k * k
+                })
+                .collect(),
+        }
+    }
+
+    /// Compute the rising half of a Hann window of `taper_len` samples
+    /// at position `i`, where `i` runs from 1 to `taper_len`
+    fn taper(i: usize, taper_len: usize) -> f64 {
+        0.5 - 0.5 * (PI * i as f64 / taper_len as f64).cos()
+    }
+
+    /// Compute a Tukey window, i.e. a rectangle whose `p / 2` edges on each
+    /// side are tapered like a Hann window
+    fn tukey_weights(len: usize, p: f64) -> Vec <f64> {
+        if p <= 0.0 {
+            return ApodizationWindow::Rectangle.weights(len);
+        }
+        if p >= 1.0 {
+            return ApodizationWindow::Hann.weights(len);
+        }
+
+        let mut weights = vec![1.0; len];
+        let taper_len = (p / 2.0 * len as f64) as i64 - 1;
+        if taper_len > 0 {
+            let taper_len = taper_len as usize;
+            for n in 0..=taper_len {
+                weights[n] = 0.5 - 0.5 * (PI * n as f64 / taper_len as f64).cos();
+                weights[len - taper_len - 1 + n] = 0.5 - 0.5 * (PI * (n + taper_len) as f64 / taper_len as f64).cos();
+            }
+        }
+        weights
+    }
+
+    /// Compute a Tukey window over the part of the block from `start` until `end`,
+    /// with the rest of the block set to zero
+    fn partial_tukey_weights(len: usize, p: f64, start: f64, end: f64) -> Vec <f64> {
+        let start_n = (start * len as f64) as usize;
+        let end_n = ((end * len as f64) as usize).min(len);
+        let part_len = end_n.saturating_sub(start_n);
+        let taper_len = if p <= 0.0 {
+            0
+        } else if p >= 1.0 {
+            part_len
+        } else {
+            (p / 2.0 * part_len as f64) as usize
+        };
+
+        let mut weights = vec![0.0; len];
+        for (n, weight) in weights.iter_mut().enumerate().take(end_n).skip(start_n) {
+            let from_start = n - start_n + 1;
+            let from_end = end_n - n;
+            *weight = if from_start <= taper_len {
+                Self::taper(from_start, taper_len)
+            } else if from_end <= taper_len {
+                Self::taper(from_end, taper_len)
+            } else {
+                1.0
+            };
+        }
+        weights
+    }
+
+    /// Compute a window that removes the part of the block from `start` until `end`,
+    /// with the remaining parts on each side tapered like a Tukey window
+    fn punchout_tukey_weights(len: usize, p: f64, start: f64, end: f64) -> Vec <f64> {
+        let start_n = ((start * len as f64) as usize).min(len);
+        let end_n = ((end * len as f64) as usize).min(len);
+        let (start_taper_len, end_taper_len) = if p <= 0.0 {
+            (0, 0)
+        } else if p >= 1.0 {
+            (start_n, len - end_n)
+        } else {
+            ((p / 2.0 * start_n as f64) as usize, (p / 2.0 * (len - end_n) as f64) as usize)
+        };
+
+        let mut weights = vec![0.0; len];
+        for (n, weight) in weights.iter_mut().enumerate().take(start_n) {
+            let from_start = n + 1;
+            let from_end = start_n - n;
+            *weight = if from_start <= start_taper_len {
+                Self::taper(from_start, start_taper_len)
+            } else if from_end <= start_taper_len {
+                Self::taper(from_end, start_taper_len)
+            } else {
+                1.0
+            };
+        }
+        for (n, weight) in weights.iter_mut().enumerate().skip(end_n) {
+            let from_start = n - end_n + 1;
+            let from_end = len - n;
+            *weight = if from_start <= end_taper_len {
+                Self::taper(from_start, end_taper_len)
+            } else if from_end <= end_taper_len {
+                Self::taper(from_end, end_taper_len)
+            } else {
+                1.0
+            };
+        }
+        weights
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    macro_rules! parse_spec_tests {
+        ($($name:ident: $value:expr,)*) => {
+        $(
+            #[test]
+            fn $name() {
+                let (spec, expected): (&str, Option <Vec <ApodizationWindow>>) = $value;
+                assert_eq!(ApodizationWindow::parse_spec(spec), expected);
+            }
+        )*
+        }
+    }
+
+    parse_spec_tests! {
+        parse_single: ("hann", Some(vec![ApodizationWindow::Hann])),
+        parse_tukey: ("tukey(5e-1)", Some(vec![ApodizationWindow::Tukey {p: 0.5}])),
+        parse_list: ("welch; gauss(0.2);rectangle", Some(vec![
+            ApodizationWindow::Welch,
+            ApodizationWindow::Gauss {stddev: 0.2},
+            ApodizationWindow::Rectangle,
+        ])),
+        parse_partial_tukey: ("partial_tukey(2/0/0.5)", Some(vec![
+            ApodizationWindow::PartialTukey {p: 0.5, start: 0.0, end: 0.5},
+            ApodizationWindow::PartialTukey {p: 0.5, start: 0.5, end: 1.0},
+        ])),
+        parse_single_part: ("punchout_tukey(1)", Some(vec![ApodizationWindow::Tukey {p: 0.2}])),
+        parse_unknown: ("tukey(0.5);sinc", None),
+        parse_missing_parameter: ("tukey", None),
+        parse_out_of_range: ("gauss(0.7)", None),
+        parse_unclosed: ("tukey(0.5", None),
+        parse_empty: ("", None),
+    }
+
+    #[test]
+    fn expand_tukey_parts() {
+        let windows = ApodizationWindow::parse_spec("tukey(0.5);partial_tukey(2);punchout_tukey(3)").unwrap();
+        assert_eq!(windows.len(), 6);
+    }
+
+    #[test]
+    fn hann_is_symmetric() {
+        let weights = ApodizationWindow::Hann.weights(9);
+        assert!(weights[0].abs() < 1e-12);
+        assert!((weights[4] - 1.0).abs() < 1e-12);
+        for n in 0..9 {
+            assert!((weights[n] - weights[8 - n]).abs() < 1e-12);
+        }
+    }
+
+    #[test]
+    fn tukey_limits() {
+        assert_eq!(ApodizationWindow::Tukey {p: 0.0}.weights(64), ApodizationWindow::Rectangle.weights(64));
+        assert_eq!(ApodizationWindow::Tukey {p: 1.0}.weights(64), ApodizationWindow::Hann.weights(64));
+
+        let weights = ApodizationWindow::Tukey {p: 0.5}.weights(64);
+        assert!(weights[0].abs() < 1e-12);
+        assert_eq!(weights[16..48], [1.0; 32]);
+    }
+
+    #[test]
+    fn partial_and_punchout_tukey_are_complementary() {
+        let partial = ApodizationWindow::PartialTukey {p: 0.0, start: 0.25, end: 0.5}.weights(64);
+        let punchout = ApodizationWindow::PunchoutTukey {p: 0.0, start: 0.25, end: 0.5}.weights(64);
+        for n in 0..64 {
+            assert_eq!(partial[n] + punchout[n], 1.0);
+        }
+        assert_eq!(partial[16..32], [1.0; 16]);
+    }
+}
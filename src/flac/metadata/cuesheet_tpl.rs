@@ -0,0 +1,575 @@
+use std::fs;
+
+use crate::flac::{FlacStreamInfo, FlacWriterError};
+
+/// Number of CD frames per second, the unit of `MM:SS:FF` times in cue sheets
+const CD_FRAMES_PER_SECOND: u64 = 75;
+
+/// Number of samples in a CD sector, which is a CD frame at 44.1 kHz
+const CD_SECTOR_SAMPLES: u64 = 588;
+
+/// Number of samples before the first track of a CD, which is 2 seconds at 44.1 kHz
+const CD_LEAD_IN_SAMPLES: u64 = 88200;
+
+/// Track number of the lead-out track of a CD
+const CD_LEAD_OUT_NUMBER: u8 = 170;
+
+/// Track number of the lead-out track of other media
+const LEAD_OUT_NUMBER: u8 = 255;
+
+/// Length of the media catalog number field in bytes
+const CATALOG_LEN: usize = 128;
+
+/// Length of the ISRC field of a track in bytes
+const ISRC_LEN: usize = 12;
+
+/// Represents a cue sheet read from a text `.cue` file
+///
+/// Times are kept in CD frames of 1/75 second as written in the sheet, so the
+/// cue sheet does not depend on the sample rate until it is converted into a
+/// CUESHEET block with `to_flac_cuesheet`.
+#[derive(Clone, Debug, PartialEq)]
+pub struct CueSheet {
+    pub catalog: Option <String>,
+    pub tracks: Vec <CueTrack>,
+}
+
+/// Represents a track of a text cue sheet
+///
+/// Each index is kept with its number, its time in CD frames from the start of
+/// the file, and the line of the sheet it was read from. The `pregap` is the
+/// length in CD frames of the silence that precedes the track but is not
+/// part of the file.
+#[derive(Clone, Debug, PartialEq)]
+pub struct CueTrack {
+    pub number: u8,
+    pub is_audio: bool,
+    pub isrc: Option <String>,
+    pub pre_emphasis: bool,
+    pub pregap: Option <u64>,
+    pub indices: Vec <CueIndex>,
+}
+
+/// Represents an index of a track of a text cue sheet
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct CueIndex {
+    pub number: u8,
+    pub frames: u64,
+    pub line: usize,
+}
+
+/// Represents the CUESHEET metadata block of a FLAC file
+///
+/// The offsets of the tracks are in samples from the start of the stream, and
+/// the offsets of the indices are in samples from the start of their track.
+/// The last track is the lead-out track, which starts right after the last
+/// sample and has no index.
+#[derive(Clone, Debug, PartialEq)]
+pub struct FlacCueSheet {
+    pub media_catalog_number: String,
+    pub lead_in_samples: u64,
+    pub is_cd: bool,
+    pub tracks: Vec <FlacCueSheetTrack>,
+}
+
+/// Represents a track of a CUESHEET block
+#[derive(Clone, Debug, PartialEq)]
+pub struct FlacCueSheetTrack {
+    pub offset: u64,
+    pub number: u8,
+    pub isrc: String,
+    pub is_audio: bool,
+    pub pre_emphasis: bool,
+    pub indices: Vec <FlacCueSheetIndex>,
+}
+
+/// Represents an index of a track of a CUESHEET block
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct FlacCueSheetIndex {
+    pub offset: u64,
+    pub number: u8,
+}
+
+impl CueSheet {
+    /// Read a cue sheet from a text `.cue` file
+    ///
+    /// # Errors
+    /// Returns `FlacWriterError::ReadError` if the file cannot be read as text, or
+    /// the error returned by `parse`.
+    pub fn from_file(file_path: &str) -> Result <Self, FlacWriterError> {
+        let text = fs::read_to_string(file_path).map_err(|_| FlacWriterError::ReadError)?;
+        Self::parse(&text)
+    }
+
+    /// Parse the text of a cue sheet describing a single file
+    ///
+    /// The `FILE`, `TRACK`, `INDEX`, `ISRC`, `CATALOG`, `PREGAP` and `FLAGS` commands
+    /// are read, and other commands such as `TITLE`, `PERFORMER` or `REM` are skipped.
+    /// Of the flags, only `PRE` (pre-emphasis) is kept. Commands are case-insensitive.
+    ///
+    /// # Errors
+    /// Returns `FlacWriterError::InvalidCueSheetError` with the 1-based number of the
+    /// offending line if a command is malformed or out of place, there is more than
+    /// one `FILE`, track numbers are not increasing, the indices of a track do not
+    /// start at 0 or 1 and count up by one, a track has no index 1, or an index
+    /// comes before the previous one. A sheet without tracks fails on its last line.
+    pub fn parse(text: &str) -> Result <Self, FlacWriterError> {
+        let mut catalog = None;
+        let mut tracks: Vec <CueTrack> = Vec::new();
+        let mut has_file = false;
+        let mut last_frames = 0;
+        let mut num_lines = 0;
+
+        for (i, line) in text.lines().enumerate() {
+            let line_number = i + 1;
+            num_lines = line_number;
+            let error = || FlacWriterError::InvalidCueSheetError {line: line_number};
+            let fields = Self::split_fields(line).ok_or_else(error)?;
+            let command = match fields.first() {
+                Some(command) => command.to_ascii_uppercase(),
+                None => continue,
+            };
+            let args = &fields[1..];
+
+            match command.as_str() {
+                "CATALOG" => {
+                    let is_valid = args.len() == 1 && args[0].len() == 13 && args[0].bytes().all(|c| c.is_ascii_digit());
+                    if !is_valid || catalog.is_some() || !tracks.is_empty() {
+                        return Err(error());
+                    }
+                    catalog = Some(args[0].clone());
+                },
+                "FILE" => {
+                    if args.len() != 2 || has_file {
+                        return Err(error());
+                    }
+                    has_file = true;
+                },
+                "TRACK" => {
+                    let number = args.first().and_then(|number| number.parse::<u8>().ok()).ok_or_else(error)?;
+                    let is_audio = match args.get(1).map(|mode| mode.to_ascii_uppercase()) {
+                        Some(mode) if args.len() == 2 => mode == "AUDIO",
+                        _ => return Err(error()),
+                    };
+                    let is_increasing = tracks.last().is_none_or(|track| track.number < number);
+                    if !has_file || !(1..=99).contains(&number) || !is_increasing {
+                        return Err(error());
+                    }
+                    if tracks.last().is_some_and(|track| !track.has_index_1()) {
+                        return Err(error());
+                    }
+                    tracks.push(CueTrack {
+                        number,
+                        is_audio,
+                        isrc: None,
+                        pre_emphasis: false,
+                        pregap: None,
+                        indices: Vec::new(),
+                    });
+                },
+                "INDEX" => {
+                    let is_first_track = tracks.len() == 1;
+                    let track = tracks.last_mut().ok_or_else(error)?;
+                    let number = args.first().and_then(|number| number.parse::<u8>().ok());
+                    let frames = args.get(1).and_then(|time| Self::parse_time(time));
+                    let (number, frames) = match (number, frames) {
+                        (Some(number), Some(frames)) if args.len() == 2 && number <= 99 => (number, frames),
+                        _ => return Err(error()),
+                    };
+                    let expected_number = match track.indices.last() {
+                        Some(index) => index.number + 1,
+                        None if number == 0 => 0,
+                        None => 1,
+                    };
+                    let is_in_order = frames > last_frames || (frames == 0 && is_first_track && track.indices.is_empty());
+                    if number != expected_number || !is_in_order {
+                        return Err(error());
+                    }
+                    track.indices.push(CueIndex {
+                        number,
+                        frames,
+                        line: line_number,
+                    });
+                    last_frames = frames;
+                },
+                "ISRC" => {
+                    let track = tracks.last_mut().ok_or_else(error)?;
+                    let is_valid = args.len() == 1 && args[0].len() == ISRC_LEN && args[0].bytes().all(|c| c.is_ascii_alphanumeric());
+                    if !is_valid || track.isrc.is_some() || !track.indices.is_empty() {
+                        return Err(error());
+                    }
+                    track.isrc = Some(args[0].to_ascii_uppercase());
+                },
+                "PREGAP" => {
+                    let track = tracks.last_mut().ok_or_else(error)?;
+                    let frames = args.first().and_then(|time| Self::parse_time(time));
+                    match frames {
+                        Some(frames) if args.len() == 1 && track.pregap.is_none() && track.indices.is_empty() => {
+                            track.pregap = Some(frames);
+                        },
+                        _ => return Err(error()),
+                    }
+                },
+                "FLAGS" => {
+                    let track = tracks.last_mut().ok_or_else(error)?;
+                    if args.is_empty() || !track.indices.is_empty() {
+                        return Err(error());
+                    }
+                    track.pre_emphasis = args.iter().any(|flag| flag.eq_ignore_ascii_case("PRE"));
+                },
+                _ => (),
+            }
+        }
+
+        let last_track = tracks.last().ok_or(FlacWriterError::InvalidCueSheetError {line: num_lines})?;
+        if !last_track.has_index_1() {
+            return Err(FlacWriterError::InvalidCueSheetError {line: num_lines});
+        }
+
+        Ok(CueSheet {
+            catalog,
+            tracks,
+        })
+    }
+
+    /// Convert this cue sheet into a CUESHEET block for a stream
+    ///
+    /// The stream is treated as a CD if it has 2 channels of 16-bit samples at
+    /// 44.1 kHz, in which case it gets a lead-in of 2 seconds and a lead-out track
+    /// numbered 170 instead of 255. Each track starts at its first index. The pregaps
+    /// are not part of the stream, so they do not move any offset.
+    ///
+    /// # Errors
+    /// Returns `FlacWriterError::InvalidCueSheetError` with the line of the first
+    /// index that does not fall before the last sample of the `total_samples`
+    /// inter-channel samples of the stream, and `FlacWriterError::InvalidFormatError`
+    /// if the stream is treated as a CD but is not made of whole CD sectors of
+    /// 588 samples.
+    pub fn to_flac_cuesheet(&self, streaminfo: &FlacStreamInfo, total_samples: u64) -> Result <FlacCueSheet, FlacWriterError> {
+        let is_cd = streaminfo.sample_rate == 44100 && streaminfo.bit_depth == 16 && streaminfo.num_channels == 2;
+        let to_samples = |frames: u64| frames * streaminfo.sample_rate as u64 / CD_FRAMES_PER_SECOND;
+
+        let mut tracks = Vec::with_capacity(self.tracks.len() + 1);
+        for track in self.tracks.iter() {
+            if let Some(index) = track.indices.iter().find(|index| to_samples(index.frames) >= total_samples) {
+                return Err(FlacWriterError::InvalidCueSheetError {line: index.line});
+            }
+
+            let offset = to_samples(track.indices[0].frames);
+            tracks.push(FlacCueSheetTrack {
+                offset,
+                number: track.number,
+                isrc: track.isrc.clone().unwrap_or_default(),
+                is_audio: track.is_audio,
+                pre_emphasis: track.pre_emphasis,
+                indices: track.indices.iter()
+                    .map(|index| FlacCueSheetIndex {
+                        offset: to_samples(index.frames) - offset,
+                        number: index.number,
+                    })
+                    .collect(),
+            });
+        }
+        if is_cd && !total_samples.is_multiple_of(CD_SECTOR_SAMPLES) {
+            return Err(FlacWriterError::InvalidFormatError);
+        }
+        tracks.push(FlacCueSheetTrack {
+            offset: total_samples,
+            number: if is_cd { CD_LEAD_OUT_NUMBER } else { LEAD_OUT_NUMBER },
+            isrc: String::new(),
+            is_audio: true,
+            pre_emphasis: false,
+            indices: Vec::new(),
+        });
+
+        Ok(FlacCueSheet {
+            media_catalog_number: self.catalog.clone().unwrap_or_default(),
+            lead_in_samples: if is_cd { CD_LEAD_IN_SAMPLES } else { 0 },
+            is_cd,
+            tracks,
+        })
+    }
+
+    /// Parse an `MM:SS:FF` time into a number of CD frames
+    ///
+    /// The minutes may take more than two digits, while the seconds are below 60
+    /// and the frames are below 75.
+    fn parse_time(time: &str) -> Option <u64> {
+        let parts: Vec <u64> = time.split(':')
+            .map(|part| part.parse::<u64>().ok())
+            .collect::<Option <_>>()?;
+        match parts[..] {
+            [minutes, seconds, frames] if seconds < 60 && frames < CD_FRAMES_PER_SECOND => {
+                Some((minutes * 60 + seconds) * CD_FRAMES_PER_SECOND + frames)
+            },
+            _ => None,
+        }
+    }
+
+    /// Split a line of a cue sheet into whitespace-separated fields
+    ///
+    /// A field in double quotes may contain whitespace, and the quotes are removed.
+    /// This method returns `None` if a quote is not closed.
+    fn split_fields(line: &str) -> Option <Vec <String>> {
+        let mut fields = Vec::new();
+        let mut rest = line.trim_start();
+        while !rest.is_empty() {
+            if let Some(quoted) = rest.strip_prefix('"') {
+                let end = quoted.find('"')?;
+                fields.push(quoted[..end].to_string());
+                rest = &quoted[end + 1..];
+            }
+            else {
+                let end = rest.find(char::is_whitespace).unwrap_or(rest.len());
+                fields.push(rest[..end].to_string());
+                rest = &rest[end..];
+            }
+            rest = rest.trim_start();
+        }
+        Some(fields)
+    }
+}
+
+impl CueTrack {
+    /// Check whether the track has an index 1, where the track itself starts
+    fn has_index_1(&self) -> bool {
+        self.indices.iter().any(|index| index.number == 1)
+    }
+}
+
+impl FlacCueSheet {
+    /// Convert this CUESHEET block into its byte representation
+    ///
+    /// The media catalog number and ISRCs are padded with NUL bytes to their field
+    /// lengths. The metadata block header is _not_ included.
+    pub fn build_bytes(&self) -> Vec <u8> {
+        let mut bytes = Vec::new();
+        bytes.extend_from_slice(&Self::padded(&self.media_catalog_number, CATALOG_LEN));
+        bytes.extend_from_slice(&self.lead_in_samples.to_be_bytes());
+        bytes.push((self.is_cd as u8) << 7);
+        bytes.extend_from_slice(&[0; 258]);
+        bytes.push(self.tracks.len() as u8);
+
+        for track in self.tracks.iter() {
+            bytes.extend_from_slice(&track.offset.to_be_bytes());
+            bytes.push(track.number);
+            bytes.extend_from_slice(&Self::padded(&track.isrc, ISRC_LEN));
+            bytes.push((!track.is_audio as u8) << 7 | (track.pre_emphasis as u8) << 6);
+            bytes.extend_from_slice(&[0; 13]);
+            bytes.push(track.indices.len() as u8);
+
+            for index in track.indices.iter() {
+                bytes.extend_from_slice(&index.offset.to_be_bytes());
+                bytes.push(index.number);
+                bytes.extend_from_slice(&[0; 3]);
+            }
+        }
+        bytes
+    }
+
+    /// Read a CUESHEET block from its contents
+    ///
+    /// # Errors
+    /// Returns `None` if the contents are truncated or the media catalog number
+    /// or an ISRC is not valid text.
+    pub fn from_bytes(data: &[u8]) -> Option <Self> {
+        let media_catalog_number = Self::unpadded(data.get(..CATALOG_LEN)?)?;
+        let lead_in_samples = u64::from_be_bytes(data.get(128..136)?.try_into().ok()?);
+        let is_cd = data.get(136)? & 0x80 != 0;
+        let num_tracks = *data.get(395)?;
+
+        let mut pos = 396;
+        let mut tracks = Vec::with_capacity(num_tracks as usize);
+        for _ in 0..num_tracks {
+            let track = data.get(pos..pos + 36)?;
+            let num_indices = track[35] as usize;
+            let indices = data.get(pos + 36..pos + 36 + 12 * num_indices)?
+                .chunks_exact(12)
+                .map(|index| FlacCueSheetIndex {
+                    offset: u64::from_be_bytes(index[..8].try_into().unwrap()),
+                    number: index[8],
+                })
+                .collect();
+
+            tracks.push(FlacCueSheetTrack {
+                offset: u64::from_be_bytes(track[..8].try_into().unwrap()),
+                number: track[8],
+                isrc: Self::unpadded(&track[9..21])?,
+                is_audio: track[21] & 0x80 == 0,
+                pre_emphasis: track[21] & 0x40 != 0,
+                indices,
+            });
+            pos += 36 + 12 * num_indices;
+        }
+
+        Some(FlacCueSheet {
+            media_catalog_number,
+            lead_in_samples,
+            is_cd,
+            tracks,
+        })
+    }
+
+    /// Pad a string with NUL bytes to `len` bytes
+    fn padded(string: &str, len: usize) -> Vec <u8> {
+        let mut bytes = string.as_bytes().to_vec();
+        bytes.resize(len, 0);
+        bytes
+    }
+
+    /// Read a string padded with NUL bytes
+    fn unpadded(bytes: &[u8]) -> Option <String> {
+        let len = bytes.iter().position(|byte| *byte == 0).unwrap_or(bytes.len());
+        String::from_utf8(bytes[..len].to_vec()).ok()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const SHEET: &str = "REM GENRE Test
+CATALOG 0123456789012
+PERFORMER \"Some Band\"
+FILE \"disc one.wav\" WAVE
+  TRACK 01 AUDIO
+    TITLE \"First\"
+    ISRC USABC2300001
+    INDEX 01 00:00:00
+  TRACK 02 AUDIO
+    FLAGS DCP PRE
+    PREGAP 00:02:00
+    INDEX 00 00:10:00
+    INDEX 01 00:12:37
+    INDEX 02 01:00:74
+  TRACK 03 MODE1/2352
+    INDEX 01 02:00:00
+";
+
+    fn cd_streaminfo() -> FlacStreamInfo {
+        FlacStreamInfo {
+            min_block_size: 4096,
+            max_block_size: 4096,
+            min_frame_size: 0,
+            max_frame_size: 0,
+            sample_rate: 44100,
+            num_channels: 2,
+            bit_depth: 16,
+            total_samples: 0,
+            md5: [0; 16],
+        }
+    }
+
+    #[test]
+    fn parse_sheet() {
+        let sheet = CueSheet::parse(SHEET).unwrap();
+
+        assert_eq!(sheet.catalog.as_deref(), Some("0123456789012"));
+        assert_eq!(sheet.tracks.len(), 3);
+        assert_eq!(sheet.tracks[0].isrc.as_deref(), Some("USABC2300001"));
+        assert!(!sheet.tracks[0].pre_emphasis);
+        assert!(sheet.tracks[1].pre_emphasis);
+        assert_eq!(sheet.tracks[1].pregap, Some(150));
+        assert_eq!(sheet.tracks[1].indices, vec![
+            CueIndex {number: 0, frames: 750, line: 12},
+            CueIndex {number: 1, frames: 937, line: 13},
+            CueIndex {number: 2, frames: 4574, line: 14},
+        ]);
+        assert!(!sheet.tracks[2].is_audio);
+    }
+
+    #[test]
+    fn cd_cuesheet() {
+        let sheet = CueSheet::parse(SHEET).unwrap();
+        let cuesheet = sheet.to_flac_cuesheet(&cd_streaminfo(), 9_998_352).unwrap();
+
+        assert!(cuesheet.is_cd);
+        assert_eq!(cuesheet.lead_in_samples, 88200);
+        assert_eq!(cuesheet.media_catalog_number, "0123456789012");
+
+        // Each CD frame is 588 samples, and tracks start at their first index
+        let offsets: Vec <(u8, u64)> = cuesheet.tracks.iter().map(|track| (track.number, track.offset)).collect();
+        assert_eq!(offsets, vec![(1, 0), (2, 750 * 588), (3, 9000 * 588), (170, 9_998_352)]);
+        assert_eq!(cuesheet.tracks[1].indices, vec![
+            FlacCueSheetIndex {offset: 0, number: 0},
+            FlacCueSheetIndex {offset: 187 * 588, number: 1},
+            FlacCueSheetIndex {offset: 3824 * 588, number: 2},
+        ]);
+        assert!(cuesheet.tracks[3].indices.is_empty());
+    }
+
+    #[test]
+    fn non_cd_cuesheet() {
+        let streaminfo = FlacStreamInfo {
+            sample_rate: 48000,
+            ..cd_streaminfo()
+        };
+        let cuesheet = CueSheet::parse(SHEET).unwrap().to_flac_cuesheet(&streaminfo, 9_998_352).unwrap();
+
+        assert!(!cuesheet.is_cd);
+        assert_eq!(cuesheet.lead_in_samples, 0);
+        assert_eq!(cuesheet.tracks[1].offset, 750 * 640);
+        assert_eq!(cuesheet.tracks.last().unwrap().number, 255);
+    }
+
+    #[test]
+    fn cd_partial_sector() {
+        // A CD image is made of whole sectors
+        let result = CueSheet::parse(SHEET).unwrap().to_flac_cuesheet(&cd_streaminfo(), 9_998_353);
+        assert!(matches!(result, Err(FlacWriterError::InvalidFormatError)));
+    }
+
+    #[test]
+    fn index_past_end() {
+        let sheet = CueSheet::parse(SHEET).unwrap();
+
+        // Track 3 starts at 5292000 samples, and index 2 of track 2 at 2689512
+        assert!(sheet.to_flac_cuesheet(&cd_streaminfo(), 5292000 + 588).is_ok());
+        assert!(matches!(sheet.to_flac_cuesheet(&cd_streaminfo(), 5292000), Err(FlacWriterError::InvalidCueSheetError {line: 16})));
+        assert!(matches!(sheet.to_flac_cuesheet(&cd_streaminfo(), 2689512), Err(FlacWriterError::InvalidCueSheetError {line: 14})));
+    }
+
+    #[test]
+    fn bytes_round_trip() {
+        let cuesheet = CueSheet::parse(SHEET).unwrap().to_flac_cuesheet(&cd_streaminfo(), 9_998_352).unwrap();
+        let bytes = cuesheet.build_bytes();
+
+        // 396 bytes of header, 36 bytes per track and 12 bytes per index
+        assert_eq!(bytes.len(), 396 + 4 * 36 + 5 * 12);
+        assert_eq!(&bytes[..13], b"0123456789012");
+        assert_eq!(bytes[136], 0x80);
+        assert_eq!(bytes[395], 4);
+        assert_eq!(FlacCueSheet::from_bytes(&bytes), Some(cuesheet));
+        assert_eq!(FlacCueSheet::from_bytes(&bytes[..bytes.len() - 1]), None);
+    }
+
+    macro_rules! invalid_sheet_tests {
+        ($($name:ident: $value:expr,)*) => {
+        $(
+            #[test]
+            fn $name() {
+                let (sheet, line) = $value;
+                assert!(matches!(CueSheet::parse(sheet), Err(FlacWriterError::InvalidCueSheetError {line: l}) if l == line));
+            }
+        )*
+        }
+    }
+
+    invalid_sheet_tests! {
+        invalid_no_tracks: ("FILE a.wav WAVE\n", 1),
+        invalid_track_before_file: ("TRACK 01 AUDIO\n", 1),
+        invalid_two_files: ("FILE a.wav WAVE\nFILE b.wav WAVE\n", 2),
+        invalid_unclosed_quote: ("FILE \"a.wav WAVE\n", 1),
+        invalid_track_number: ("FILE a.wav WAVE\nTRACK 00 AUDIO\n", 2),
+        invalid_track_order: ("FILE a.wav WAVE\nTRACK 02 AUDIO\nINDEX 01 00:00:00\nTRACK 01 AUDIO\n", 4),
+        invalid_missing_index_1: ("FILE a.wav WAVE\nTRACK 01 AUDIO\nINDEX 00 00:00:00\nTRACK 02 AUDIO\n", 4),
+        invalid_index_outside_track: ("FILE a.wav WAVE\nINDEX 01 00:00:00\n", 2),
+        invalid_index_number: ("FILE a.wav WAVE\nTRACK 01 AUDIO\nINDEX 02 00:00:00\n", 3),
+        invalid_index_time: ("FILE a.wav WAVE\nTRACK 01 AUDIO\nINDEX 01 00:00:75\n", 3),
+        invalid_index_seconds: ("FILE a.wav WAVE\nTRACK 01 AUDIO\nINDEX 01 00:60:00\n", 3),
+        invalid_index_backwards: ("FILE a.wav WAVE\nTRACK 01 AUDIO\nINDEX 01 00:05:00\nTRACK 02 AUDIO\nINDEX 01 00:04:00\n", 5),
+        invalid_isrc: ("FILE a.wav WAVE\nTRACK 01 AUDIO\nISRC US-ABC-23-00001\n", 3),
+        invalid_catalog: ("CATALOG 12345\n", 1),
+        invalid_pregap_after_index: ("FILE a.wav WAVE\nTRACK 01 AUDIO\nINDEX 01 00:00:00\nPREGAP 00:02:00\n", 4),
+    }
+}
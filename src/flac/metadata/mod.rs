@@ -0,0 +1,32 @@
+pub mod cuesheet;
+pub mod picture;
+pub mod seektable;
+pub mod vorbis_comment;
+
+/// Block type of the STREAMINFO metadata block
+pub const BLOCK_TYPE_STREAMINFO: u8 = 0;
+
+/// Block type of the PADDING metadata block
+pub const BLOCK_TYPE_PADDING: u8 = 1;
+
+/// Block type of the APPLICATION metadata block
+pub const BLOCK_TYPE_APPLICATION: u8 = 2;
+
+/// Block type of the SEEKTABLE metadata block
+pub const BLOCK_TYPE_SEEKTABLE: u8 = 3;
+
+/// Block type of the VORBIS_COMMENT metadata block
+pub const BLOCK_TYPE_VORBIS_COMMENT: u8 = 4;
+
+/// Block type of the CUESHEET metadata block
+pub const BLOCK_TYPE_CUESHEET: u8 = 5;
+
+/// Block type of the PICTURE metadata block
+pub const BLOCK_TYPE_PICTURE: u8 = 6;
+
+/// Application ID of the APPLICATION blocks holding the chunks of a RIFF file
+/// other than its samples, as used by the reference encoder
+pub const APPLICATION_ID_RIFF: &[u8; 4] = b"riff";
+
+/// Largest length of the contents of a metadata block, which is a 24-bit field
+pub const MAX_BLOCK_LENGTH: usize = (1 << 24) - 1;
@@ -0,0 +1,379 @@
+use std::fs;
+
+use crate::flac::FlacWriterError;
+
+/// Picture type of a 32x32 PNG file icon
+pub const PICTURE_TYPE_FILE_ICON: u32 = 1;
+
+/// Picture type of any other file icon
+pub const PICTURE_TYPE_OTHER_FILE_ICON: u32 = 2;
+
+/// Picture type of the front cover
+pub const PICTURE_TYPE_FRONT_COVER: u32 = 3;
+
+/// Picture type of the back cover
+pub const PICTURE_TYPE_BACK_COVER: u32 = 4;
+
+/// Largest picture type defined by the FLAC format
+pub const MAX_PICTURE_TYPE: u32 = 20;
+
+/// Represents the PICTURE metadata block of a FLAC file
+///
+/// The `picture_type` follows the ID3v2 APIC frame, e.g. 3 for the front cover.
+/// The `depth` is the number of bits per pixel, and `num_colors` is the number
+/// of colors of indexed images such as GIF, or 0 for other images.
+#[derive(Clone, Debug, PartialEq)]
+pub struct FlacPicture {
+    pub picture_type: u32,
+    pub mime_type: String,
+    pub description: String,
+    pub width: u32,
+    pub height: u32,
+    pub depth: u32,
+    pub num_colors: u32,
+    pub data: Vec <u8>,
+}
+
+/// Represents the format and dimensions read from the header of an image
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct ImageInfo {
+    pub mime_type: &'static str,
+    pub width: u32,
+    pub height: u32,
+    pub depth: u32,
+    pub num_colors: u32,
+}
+
+impl FlacPicture {
+    /// Create a PICTURE block from the contents of a PNG, JPEG or GIF image
+    ///
+    /// The MIME type and dimensions are read from the header of the image.
+    ///
+    /// # Errors
+    /// Returns `FlacWriterError::InvalidMetadataError` if the picture type is
+    /// larger than 20 or the image is not a PNG, JPEG or GIF image.
+    pub fn from_image(picture_type: u32, description: &str, data: Vec <u8>) -> Result <Self, FlacWriterError> {
+        let info = ImageInfo::read(&data).ok_or(FlacWriterError::InvalidMetadataError)?;
+        if picture_type > MAX_PICTURE_TYPE {
+            return Err(FlacWriterError::InvalidMetadataError);
+        }
+
+        Ok(FlacPicture {
+            picture_type,
+            mime_type: info.mime_type.to_string(),
+            description: description.to_string(),
+            width: info.width,
+            height: info.height,
+            depth: info.depth,
+            num_colors: info.num_colors,
+            data,
+        })
+    }
+
+    /// Create a PICTURE block from a PNG, JPEG or GIF file
+    ///
+    /// See `from_image` for the details.
+    ///
+    /// # Errors
+    /// Returns `FlacWriterError::ReadError` if the file cannot be read, and
+    /// `FlacWriterError::InvalidMetadataError` if it is not a supported image.
+    pub fn from_file(picture_type: u32, description: &str, file_path: &str) -> Result <Self, FlacWriterError> {
+        let data = fs::read(file_path).map_err(|_| FlacWriterError::ReadError)?;
+        Self::from_image(picture_type, description, data)
+    }
+
+    /// Get the usual file extension of the image, without the dot
+    pub fn extension(&self) -> &str {
+        match self.mime_type.as_str() {
+            "image/png" => "png",
+            "image/jpeg" | "image/jpg" => "jpg",
+            "image/gif" => "gif",
+            _ => "bin",
+        }
+    }
+
+    /// Convert this PICTURE block into its byte representation
+    ///
+    /// The metadata block header is _not_ included.
+    pub fn build_bytes(&self) -> Vec <u8> {
+        let mut bytes = Vec::with_capacity(32 + self.mime_type.len() + self.description.len() + self.data.len());
+        bytes.extend_from_slice(&self.picture_type.to_be_bytes());
+        bytes.extend_from_slice(&(self.mime_type.len() as u32).to_be_bytes());
+        bytes.extend_from_slice(self.mime_type.as_bytes());
+        bytes.extend_from_slice(&(self.description.len() as u32).to_be_bytes());
+        bytes.extend_from_slice(self.description.as_bytes());
+        bytes.extend_from_slice(&self.width.to_be_bytes());
+        bytes.extend_from_slice(&self.height.to_be_bytes());
+        bytes.extend_from_slice(&self.depth.to_be_bytes());
+        bytes.extend_from_slice(&self.num_colors.to_be_bytes());
+        bytes.extend_from_slice(&(self.data.len() as u32).to_be_bytes());
+        bytes.extend_from_slice(&self.data);
+        bytes
+    }
+
+    /// Read a PICTURE block from its contents
+    ///
+    /// # Errors
+    /// Returns `None` if the contents are truncated or the MIME type or the
+    /// description are not valid text.
+    pub fn from_bytes(data: &[u8]) -> Option <Self> {
+        let mut pos = 0;
+        let read_u32 = |pos: &mut usize| -> Option <u32> {
+            let value = u32::from_be_bytes(data.get(*pos..*pos + 4)?.try_into().ok()?);
+            *pos += 4;
+            Some(value)
+        };
+        let read_bytes = |pos: &mut usize| -> Option <&[u8]> {
+            let len = read_u32(pos)? as usize;
+            let bytes = data.get(*pos..*pos + len)?;
+            *pos += len;
+            Some(bytes)
+        };
+
+        let picture_type = read_u32(&mut pos)?;
+        let mime_type = String::from_utf8(read_bytes(&mut pos)?.to_vec()).ok()?;
+        let description = String::from_utf8(read_bytes(&mut pos)?.to_vec()).ok()?;
+        let width = read_u32(&mut pos)?;
+        let height = read_u32(&mut pos)?;
+        let depth = read_u32(&mut pos)?;
+        let num_colors = read_u32(&mut pos)?;
+        let picture_data = read_bytes(&mut pos)?.to_vec();
+
+        Some(FlacPicture {
+            picture_type,
+            mime_type,
+            description,
+            width,
+            height,
+            depth,
+            num_colors,
+            data: picture_data,
+        })
+    }
+}
+
+impl ImageInfo {
+    /// Read the format and dimensions of a PNG, JPEG or GIF image
+    ///
+    /// # Errors
+    /// Returns `None` if the image is in another format or its header is malformed.
+    pub fn read(data: &[u8]) -> Option <Self> {
+        if data.starts_with(b"\x89PNG\r\n\x1a\n") {
+            Self::read_png(data)
+        }
+        else if data.starts_with(&[0xff, 0xd8]) {
+            Self::read_jpeg(data)
+        }
+        else if data.starts_with(b"GIF87a") || data.starts_with(b"GIF89a") {
+            Self::read_gif(data)
+        }
+        else {
+            None
+        }
+    }
+
+    /// Read the IHDR chunk of a PNG image, and its PLTE chunk for indexed images
+    ///
+    /// The palette entries of indexed images always take up 24 bits.
+    fn read_png(data: &[u8]) -> Option <Self> {
+        let ihdr = data.get(8..33)?;
+        if &ihdr[4..8] != b"IHDR" {
+            return None;
+        }
+        let width = u32::from_be_bytes(ihdr[8..12].try_into().ok()?);
+        let height = u32::from_be_bytes(ihdr[12..16].try_into().ok()?);
+        let bit_depth = ihdr[16] as u32;
+        let (depth, num_colors) = match ihdr[17] {
+            0 => (bit_depth, 0),
+            2 => (3 * bit_depth, 0),
+            3 => (24, Self::read_png_palette_len(data)?),
+            4 => (2 * bit_depth, 0),
+            6 => (4 * bit_depth, 0),
+            _ => return None,
+        };
+
+        Some(ImageInfo {
+            mime_type: "image/png",
+            width,
+            height,
+            depth,
+            num_colors,
+        })
+    }
+
+    /// Get the number of entries of the PLTE chunk of a PNG image
+    fn read_png_palette_len(data: &[u8]) -> Option <u32> {
+        let mut pos = 8;
+        loop {
+            let chunk_len = u32::from_be_bytes(data.get(pos..pos + 4)?.try_into().ok()?);
+            let chunk_type = data.get(pos + 4..pos + 8)?;
+            if chunk_type == b"PLTE" {
+                return Some(chunk_len / 3);
+            }
+            if chunk_type == b"IDAT" {
+                return None;
+            }
+            // Each chunk is followed by its 4-byte CRC
+            pos += 12 + chunk_len as usize;
+        }
+    }
+
+    /// Read the first start-of-frame segment of a JPEG image
+    ///
+    /// The segments before it are skipped using their lengths.
+    fn read_jpeg(data: &[u8]) -> Option <Self> {
+        let mut pos = 2;
+        loop {
+            if *data.get(pos)? != 0xff {
+                return None;
+            }
+            let marker = *data.get(pos + 1)?;
+            match marker {
+                // Fill bytes before a marker
+                0xff => {
+                    pos += 1;
+                    continue;
+                },
+                // Markers without a segment
+                0x01 | 0xd0..=0xd7 => {
+                    pos += 2;
+                    continue;
+                },
+                // The image data starts before any start-of-frame segment
+                0xd9 | 0xda => return None,
+                _ => (),
+            }
+
+            let segment = data.get(pos + 2..)?;
+            let segment_len = u16::from_be_bytes(segment.get(0..2)?.try_into().ok()?) as usize;
+            // Start-of-frame markers, except DHT, JPG and DAC which share the range
+            if (0xc0..=0xcf).contains(&marker) && ![0xc4, 0xc8, 0xcc].contains(&marker) {
+                let precision = *segment.get(2)? as u32;
+                let height = u16::from_be_bytes(segment.get(3..5)?.try_into().ok()?) as u32;
+                let width = u16::from_be_bytes(segment.get(5..7)?.try_into().ok()?) as u32;
+                let num_components = *segment.get(7)? as u32;
+
+                return Some(ImageInfo {
+                    mime_type: "image/jpeg",
+                    width,
+                    height,
+                    depth: precision * num_components,
+                    num_colors: 0,
+                });
+            }
+            pos += 2 + segment_len;
+        }
+    }
+
+    /// Read the logical screen descriptor of a GIF image
+    ///
+    /// The colors of GIF images always take up 24 bits. The number of colors is
+    /// the size of the global color table, or 0 if there is none.
+    fn read_gif(data: &[u8]) -> Option <Self> {
+        let descriptor = data.get(6..11)?;
+        let width = u16::from_le_bytes([descriptor[0], descriptor[1]]) as u32;
+        let height = u16::from_le_bytes([descriptor[2], descriptor[3]]) as u32;
+        let flags = descriptor[4];
+        let num_colors = if flags & 0x80 != 0 { 1 << ((flags & 0x07) + 1) } else { 0 };
+
+        Some(ImageInfo {
+            mime_type: "image/gif",
+            width,
+            height,
+            depth: 24,
+            num_colors,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Build the start of a PNG image with an IHDR chunk and an optional PLTE chunk
+    fn png_header(width: u32, height: u32, bit_depth: u8, color_type: u8, palette_len: Option <u32>) -> Vec <u8> {
+        let mut data = b"\x89PNG\r\n\x1a\n".to_vec();
+        data.extend_from_slice(&13u32.to_be_bytes());
+        data.extend_from_slice(b"IHDR");
+        data.extend_from_slice(&width.to_be_bytes());
+        data.extend_from_slice(&height.to_be_bytes());
+        data.extend_from_slice(&[bit_depth, color_type, 0, 0, 0]);
+        data.extend_from_slice(&[0; 4]);
+        if let Some(palette_len) = palette_len {
+            // An ancillary chunk comes before the palette
+            data.extend_from_slice(&1u32.to_be_bytes());
+            data.extend_from_slice(b"sRGB\0");
+            data.extend_from_slice(&[0; 4]);
+            data.extend_from_slice(&(3 * palette_len).to_be_bytes());
+            data.extend_from_slice(b"PLTE");
+            data.extend(vec![0; 3 * palette_len as usize + 4]);
+        }
+        data.extend_from_slice(&0u32.to_be_bytes());
+        data.extend_from_slice(b"IDAT");
+        data
+    }
+
+    /// Build the start of a baseline JPEG image with an APP0 segment
+    fn jpeg_header(marker: u8, width: u16, height: u16, num_components: u8) -> Vec <u8> {
+        let mut data = vec![0xff, 0xd8, 0xff, 0xe0, 0x00, 0x10];
+        data.extend_from_slice(b"JFIF\0\x01\x01\0\0\x01\0\x01\0\0");
+        data.extend_from_slice(&[0xff, 0xff, marker, 0x00, 8 + 3 * num_components, 8]);
+        data.extend_from_slice(&height.to_be_bytes());
+        data.extend_from_slice(&width.to_be_bytes());
+        data.push(num_components);
+        data.extend(vec![0; 3 * num_components as usize]);
+        data.extend_from_slice(&[0xff, 0xda]);
+        data
+    }
+
+    macro_rules! image_info_tests {
+        ($($name:ident: $value:expr,)*) => {
+        $(
+            #[test]
+            fn $name() {
+                let (data, expected): (Vec <u8>, Option <(&str, u32, u32, u32, u32)>) = $value;
+                let info = ImageInfo::read(&data)
+                    .map(|info| (info.mime_type, info.width, info.height, info.depth, info.num_colors));
+                assert_eq!(info, expected);
+            }
+        )*
+        }
+    }
+
+    image_info_tests! {
+        image_info_png_rgba: (png_header(640, 480, 8, 6, None), Some(("image/png", 640, 480, 32, 0))),
+        image_info_png_gray: (png_header(32, 32, 16, 0, None), Some(("image/png", 32, 32, 16, 0))),
+        image_info_png_indexed: (png_header(100, 50, 4, 3, Some(16)), Some(("image/png", 100, 50, 24, 16))),
+        image_info_png_no_palette: (png_header(100, 50, 4, 3, None), None),
+        image_info_jpeg_baseline: (jpeg_header(0xc0, 1200, 1000, 3), Some(("image/jpeg", 1200, 1000, 24, 0))),
+        image_info_jpeg_progressive: (jpeg_header(0xc2, 300, 200, 1), Some(("image/jpeg", 300, 200, 8, 0))),
+        image_info_jpeg_no_frame: (jpeg_header(0xc4, 300, 200, 1), None),
+        image_info_gif_palette: (b"GIF89a\x40\x01\xf0\x00\xf7\x00\x00".to_vec(), Some(("image/gif", 320, 240, 24, 256))),
+        image_info_gif_no_palette: (b"GIF87a\x10\x00\x08\x00\x00\x00\x00".to_vec(), Some(("image/gif", 16, 8, 24, 0))),
+        image_info_unknown: (b"BM\0\0\0\0".to_vec(), None),
+        image_info_truncated: (b"\x89PNG\r\n\x1a\n\0\0".to_vec(), None),
+    }
+
+    #[test]
+    fn from_image() {
+        let picture = FlacPicture::from_image(PICTURE_TYPE_FRONT_COVER, "Cover", png_header(500, 400, 8, 2, None)).unwrap();
+
+        assert_eq!(picture.mime_type, "image/png");
+        assert_eq!((picture.width, picture.height, picture.depth, picture.num_colors), (500, 400, 24, 0));
+        assert_eq!(picture.extension(), "png");
+        assert!(FlacPicture::from_image(21, "", png_header(1, 1, 8, 2, None)).is_err());
+        assert!(FlacPicture::from_image(PICTURE_TYPE_FRONT_COVER, "", b"not an image".to_vec()).is_err());
+    }
+
+    #[test]
+    fn bytes_round_trip() {
+        let picture = FlacPicture::from_image(PICTURE_TYPE_BACK_COVER, "Back", jpeg_header(0xc0, 2, 1, 3)).unwrap();
+        let bytes = picture.build_bytes();
+
+        assert_eq!(&bytes[..8], &[0, 0, 0, 4, 0, 0, 0, 10]);
+        assert_eq!(&bytes[8..18], b"image/jpeg");
+        assert_eq!(bytes.len(), 32 + 10 + 4 + picture.data.len());
+        assert_eq!(FlacPicture::from_bytes(&bytes), Some(picture));
+        assert_eq!(FlacPicture::from_bytes(&bytes[..bytes.len() - 1]), None);
+    }
+}
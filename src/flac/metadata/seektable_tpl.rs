@@ -0,0 +1,217 @@
+/// Sample number marking a placeholder seek point
+pub const PLACEHOLDER_SAMPLE_NUMBER: u64 = u64::MAX;
+
+/// Number of bytes of each seek point
+const SEEK_POINT_LEN: usize = 18;
+
+/// Represents where the seek points of a SEEKTABLE are placed
+///
+/// `Seconds(n)` and `Samples(n)` place a point every `n` seconds or every `n`
+/// inter-channel samples from the start of the stream, while `Positions` places
+/// one at each listed sample number.
+#[derive(Clone, Debug, PartialEq)]
+pub enum SeekPointSpacing {
+    Seconds(u32),
+    Samples(u64),
+    Positions(Vec <u64>),
+}
+
+/// Represents a seek point of a SEEKTABLE
+///
+/// The `sample_number` is the number of the first sample of the target frame,
+/// and `stream_offset` is the offset of its header in bytes from the header of
+/// the first frame. The `frame_samples` is the number of inter-channel samples
+/// of the target frame.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct FlacSeekPoint {
+    pub sample_number: u64,
+    pub stream_offset: u64,
+    pub frame_samples: u16,
+}
+
+/// Represents the SEEKTABLE metadata block of a FLAC file
+///
+/// The seek points are sorted by sample number and unique, except for the
+/// placeholder points, which all come last.
+#[derive(Clone, Debug, PartialEq)]
+pub struct FlacSeekTable {
+    pub points: Vec <FlacSeekPoint>,
+}
+
+impl SeekPointSpacing {
+    /// Get the sorted and unique sample numbers that seek points should target
+    ///
+    /// Sample numbers from `Positions` are kept even if they are past the end of
+    /// the stream so that a point is still reserved for each of them.
+    pub fn targets(&self, sample_rate: u32, total_samples: u64) -> Vec <u64> {
+        let step = match self {
+            SeekPointSpacing::Seconds(seconds) => *seconds as u64 * sample_rate as u64,
+            SeekPointSpacing::Samples(samples) => *samples,
+            SeekPointSpacing::Positions(positions) => {
+                let mut targets = positions.clone();
+                targets.sort_unstable();
+                targets.dedup();
+                return targets;
+            },
+        };
+        if step == 0 {
+            return Vec::new();
+        }
+
+        (0..total_samples).step_by(step as usize).collect()
+    }
+
+    /// Check whether the spacing can place any seek point
+    pub fn is_valid(&self) -> bool {
+        match self {
+            SeekPointSpacing::Seconds(seconds) => *seconds > 0,
+            SeekPointSpacing::Samples(samples) => *samples > 0,
+            SeekPointSpacing::Positions(_) => true,
+        }
+    }
+}
+
+impl FlacSeekPoint {
+    /// Create a placeholder seek point, which does not point to any frame
+    pub fn placeholder() -> Self {
+        FlacSeekPoint {
+            sample_number: PLACEHOLDER_SAMPLE_NUMBER,
+            stream_offset: 0,
+            frame_samples: 0,
+        }
+    }
+
+    /// Check whether this seek point is a placeholder
+    pub fn is_placeholder(&self) -> bool {
+        self.sample_number == PLACEHOLDER_SAMPLE_NUMBER
+    }
+}
+
+impl FlacSeekTable {
+    /// Create a SEEKTABLE of `num_points` placeholder points
+    ///
+    /// This is written in place of the actual SEEKTABLE before the frames are known.
+    pub fn placeholders(num_points: usize) -> Self {
+        FlacSeekTable {
+            points: vec![FlacSeekPoint::placeholder(); num_points],
+        }
+    }
+
+    /// Create a SEEKTABLE pointing to the frames that contain the sorted sample
+    /// numbers in `targets`
+    ///
+    /// The frames are given by their numbers of inter-channel samples and their
+    /// sizes in bytes, in order. Targets falling in the same frame share a single
+    /// seek point, and targets past the last frame are dropped. The table is then
+    /// filled up with placeholder points to hold one point for each target.
+    pub fn from_frames(targets: &[u64], frame_block_sizes: &[u16], frame_sizes: &[u32]) -> Self {
+        let mut points = Vec::with_capacity(targets.len());
+        let mut targets_iter = targets.iter().peekable();
+        let mut sample_number = 0;
+        let mut stream_offset = 0;
+        for (&block_size, &frame_size) in frame_block_sizes.iter().zip(frame_sizes.iter()) {
+            let next_sample_number = sample_number + block_size as u64;
+
+            let mut is_target = false;
+            while targets_iter.next_if(|&&target| target < next_sample_number).is_some() {
+                is_target = true;
+            }
+            if is_target {
+                points.push(FlacSeekPoint {
+                    sample_number,
+                    stream_offset,
+                    frame_samples: block_size,
+                });
+            }
+
+            sample_number = next_sample_number;
+            stream_offset += frame_size as u64;
+        }
+        points.resize(targets.len(), FlacSeekPoint::placeholder());
+
+        FlacSeekTable {
+            points,
+        }
+    }
+
+    /// Convert this SEEKTABLE block into its byte representation
+    ///
+    /// Each seek point takes up 18 bytes. The metadata block header is _not_ included.
+    pub fn build_bytes(&self) -> Vec <u8> {
+        let mut bytes = Vec::with_capacity(self.points.len() * SEEK_POINT_LEN);
+        for point in self.points.iter() {
+            bytes.extend_from_slice(&point.sample_number.to_be_bytes());
+            bytes.extend_from_slice(&point.stream_offset.to_be_bytes());
+            bytes.extend_from_slice(&point.frame_samples.to_be_bytes());
+        }
+        bytes
+    }
+
+    /// Read a SEEKTABLE block from its contents
+    ///
+    /// # Errors
+    /// Returns `None` if the length of the contents is not a multiple of 18 bytes.
+    pub fn from_bytes(data: &[u8]) -> Option <Self> {
+        if !data.len().is_multiple_of(SEEK_POINT_LEN) {
+            return None;
+        }
+
+        let points = data.chunks_exact(SEEK_POINT_LEN)
+            .map(|point| FlacSeekPoint {
+                sample_number: u64::from_be_bytes(point[0..8].try_into().unwrap()),
+                stream_offset: u64::from_be_bytes(point[8..16].try_into().unwrap()),
+                frame_samples: u16::from_be_bytes(point[16..18].try_into().unwrap()),
+            })
+            .collect();
+
+        Some(FlacSeekTable {
+            points,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn spacing_targets() {
+        assert_eq!(SeekPointSpacing::Seconds(2).targets(10, 45), vec![0, 20, 40]);
+        assert_eq!(SeekPointSpacing::Samples(20).targets(10, 40), vec![0, 20]);
+        assert_eq!(SeekPointSpacing::Samples(20).targets(10, 0), vec![]);
+        assert_eq!(SeekPointSpacing::Positions(vec![50, 7, 7, 0]).targets(10, 40), vec![0, 7, 50]);
+        assert!(!SeekPointSpacing::Seconds(0).is_valid());
+        assert!(!SeekPointSpacing::Samples(0).is_valid());
+    }
+
+    #[test]
+    fn points_from_frames() {
+        // Frames start at samples 0, 16, 32 and 48
+        let seektable = FlacSeekTable::from_frames(&[0, 5, 20, 40, 70], &[16, 16, 16, 10], &[100, 90, 80, 50]);
+
+        assert_eq!(seektable.points, vec![
+            FlacSeekPoint {sample_number: 0, stream_offset: 0, frame_samples: 16},
+            FlacSeekPoint {sample_number: 16, stream_offset: 100, frame_samples: 16},
+            FlacSeekPoint {sample_number: 32, stream_offset: 190, frame_samples: 16},
+            FlacSeekPoint::placeholder(),
+            FlacSeekPoint::placeholder(),
+        ]);
+    }
+
+    #[test]
+    fn bytes_round_trip() {
+        let seektable = FlacSeekTable {
+            points: vec![
+                FlacSeekPoint {sample_number: 4096, stream_offset: 0x0102_0304, frame_samples: 4096},
+                FlacSeekPoint::placeholder(),
+            ],
+        };
+        let bytes = seektable.build_bytes();
+
+        assert_eq!(bytes.len(), 36);
+        assert_eq!(&bytes[..18], &[0, 0, 0, 0, 0, 0, 0x10, 0, 0, 0, 0, 0, 1, 2, 3, 4, 0x10, 0]);
+        assert_eq!(&bytes[18..26], &[0xff; 8]);
+        assert_eq!(FlacSeekTable::from_bytes(&bytes), Some(seektable));
+        assert_eq!(FlacSeekTable::from_bytes(&bytes[1..]), None);
+    }
+}
@@ -0,0 +1,177 @@
+use crate::flac::FlacWriterError;
+
+/// Vendor string written by this encoder
+pub const DEFAULT_VENDOR: &str = concat!("midp ", env!("CARGO_PKG_VERSION"));
+
+/// Represents the VORBIS_COMMENT metadata block of a FLAC file
+///
+/// The block holds a vendor string and a list of `FIELD=value` pairs, which
+/// are kept in the order they were added. A field name may appear more than
+/// once, e.g. for several artists. Field names are compared case-insensitively.
+#[derive(Clone, Debug, PartialEq)]
+pub struct VorbisComment {
+    vendor: String,
+    fields: Vec <(String, String)>,
+}
+
+impl VorbisComment {
+    /// Create an empty VORBIS_COMMENT block with the given vendor string
+    pub fn new(vendor: &str) -> Self {
+        VorbisComment {
+            vendor: vendor.to_string(),
+            fields: Vec::new(),
+        }
+    }
+
+    /// Get the vendor string
+    pub fn vendor(&self) -> &str {
+        &self.vendor
+    }
+
+    /// Get the `(name, value)` pairs in order
+    pub fn fields(&self) -> &Vec <(String, String)> {
+        &self.fields
+    }
+
+    /// Get the values of every field named `name`, ignoring case, in order
+    pub fn get(&self, name: &str) -> Vec <&str> {
+        self.fields.iter()
+            .filter(|(field_name, _)| field_name.eq_ignore_ascii_case(name))
+            .map(|(_, value)| value.as_str())
+            .collect()
+    }
+
+    /// Append a `name=value` field
+    ///
+    /// # Errors
+    /// Returns `FlacWriterError::InvalidMetadataError` if `name` is not a
+    /// valid field name. See `is_valid_field_name`.
+    pub fn add_field(&mut self, name: &str, value: &str) -> Result <(), FlacWriterError> {
+        if !Self::is_valid_field_name(name) {
+            return Err(FlacWriterError::InvalidMetadataError);
+        }
+        self.fields.push((name.to_string(), value.to_string()));
+        Ok(())
+    }
+
+    /// Append a `name=value` field and return the block, for chaining
+    ///
+    /// # Errors
+    /// Returns `FlacWriterError::InvalidMetadataError` if `name` is not a
+    /// valid field name. See `is_valid_field_name`.
+    pub fn with_field(mut self, name: &str, value: &str) -> Result <Self, FlacWriterError> {
+        self.add_field(name, value)?;
+        Ok(self)
+    }
+
+    /// Check whether `name` can be used as a field name
+    ///
+    /// A field name is a non-empty string of printable ASCII characters from
+    /// 0x20 to 0x7d, except for `=` which separates the name from the value.
+    pub fn is_valid_field_name(name: &str) -> bool {
+        !name.is_empty() && name.bytes().all(|c| (0x20..=0x7d).contains(&c) && c != b'=')
+    }
+
+    /// Convert this VORBIS_COMMENT block into its byte representation
+    ///
+    /// Unlike the rest of FLAC, the lengths and the number of fields are stored
+    /// as 32-bit little-endian numbers. The metadata block header is _not_ included.
+    pub fn build_bytes(&self) -> Vec <u8> {
+        let mut bytes = Vec::new();
+        bytes.extend_from_slice(&(self.vendor.len() as u32).to_le_bytes());
+        bytes.extend_from_slice(self.vendor.as_bytes());
+        bytes.extend_from_slice(&(self.fields.len() as u32).to_le_bytes());
+        for (name, value) in self.fields.iter() {
+            bytes.extend_from_slice(&((name.len() + 1 + value.len()) as u32).to_le_bytes());
+            bytes.extend_from_slice(name.as_bytes());
+            bytes.push(b'=');
+            bytes.extend_from_slice(value.as_bytes());
+        }
+        bytes
+    }
+
+    /// Read a VORBIS_COMMENT block from its contents
+    ///
+    /// # Errors
+    /// Returns `None` if the contents are truncated, are not valid UTF-8, or hold
+    /// a field without `=` or with an invalid name.
+    pub fn from_bytes(data: &[u8]) -> Option <Self> {
+        let mut pos = 0;
+        let read_string = |pos: &mut usize| -> Option <String> {
+            let len = u32::from_le_bytes(data.get(*pos..*pos + 4)?.try_into().ok()?) as usize;
+            let string = data.get(*pos + 4..*pos + 4 + len)?;
+            *pos += 4 + len;
+            String::from_utf8(string.to_vec()).ok()
+        };
+
+        let mut comment = VorbisComment::new(&read_string(&mut pos)?);
+        let num_fields = u32::from_le_bytes(data.get(pos..pos + 4)?.try_into().ok()?);
+        pos += 4;
+        for _ in 0..num_fields {
+            let field = read_string(&mut pos)?;
+            let (name, value) = field.split_once('=')?;
+            comment.add_field(name, value).ok()?;
+        }
+
+        Some(comment)
+    }
+}
+
+impl Default for VorbisComment {
+    fn default() -> Self {
+        Self::new(DEFAULT_VENDOR)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn build_bytes() {
+        let comment = VorbisComment::new("ref")
+            .with_field("TITLE", "Song").unwrap();
+
+        assert_eq!(comment.build_bytes(), b"\x03\0\0\0ref\x01\0\0\0\x0a\0\0\0TITLE=Song".to_vec());
+    }
+
+    #[test]
+    fn bytes_round_trip() {
+        let comment = VorbisComment::default()
+            .with_field("ARTIST", "First").unwrap()
+            .with_field("Title", "Título = ünïcode").unwrap()
+            .with_field("artist", "Second").unwrap()
+            .with_field("COMMENT", "").unwrap();
+        let bytes = comment.build_bytes();
+
+        let read_back = VorbisComment::from_bytes(&bytes).unwrap();
+        assert_eq!(read_back, comment);
+        assert_eq!(read_back.get("Artist"), vec!["First", "Second"]);
+        assert_eq!(read_back.get("TITLE"), vec!["Título = ünïcode"]);
+        assert_eq!(VorbisComment::from_bytes(&bytes[..bytes.len() - 1]), None);
+    }
+
+    macro_rules! field_name_tests {
+        ($($name:ident: $value:expr,)*) => {
+        $(
+            #[test]
+            fn $name() {
+                let (field_name, is_valid) = $value;
+                assert_eq!(VorbisComment::is_valid_field_name(field_name), is_valid);
+                assert_eq!(VorbisComment::default().add_field(field_name, "value").is_ok(), is_valid);
+            }
+        )*
+        }
+    }
+
+    field_name_tests! {
+        field_name_plain: ("REPLAYGAIN_TRACK_GAIN", true),
+        field_name_printable: ("A b}!", true),
+        field_name_tilde: ("TITLE~", false),
+        field_name_empty: ("", false),
+        field_name_equals: ("TITLE=", false),
+        field_name_control: ("TI\tTLE", false),
+        field_name_delete: ("TITLE\x7f", false),
+        field_name_non_ascii: ("TÍTULO", false),
+    }
+}
@@ -1,47 +1,122 @@
 pub mod encoder;
 pub mod lpc;
 pub mod bitstream;
+pub mod config;
+pub mod decoder;
+pub mod metadata;
+pub mod ogg;
 
+use std::collections::BTreeMap;
 use std::fmt;
 use std::fs::File;
-use std::io::{self, Read, Write};
-use std::os::windows::fs::FileExt;
-use std::path::Path;
+use std::io::{self, BufWriter, Seek, SeekFrom, Write};
+use std::sync::{mpsc, Arc, Mutex};
+use std::thread;
 
-use byteorder::{BigEndian, WriteBytesExt};
-use md5::{Md5, Digest};
+use md5::Context;
 
 use crate::wav::PCMWaveInfo;
 
 use encoder::crc::CrcOptions;
 
 use self::bitstream::BitstreamWriter;
-use self::encoder::rice::{RiceEncodedStream, RiceEncoderOptions};
+use self::config::{EncoderConfig, Padding, QlpPrecisionSearch, StereoMode};
+use self::decoder::{FlacReader, FlacReaderError};
+use self::encoder::rice::{RiceCodingMethod, RiceEncodedStream, RiceEncoderOptions};
 use self::encoder::utf8::Utf8Encoder;
 use self::lpc::fixed::FixedPredictor;
 use self::lpc::var::VarPredictor;
+use self::metadata::{APPLICATION_ID_RIFF, BLOCK_TYPE_APPLICATION, BLOCK_TYPE_CUESHEET, BLOCK_TYPE_PADDING, BLOCK_TYPE_PICTURE, BLOCK_TYPE_SEEKTABLE, BLOCK_TYPE_STREAMINFO, BLOCK_TYPE_VORBIS_COMMENT, MAX_BLOCK_LENGTH};
+use self::metadata::cuesheet::FlacCueSheet;
+use self::metadata::seektable::FlacSeekTable;
+use self::metadata::vorbis_comment::VorbisComment;
+use self::ogg::OggWriter;
 
+/// Number of times a block can be halved when encoding with variable block sizes
+const MAX_BLOCK_SPLITS: u32 = 3;
+
+/// Represents an error in the FLAC writer
+/// 
+/// `VerifyError` is returned in verify mode when a frame does not decode back to
+/// the samples it was built from. The `frame_index` is the frame number for fixed
+/// block size streams and the index of the first sample of the frame otherwise,
+/// and `sample_index` is the index of the first differing sample within the frame.
+/// `VerifyDecodeError` is returned instead with the error of the decoder if the frame
+/// cannot be decoded at all.
+/// `InvalidCueSheetError` gives the 1-based number of the offending line of a cue sheet.
 #[derive(Debug)]
 pub enum FlacWriterError {
     InvalidFormatError,
+    InvalidConfigError,
+    InvalidMetadataError,
+    InvalidCueSheetError {
+        line: usize,
+    },
+    VerifyError {
+        frame_index: u64,
+        channel: u8,
+        sample_index: u64,
+    },
+    VerifyDecodeError {
+        frame_index: u64,
+        error: FlacReaderError,
+    },
     DataAlignmentError,
     WriteError,
     ReadError,
 }
 
+/// Represents the STREAMINFO metadata block of a FLAC file
+/// 
+/// The STREAMINFO block is mandatory and is always the first metadata
+/// block of a FLAC file. It is 34 bytes long and describes the whole stream.
+/// Frame sizes of zero and a total sample count of zero denote unknown values.
+#[derive(Clone, Debug, PartialEq)]
+pub struct FlacStreamInfo {
+    pub min_block_size: u16,
+    pub max_block_size: u16,
+    pub min_frame_size: u32,
+    pub max_frame_size: u32,
+    pub sample_rate: u32,
+    pub num_channels: u8,
+    pub bit_depth: u8,
+    pub total_samples: u64,
+    pub md5: [u8; 16],
+}
+
 pub struct FlacFrame {
     is_variable_blocksize: bool,
     block_size: u16,
     sample_rate: FlacFrameHeaderValueOption <u64>,
     num_channels: u8,
+    channel_assignment: FlacChannelAssignment,
     bit_depth: FlacFrameHeaderValueOption <u8>,
     frame_index: u64,
     subframes: Vec <FlacSubframe>,
 }
 
+/// Represents an audio subframe
+/// 
+/// The samples of a subframe with `wasted_bits` are shifted to the right by that
+/// many bits before being coded, and `bit_depth` is the bit depth of the shifted samples.
 pub struct FlacSubframe {
     subframe_type: FlacSubframeType,
     bit_depth: u8,
+    wasted_bits: u8,
+}
+
+/// Represents how the channels of an audio frame are coded
+/// 
+/// Stereo frames can store a side channel, the difference of the left and right
+/// channels, in place of one of the channels. With `MidSide`, the first channel is
+/// the mid channel `(left + right) >> 1`. The side channel takes up one more bit
+/// than the bit depth of the stream.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum FlacChannelAssignment {
+    Independent,
+    LeftSide,
+    RightSide,
+    MidSide,
 }
 
 pub enum FlacFrameHeaderValueOption <T> {
@@ -64,6 +139,103 @@ pub enum FlacSubframeType {
 
 pub struct FlacWriter;
 
+/// Represents how STREAMINFO is filled in when the output cannot be seeked
+/// 
+/// `FromWaveSizes` computes the total number of samples from the sizes of the
+/// data chunks before encoding. `Unknown` leaves the total number of samples at
+/// zero. In both cases, the frame sizes and the MD5 signature are left unknown
+/// as they are only known after all frames have been written.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum FlacStreamInfoMode {
+    FromWaveSizes,
+    Unknown,
+}
+
+/// Represents the STREAMINFO fields that were written as "unknown"
+/// 
+/// The FLAC specification allows zero frame sizes, a zero total number
+/// of samples and an all-zero MD5 signature to denote unknown values.
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct FlacUnknownFields {
+    pub frame_sizes: bool,
+    pub total_samples: bool,
+    pub md5: bool,
+}
+
+/// Represents the outcome of encoding a stream
+/// 
+/// The `streaminfo` is the STREAMINFO block as written to the output. The `md5`
+/// is the signature of the unencoded audio, which is computed even if it could not
+/// be written to the STREAMINFO block.
+pub struct FlacWriterSummary {
+    pub streaminfo: FlacStreamInfo,
+    pub unknown_fields: FlacUnknownFields,
+    pub md5: [u8; 16],
+    pub stats: FlacEncoderStats,
+}
+
+impl FlacWriterSummary {
+    /// Get the MD5 signature as a lowercase hexadecimal string
+    pub fn md5_hex(&self) -> String {
+        self.md5.iter()
+            .map(|byte| format!("{:02x}", byte))
+            .collect()
+    }
+}
+
+/// Represents statistics collected while encoding a stream
+/// 
+/// `frame_block_sizes` and `frame_sizes` hold the number of inter-channel samples
+/// and the number of bytes of each frame in order, respectively. The subframes of every channel of every frame are counted by type, and
+/// the parameters of each LPC subframe are kept in `lpc_subframes`.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct FlacEncoderStats {
+    pub frame_block_sizes: Vec <u16>,
+    pub frame_sizes: Vec <u32>,
+    pub num_constant_subframes: u64,
+    pub num_verbatim_subframes: u64,
+    pub num_fixed_subframes: u64,
+    pub lpc_subframes: Vec <FlacLpcSubframeStats>,
+}
+
+/// Represents the parameters chosen for an LPC subframe
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct FlacLpcSubframeStats {
+    pub frame_index: u64,
+    pub channel: u8,
+    pub order: u8,
+    pub precision: u8,
+    pub shift: u8,
+}
+
+/// Represents an output of encoded frames
+trait FrameSink {
+    /// Write the bytes of a frame of `block_size` inter-channel samples
+    fn write_frame(&mut self, frame_bytes: &[u8], block_size: u16) -> io::Result <()>;
+}
+
+/// Represents an output of frames as the packets of an Ogg FLAC stream
+/// 
+/// The granule position of each packet is the number of inter-channel samples
+/// up to the end of its frame.
+struct OggFrameWriter <W: Write> {
+    ogg: OggWriter <W>,
+    granule_position: u64,
+}
+
+impl <W: Write> FrameSink for W {
+    fn write_frame(&mut self, frame_bytes: &[u8], _block_size: u16) -> io::Result <()> {
+        self.write_all(frame_bytes)
+    }
+}
+
+impl <W: Write> FrameSink for OggFrameWriter <W> {
+    fn write_frame(&mut self, frame_bytes: &[u8], block_size: u16) -> io::Result <()> {
+        self.granule_position += block_size as u64;
+        self.ogg.write_packet(frame_bytes, self.granule_position)
+    }
+}
+
 impl From <io::Error> for FlacWriterError {
     fn from(_: io::Error) -> Self {
         FlacWriterError::WriteError
@@ -78,16 +250,680 @@ impl fmt::Display for FlacWriterError {
 
 impl FlacWriter {
     /// Create a FLAC file from a PCM Wave file
-    pub fn write_from_wave(wav: PCMWaveInfo, file_path: &str) -> Result <(), FlacWriterError> {
-        todo!()
+    /// 
+    /// See `write_from_wave_to` for the layout of the file and `EncoderConfig`
+    /// for the available settings.
+    /// 
+    /// # Errors
+    /// Returns the same errors as `write_from_wave_to`, with
+    /// `FlacWriterError::WriteError` also covering the creation of the file.
+    pub fn write_from_wave(wav: PCMWaveInfo, file_path: &str, config: &EncoderConfig) -> Result <FlacWriterSummary, FlacWriterError> {
+        let fh = File::create(file_path)?;
+        let mut out = BufWriter::new(fh);
+        let summary = Self::write_from_wave_to(wav, &mut out, config)?;
+        out.flush()?;
+
+        Ok(summary)
+    }
+
+    /// Encode a PCM Wave file into any seekable output
+    /// 
+    /// The output starts with the `fLaC` marker and a placeholder STREAMINFO block,
+    /// followed by one frame for every `config.block_size()` inter-channel samples of the
+    /// data chunks. As the frame sizes, the total number of samples and the MD5 signature
+    /// are only known after all frames have been written, the writer then seeks back
+    /// and fills in the STREAMINFO block. The output is left positioned after the last frame.
+    /// 
+    /// If `config.seektable()` is set, a SEEKTABLE block of placeholder points follows
+    /// the STREAMINFO block, with one point reserved for each sample number targeted
+    /// in the data chunks. It is filled in along with the STREAMINFO block, and the
+    /// points that are left over remain placeholders. The other metadata blocks are
+    /// listed in `build_metadata_blocks`.
+    /// 
+    /// The stream does not need to start at the beginning of `out`; the metadata blocks
+    /// are patched relative to the position of `out` when this method is called.
+    /// 
+    /// # Errors
+    /// Returns `FlacWriterError::InvalidFormatError` if the WAV file cannot be
+    /// represented in FLAC, or as a CD with `config.cuesheet()` if it has the format
+    /// of one, `FlacWriterError::InvalidMetadataError` if a metadata block
+    /// is too long, `FlacWriterError::InvalidCueSheetError` if an index of
+    /// `config.cuesheet()` is past the end of the data chunks, `FlacWriterError::VerifyError`
+    /// or `FlacWriterError::VerifyDecodeError` if `config.verify()` is set and a frame does
+    /// not decode back to its samples, `FlacWriterError::ReadError` if the Wave file cannot
+    /// be read, and `FlacWriterError::WriteError` if writing or seeking fails.
+    pub fn write_from_wave_to <W: Write + Seek> (mut wav: PCMWaveInfo, out: &mut W, config: &EncoderConfig) -> Result <FlacWriterSummary, FlacWriterError> {
+        let mut streaminfo = Self::new_streaminfo(&mut wav, config)?;
+        let total_samples = wav.data_chunks.iter()
+            .map(|data_chunk| data_chunk.num_samples())
+            .sum();
+        let seek_targets = config.seektable()
+            .map(|spacing| spacing.targets(streaminfo.sample_rate, total_samples));
+        let seektable = seek_targets.as_ref()
+            .map(|targets| FlacSeekTable::placeholders(targets.len()));
+        let cuesheet = config.cuesheet()
+            .map(|cuesheet| cuesheet.to_flac_cuesheet(&streaminfo, total_samples))
+            .transpose()?;
+        let foreign_chunks = Self::read_foreign_chunks(&wav, config)?;
+
+        let start_pos = out.stream_position()?;
+        Self::write_metadata(out, &streaminfo, seektable.as_ref(), cuesheet.as_ref(), &foreign_chunks, config)?;
+
+        let stats = Self::write_frames(wav, out, &mut streaminfo, config)?;
+
+        let end_pos = out.stream_position()?;
+        out.seek(SeekFrom::Start(start_pos + 8))?;
+        out.write_all(&streaminfo.build_bytes())?;
+        if let Some(targets) = &seek_targets {
+            let seektable = FlacSeekTable::from_frames(targets, &stats.frame_block_sizes, &stats.frame_sizes);
+            out.seek(SeekFrom::Start(start_pos + 8 + 34 + 4))?;
+            out.write_all(&seektable.build_bytes())?;
+        }
+        out.seek(SeekFrom::Start(end_pos))?;
+
+        Ok(FlacWriterSummary {
+            md5: streaminfo.md5,
+            streaminfo,
+            unknown_fields: FlacUnknownFields::default(),
+            stats,
+        })
+    }
+
+    /// Encode a PCM Wave file into an output that cannot be seeked, such as a pipe
+    /// 
+    /// The layout is the same as in `write_from_wave_to`, but the STREAMINFO block is
+    /// written once before any frame. Hence, the frame sizes and the MD5 signature are
+    /// always left unknown, and the total number of samples is filled in depending on
+    /// `mode`. The returned summary says which fields were left unknown. For the same
+    /// reason, no SEEKTABLE block is written even if `config.seektable()` is set.
+    /// With `config.variable_block_size()`, the minimum block size is the smallest
+    /// one that splitting the blocks could give, since the frames are not known yet.
+    /// 
+    /// # Errors
+    /// See `write_from_wave_to`. As `out` is never seeked, `FlacWriterError::WriteError`
+    /// is only returned if writing fails.
+    pub fn write_from_wave_stream <W: Write> (mut wav: PCMWaveInfo, out: &mut W, mode: FlacStreamInfoMode, config: &EncoderConfig) -> Result <FlacWriterSummary, FlacWriterError> {
+        let mut streaminfo = Self::new_streaminfo(&mut wav, config)?;
+        let unknown_fields = FlacUnknownFields {
+            frame_sizes: true,
+            total_samples: mode == FlacStreamInfoMode::Unknown,
+            md5: true,
+        };
+        let total_samples = wav.data_chunks.iter()
+            .map(|data_chunk| data_chunk.num_samples())
+            .sum();
+        if mode == FlacStreamInfoMode::FromWaveSizes {
+            streaminfo.total_samples = total_samples;
+        }
+        if config.variable_block_size() {
+            let block_size = streaminfo.max_block_size as u64;
+            let mut min_block_size = Self::min_split_block_size(block_size);
+            // Halves of a split last block are not the last frame either
+            let last_block_len = total_samples % block_size;
+            if last_block_len > 0 && Self::min_split_block_size(last_block_len) < last_block_len {
+                min_block_size = min_block_size.min(Self::min_split_block_size(last_block_len));
+            }
+            streaminfo.min_block_size = min_block_size as u16;
+        }
+        let cuesheet = config.cuesheet()
+            .map(|cuesheet| cuesheet.to_flac_cuesheet(&streaminfo, total_samples))
+            .transpose()?;
+        let foreign_chunks = Self::read_foreign_chunks(&wav, config)?;
+
+        Self::write_metadata(out, &streaminfo, None, cuesheet.as_ref(), &foreign_chunks, config)?;
+
+        let written_streaminfo = streaminfo.clone();
+        let stats = Self::write_frames(wav, out, &mut streaminfo, config)?;
+
+        Ok(FlacWriterSummary {
+            streaminfo: written_streaminfo,
+            unknown_fields,
+            md5: streaminfo.md5,
+            stats,
+        })
+    }
+
+    /// Encode a PCM Wave file into an Ogg FLAC stream
+    /// 
+    /// The stream is the logical Ogg stream `serial_number`. Its first page holds only
+    /// the mapping header packet with the STREAMINFO block. The other metadata blocks
+    /// listed in `build_metadata_blocks` follow one per packet and end their page. The
+    /// first of them is always a VORBIS_COMMENT block, with the default vendor string
+    /// and no fields if `config.vorbis_comment()` is not set. Each frame is then a
+    /// packet, and the granule position of a page is the number of inter-channel
+    /// samples up to the end of the last frame ending on it. The frames are written as
+    /// they are encoded, and the first page is written again with the complete
+    /// STREAMINFO block at the end. No SEEKTABLE block is written, as its offsets would
+    /// not point to Ogg pages.
+    /// 
+    /// # Errors
+    /// See `write_from_wave_to`.
+    pub fn write_from_wave_ogg <W: Write + Seek> (mut wav: PCMWaveInfo, out: &mut W, serial_number: u32, config: &EncoderConfig) -> Result <FlacWriterSummary, FlacWriterError> {
+        let mut streaminfo = Self::new_streaminfo(&mut wav, config)?;
+        let total_samples = wav.data_chunks.iter()
+            .map(|data_chunk| data_chunk.num_samples())
+            .sum();
+        let cuesheet = config.cuesheet()
+            .map(|cuesheet| cuesheet.to_flac_cuesheet(&streaminfo, total_samples))
+            .transpose()?;
+        let foreign_chunks = Self::read_foreign_chunks(&wav, config)?;
+
+        // The mapping requires a VORBIS_COMMENT block right after STREAMINFO, which
+        // holds as no SEEKTABLE block is written
+        let default_vorbis_comment = VorbisComment::default();
+        let vorbis_comment = config.vorbis_comment().unwrap_or(&default_vorbis_comment);
+        let blocks = Self::build_metadata_blocks(&streaminfo, None, Some(vorbis_comment), cuesheet.as_ref(), &foreign_chunks, config)?;
+
+        // The number of header packets is left unknown if it does not fit in 16 bits
+        let start_pos = out.stream_position()?;
+        let mut ogg = OggWriter::new(&mut *out, serial_number);
+        let num_header_packets = u16::try_from(blocks.len() - 1).unwrap_or(0);
+        ogg.write_packet(&ogg::build_mapping_packet(&blocks[0], num_header_packets), 0)?;
+        ogg.flush()?;
+        for block in blocks[1..].iter() {
+            ogg.write_packet(block, 0)?;
+        }
+        ogg.flush()?;
+
+        let mut frame_writer = OggFrameWriter {ogg, granule_position: 0};
+        let stats = Self::write_frames(wav, &mut frame_writer, &mut streaminfo, config)?;
+        frame_writer.ogg.finish()?;
+
+        // The mapping packet keeps its length, so its page can be written over
+        let end_pos = out.stream_position()?;
+        let streaminfo_block = [&blocks[0][..4], &streaminfo.build_bytes()].concat();
+        out.seek(SeekFrom::Start(start_pos))?;
+        out.write_all(&ogg::build_first_page(serial_number, &ogg::build_mapping_packet(&streaminfo_block, num_header_packets)))?;
+        out.seek(SeekFrom::Start(end_pos))?;
+
+        Ok(FlacWriterSummary {
+            md5: streaminfo.md5,
+            streaminfo,
+            unknown_fields: FlacUnknownFields::default(),
+            stats,
+        })
+    }
+
+    /// Create the initial STREAMINFO block of a PCM Wave file
+    /// 
+    /// The frame sizes, total number of samples and MD5 signature are left unknown.
+    /// If `config.reduce_bit_depth()` is set, the data chunks are scanned first and
+    /// the bit depth is the effective bit depth of the samples, but at least 4 bits.
+    /// Only low-order bits that are zero in every sample are dropped, so reducing the
+    /// bit depth never loses information.
+    /// 
+    /// # Errors
+    /// Returns `FlacWriterError::InvalidFormatError` if the number of channels,
+    /// bit depth or sample rate cannot be represented in FLAC, and
+    /// `FlacWriterError::ReadError` if the data chunks cannot be scanned.
+    fn new_streaminfo(wav: &mut PCMWaveInfo, config: &EncoderConfig) -> Result <FlacStreamInfo, FlacWriterError> {
+        let fmt_info = wav.fmt_header;
+        if fmt_info.num_channels < 1 || fmt_info.num_channels > 8
+            || fmt_info.bps < 4 || fmt_info.bps > 32
+            || fmt_info.samp_rate == 0 || fmt_info.samp_rate >= (1 << 20) {
+            return Err(FlacWriterError::InvalidFormatError);
+        }
+
+        let bit_depth = if config.reduce_bit_depth() {
+            wav.effective_bit_depth()
+                .map_err(|_| FlacWriterError::ReadError)?
+                .max(4)
+        } else {
+            fmt_info.bps
+        };
+
+        let block_size = config.block_size();
+        Ok(FlacStreamInfo {
+            min_block_size: block_size,
+            max_block_size: block_size,
+            min_frame_size: 0,
+            max_frame_size: 0,
+            sample_rate: fmt_info.samp_rate,
+            num_channels: fmt_info.num_channels as u8,
+            bit_depth: bit_depth as u8,
+            total_samples: 0,
+            md5: [0; 16],
+        })
+    }
+
+    /// Encode all data chunks of a PCM Wave file into frames and write them
+    /// 
+    /// The frame sizes, total number of samples and MD5 signature of `streaminfo`
+    /// are filled in along the way. With variable block sizes, the minimum and
+    /// maximum block sizes are also replaced by the ones actually used. If the bit
+    /// depth of `streaminfo` is smaller than that of the Wave file, the samples are
+    /// shifted to the right by the difference. With more than one thread in `config`,
+    /// the blocks are encoded by `write_blocks_parallel` with the same output. This
+    /// method returns the statistics of the encoded frames.
+    /// 
+    /// # Errors
+    /// Returns `FlacWriterError::WriteError` if writing fails.
+    fn write_frames(wav: PCMWaveInfo, out: &mut impl FrameSink, streaminfo: &mut FlacStreamInfo, config: &EncoderConfig) -> Result <FlacEncoderStats, FlacWriterError> {
+        let mut md5_context = Context::new();
+        let mut stats = FlacEncoderStats::default();
+        if config.threads() > 1 {
+            Self::write_blocks_parallel(wav, out, streaminfo, config, &mut md5_context, &mut stats)?;
+        } else {
+            let encoder_streaminfo = streaminfo.clone();
+            Self::for_each_block(wav, &encoder_streaminfo, |block_index, block| {
+                let frames = Self::encode_block(&block, block_index, &encoder_streaminfo, config)?;
+                Self::write_encoded_block(out, &block, frames, streaminfo, &mut md5_context, &mut stats)
+            })?;
+        }
+
+        // The last frame does not count towards the minimum block size
+        if config.variable_block_size() && stats.frame_block_sizes.len() > 1 {
+            let (last_len, frame_lens) = stats.frame_block_sizes.split_last().unwrap();
+            streaminfo.min_block_size = *frame_lens.iter().min().unwrap();
+            streaminfo.max_block_size = *frame_lens.iter().max().unwrap().max(last_len);
+        }
+        streaminfo.md5 = md5_context.compute().0;
+
+        Ok(stats)
+    }
+
+    /// Call `f` with the index and samples of each block of a PCM Wave file in order
+    /// 
+    /// Data chunks are regrouped into full blocks of the maximum block size of
+    /// `streaminfo` so that only the last block of the stream can be shorter. The
+    /// samples are shifted down to the bit depth of `streaminfo` if it is smaller
+    /// than that of the Wave file, which only drops bits found to be zero by
+    /// `new_streaminfo`.
+    /// 
+    /// # Errors
+    /// Returns the first error returned by `f`.
+    fn for_each_block <F> (wav: PCMWaveInfo, streaminfo: &FlacStreamInfo, mut f: F) -> Result <(), FlacWriterError>
+    where F: FnMut(u64, Vec <Vec <i64>>) -> Result <(), FlacWriterError> {
+        let block_size = streaminfo.max_block_size as usize;
+        let shift = (wav.fmt_header.bps as u8).saturating_sub(streaminfo.bit_depth);
+
+        let mut block_index = 0;
+        let mut pending: Vec <Vec <i64>> = Vec::new();
+        for data_chunk in wav.data_chunks {
+            for mut samples in data_chunk.chunks(block_size) {
+                if shift > 0 {
+                    for value in samples.iter_mut().flatten() {
+                        *value >>= shift;
+                    }
+                }
+                pending.extend(samples);
+                while pending.len() >= block_size {
+                    let rest = pending.split_off(block_size);
+                    let block = std::mem::replace(&mut pending, rest);
+                    f(block_index, block)?;
+                    block_index += 1;
+                }
+            }
+        }
+        if !pending.is_empty() {
+            f(block_index, pending)?;
+        }
+
+        Ok(())
+    }
+
+    /// Encode the blocks of a PCM Wave file on `config.threads()` worker threads
+    /// 
+    /// The blocks are handed out to the workers in order, and the encoded blocks are
+    /// written in the same order as they would be by a single thread, so the output is
+    /// the same. At most `config.max_frames_in_flight()` blocks are being encoded or
+    /// waiting to be written at any time, which bounds the memory used.
+    /// 
+    /// # Errors
+    /// Returns `FlacWriterError::WriteError` if writing fails.
+    fn write_blocks_parallel(wav: PCMWaveInfo, out: &mut impl FrameSink, streaminfo: &mut FlacStreamInfo, config: &EncoderConfig, md5_context: &mut Context, stats: &mut FlacEncoderStats) -> Result <(), FlacWriterError> {
+        let encoder_streaminfo = streaminfo.clone();
+        let max_in_flight = config.max_frames_in_flight() as u64;
+
+        thread::scope(|scope| {
+            let (job_sender, job_receiver) = mpsc::channel::<(u64, Vec <Vec <i64>>)>();
+            let (result_sender, result_receiver) = mpsc::channel();
+            let job_receiver = Arc::new(Mutex::new(job_receiver));
+
+            for _ in 0..config.threads() {
+                let job_receiver = Arc::clone(&job_receiver);
+                let result_sender = result_sender.clone();
+                let encoder_streaminfo = &encoder_streaminfo;
+                scope.spawn(move || loop {
+                    // The lock is released before encoding so that other workers can take jobs
+                    let job = job_receiver.lock().unwrap().recv();
+                    let (block_index, block) = match job {
+                        Ok(job) => job,
+                        Err(_) => break,
+                    };
+                    let frames = Self::encode_block(&block, block_index, encoder_streaminfo, config);
+                    if result_sender.send((block_index, block, frames)).is_err() {
+                        break;
+                    }
+                });
+            }
+            drop(result_sender);
+
+            // Encoded blocks that arrive early wait here until the blocks before them are written
+            let mut finished = BTreeMap::new();
+            let mut num_sent = 0;
+            let mut num_written = 0;
+            let mut write_next = |finished: &mut BTreeMap <u64, _>, num_written: &mut u64| -> Result <(), FlacWriterError> {
+                let (block_index, block, frames) = result_receiver.recv()
+                    .map_err(|_| FlacWriterError::WriteError)?;
+                finished.insert(block_index, (block, frames));
+                while let Some((block, frames)) = finished.remove(num_written) {
+                    Self::write_encoded_block(out, &block, frames?, streaminfo, md5_context, stats)?;
+                    *num_written += 1;
+                }
+                Ok(())
+            };
+
+            Self::for_each_block(wav, &encoder_streaminfo, |block_index, block| {
+                while num_sent - num_written >= max_in_flight {
+                    write_next(&mut finished, &mut num_written)?;
+                }
+                job_sender.send((block_index, block)).map_err(|_| FlacWriterError::WriteError)?;
+                num_sent += 1;
+                Ok(())
+            })?;
+            drop(job_sender);
+
+            while num_written < num_sent {
+                write_next(&mut finished, &mut num_written)?;
+            }
+            Ok(())
+        })
+    }
+
+    /// Encode a block of inter-channel samples into frames
+    /// 
+    /// A block is a single frame unless variable block sizes are enabled, in which
+    /// case it may be split into several frames by `build_variable_frames`. As every
+    /// block but the last one is full, the block index gives both the frame number of
+    /// a fixed block size frame and the first sample number of a variable block size
+    /// block. In verify mode, each frame is decoded and checked against its samples.
+    /// This method returns the bytes of each frame and the frame itself in order.
+    /// 
+    /// # Errors
+    /// Returns `FlacWriterError::VerifyError` if a frame does not decode back to its samples.
+    fn encode_block(block: &[Vec <i64>], block_index: u64, streaminfo: &FlacStreamInfo, config: &EncoderConfig) -> Result <Vec <(Vec <u8>, FlacFrame)>, FlacWriterError> {
+        let frames = if config.variable_block_size() {
+            let first_sample = block_index * streaminfo.max_block_size as u64;
+            Self::build_variable_frames(block, first_sample, MAX_BLOCK_SPLITS, streaminfo, config)
+        } else {
+            vec![Self::build_frame(block, block_index, false, streaminfo, config)]
+        };
+
+        if config.verify() {
+            let mut start = 0;
+            for (frame_bytes, frame) in frames.iter() {
+                let end = start + frame.block_size as usize;
+                Self::verify_frame(frame_bytes, frame.frame_index, &block[start..end], streaminfo)?;
+                start = end;
+            }
+        }
+
+        Ok(frames)
+    }
+
+    /// Decode the bytes of a frame and compare its samples with the inter-channel
+    /// samples it was built from
+    /// 
+    /// # Errors
+    /// Returns `FlacWriterError::VerifyError` naming the earliest differing sample, and
+    /// the lowest channel that differs at it, or `FlacWriterError::VerifyDecodeError`
+    /// if the frame cannot be decoded.
+    fn verify_frame(frame_bytes: &[u8], frame_index: u64, block: &[Vec <i64>], streaminfo: &FlacStreamInfo) -> Result <(), FlacWriterError> {
+        let mismatch = |channel: usize, sample_index: usize| FlacWriterError::VerifyError {
+            frame_index,
+            channel: channel as u8,
+            sample_index: sample_index as u64,
+        };
+        let decoded = FlacReader::decode_frame(frame_bytes, streaminfo)
+            .map_err(|error| FlacWriterError::VerifyDecodeError {frame_index, error})?;
+
+        for (i, sample) in block.iter().enumerate() {
+            for (channel, value) in sample.iter().enumerate() {
+                let decoded_value = decoded.samples.get(channel).and_then(|samples| samples.get(i));
+                if decoded_value != Some(value) {
+                    return Err(mismatch(channel, i));
+                }
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Write the frames of an encoded block of inter-channel samples
+    /// 
+    /// The frames are recorded in `stats`. The STREAMINFO frame sizes and total
+    /// sample count and the MD5 signature are updated along the way.
+    fn write_encoded_block(out: &mut impl FrameSink, block: &[Vec <i64>], frames: Vec <(Vec <u8>, FlacFrame)>, streaminfo: &mut FlacStreamInfo, md5_context: &mut Context, stats: &mut FlacEncoderStats) -> Result <(), FlacWriterError> {
+        for (frame_bytes, frame) in frames {
+            out.write_frame(&frame_bytes, frame.block_size)?;
+
+            let frame_size = frame_bytes.len() as u32;
+            if streaminfo.min_frame_size == 0 || frame_size < streaminfo.min_frame_size {
+                streaminfo.min_frame_size = frame_size;
+            }
+            if frame_size > streaminfo.max_frame_size {
+                streaminfo.max_frame_size = frame_size;
+            }
+            stats.add_frame(&frame, frame_size);
+        }
+        streaminfo.total_samples += block.len() as u64;
+
+        Self::update_md5(md5_context, block, streaminfo.bit_depth);
+
+        Ok(())
+    }
+
+    /// Encode a block of inter-channel samples into a single frame
+    /// 
+    /// The `frame_index` is the frame number for fixed block sizes and the number of
+    /// the first sample of the block for variable block sizes. This method returns
+    /// the bytes of the frame and the frame itself, respectively.
+    fn build_frame(block: &[Vec <i64>], frame_index: u64, is_variable_blocksize: bool, streaminfo: &FlacStreamInfo, config: &EncoderConfig) -> (Vec <u8>, FlacFrame) {
+        let mut frame = FlacFrame::new(
+            block.len() as u16,
+            FlacFrameHeaderValueOption::Streaminfo(streaminfo.sample_rate as u64),
+            streaminfo.num_channels,
+            FlacFrameHeaderValueOption::Streaminfo(streaminfo.bit_depth),
+            frame_index,
+        );
+        frame.is_variable_blocksize = is_variable_blocksize;
+        let frame_bytes = frame.build_bytes(block, config);
+        (frame_bytes, frame)
+    }
+
+    /// Encode a block of inter-channel samples into frames of adaptive lengths
+    /// 
+    /// The block is encoded as a whole and as its two halves, each of which is split
+    /// further in the same way up to `max_splits` times. The halves are kept if their
+    /// frames take up fewer bytes than the frame of the whole block. Blocks of odd
+    /// length or whose halves would be shorter than 16 samples are not split. This
+    /// method returns the bytes of each frame and the frame itself in order.
+    fn build_variable_frames(block: &[Vec <i64>], first_sample: u64, max_splits: u32, streaminfo: &FlacStreamInfo, config: &EncoderConfig) -> Vec <(Vec <u8>, FlacFrame)> {
+        let whole = Self::build_frame(block, first_sample, true, streaminfo, config);
+        let half_len = block.len() / 2;
+        if max_splits == 0 || !block.len().is_multiple_of(2) || half_len < 16 {
+            return vec![whole];
+        }
+
+        let mut halves = Self::build_variable_frames(&block[..half_len], first_sample, max_splits - 1, streaminfo, config);
+        halves.extend(Self::build_variable_frames(&block[half_len..], first_sample + half_len as u64, max_splits - 1, streaminfo, config));
+
+        let halves_size: usize = halves.iter().map(|(frame_bytes, _)| frame_bytes.len()).sum();
+        if halves_size < whole.0.len() {
+            halves
+        } else {
+            vec![whole]
+        }
     }
 
-    /// Determine the best block size given a predictor order
+    /// Get the smallest block size that `build_variable_frames` can give for a block
+    /// of `block_len` samples
+    fn min_split_block_size(block_len: u64) -> u64 {
+        let mut len = block_len;
+        for _ in 0..MAX_BLOCK_SPLITS {
+            if !len.is_multiple_of(2) || len / 2 < 16 {
+                break;
+            }
+            len /= 2;
+        }
+        len
+    }
+
+    /// Read the foreign chunks of a PCM Wave file if `config.keep_foreign_metadata()` is set
     /// 
-    /// By default, the block size is 4096 unless the maximum
-    /// LPC order is 0, which defaults to a block size of 1152.
-    fn best_block_size(max_lpc_order: u64) -> u16 {
-        todo!()
+    /// # Errors
+    /// Returns `FlacWriterError::ReadError` if the chunks cannot be read from the file.
+    fn read_foreign_chunks(wav: &PCMWaveInfo, config: &EncoderConfig) -> Result <Vec <Vec <u8>>, FlacWriterError> {
+        if config.keep_foreign_metadata() {
+            wav.read_foreign_chunks().map_err(|_| FlacWriterError::ReadError)
+        } else {
+            Ok(Vec::new())
+        }
+    }
+
+    /// Feed a block of inter-channel samples to the MD5 signature
+    /// 
+    /// The signature is computed over the interleaved samples as signed little-endian
+    /// integers, each taking up the bit depth rounded up to a whole byte.
+    fn update_md5(md5_context: &mut Context, block: &[Vec <i64>], bit_depth: u8) {
+        let bytes_per_sample = (bit_depth as usize).div_ceil(8);
+        let mut buffer = Vec::with_capacity(block.len() * block.first().map_or(0, |s| s.len()) * bytes_per_sample);
+        for sample in block.iter() {
+            for channel_sample in sample.iter() {
+                buffer.extend_from_slice(&channel_sample.to_le_bytes()[..bytes_per_sample]);
+            }
+        }
+        md5_context.consume(&buffer);
+    }
+
+    /// Write the `fLaC` marker and the metadata blocks of a stream
+    /// 
+    /// The blocks are listed in `build_metadata_blocks`.
+    /// 
+    /// # Errors
+    /// Returns `FlacWriterError::InvalidMetadataError` if the contents of a block are
+    /// too long for its 24-bit length, and `FlacWriterError::WriteError` if writing fails.
+    fn write_metadata(out: &mut impl Write, streaminfo: &FlacStreamInfo, seektable: Option <&FlacSeekTable>, cuesheet: Option <&FlacCueSheet>, foreign_chunks: &[Vec <u8>], config: &EncoderConfig) -> Result <(), FlacWriterError> {
+        out.write_all(b"fLaC")?;
+        for block in Self::build_metadata_blocks(streaminfo, seektable, config.vorbis_comment(), cuesheet, foreign_chunks, config)? {
+            out.write_all(&block)?;
+        }
+
+        Ok(())
+    }
+
+    /// Build the metadata blocks of a stream, each with its header
+    /// 
+    /// The STREAMINFO block comes first, followed by the SEEKTABLE block if `seektable`
+    /// is given, the VORBIS_COMMENT block if `vorbis_comment` is given, the
+    /// CUESHEET block if `cuesheet` is given, one PICTURE block for each of
+    /// `config.pictures()`, and one `riff` APPLICATION block for each of `foreign_chunks`.
+    /// The PADDING block sized by `config.padding()` always comes last so that the
+    /// blocks before it can grow into it. Only the last block is marked as such.
+    /// 
+    /// # Errors
+    /// Returns `FlacWriterError::InvalidMetadataError` if the contents of a block are
+    /// too long for its 24-bit length.
+    fn build_metadata_blocks(streaminfo: &FlacStreamInfo, seektable: Option <&FlacSeekTable>, vorbis_comment: Option <&VorbisComment>, cuesheet: Option <&FlacCueSheet>, foreign_chunks: &[Vec <u8>], config: &EncoderConfig) -> Result <Vec <Vec <u8>>, FlacWriterError> {
+        let mut blocks = vec![(BLOCK_TYPE_STREAMINFO, streaminfo.build_bytes())];
+        if let Some(seektable) = seektable {
+            blocks.push((BLOCK_TYPE_SEEKTABLE, seektable.build_bytes()));
+        }
+        if let Some(vorbis_comment) = vorbis_comment {
+            blocks.push((BLOCK_TYPE_VORBIS_COMMENT, vorbis_comment.build_bytes()));
+        }
+        if let Some(cuesheet) = cuesheet {
+            blocks.push((BLOCK_TYPE_CUESHEET, cuesheet.build_bytes()));
+        }
+        for picture in config.pictures().iter() {
+            blocks.push((BLOCK_TYPE_PICTURE, picture.build_bytes()));
+        }
+        for chunk in foreign_chunks.iter() {
+            blocks.push((BLOCK_TYPE_APPLICATION, [APPLICATION_ID_RIFF.as_slice(), chunk].concat()));
+        }
+
+        let tags_len: usize = blocks.iter()
+            .filter(|(block_type, _)| *block_type == BLOCK_TYPE_VORBIS_COMMENT || *block_type == BLOCK_TYPE_PICTURE)
+            .map(|(_, contents)| 4 + contents.len())
+            .sum();
+        let padding_len = match config.padding() {
+            Padding::None => None,
+            Padding::Bytes(len) => Some(len as usize),
+            Padding::ExpectedTags(len) => Some((len as usize).saturating_sub(tags_len)).filter(|&len| len > 0),
+        };
+        if let Some(padding_len) = padding_len {
+            blocks.push((BLOCK_TYPE_PADDING, vec![0; padding_len]));
+        }
+        if blocks.iter().any(|(_, contents)| contents.len() > MAX_BLOCK_LENGTH) {
+            return Err(FlacWriterError::InvalidMetadataError);
+        }
+
+        let num_blocks = blocks.len();
+        Ok(blocks.into_iter()
+            .enumerate()
+            .map(|(i, (block_type, contents))| {
+                let mut block = Self::build_metadata_block_header(i + 1 == num_blocks, block_type, contents.len() as u32);
+                block.extend_from_slice(&contents);
+                block
+            })
+            .collect())
+    }
+
+    /// Build the 4-byte header of a metadata block
+    /// 
+    /// The header consists of a flag marking the last metadata block, the 7-bit
+    /// block type and the 24-bit length of the block contents.
+    fn build_metadata_block_header(is_last: bool, block_type: u8, length: u32) -> Vec <u8> {
+        let mut header = Vec::with_capacity(4);
+        header.push(((is_last as u8) << 7) | (block_type & 0x7f));
+        header.extend_from_slice(&length.to_be_bytes()[1..]);
+        header
+    }
+}
+
+impl FlacEncoderStats {
+    /// Record the block size, size in bytes and subframes of an encoded frame
+    fn add_frame(&mut self, frame: &FlacFrame, frame_size: u32) {
+        let frame_index = self.frame_block_sizes.len() as u64;
+        self.frame_block_sizes.push(frame.block_size);
+        self.frame_sizes.push(frame_size);
+
+        for (channel, subframe) in frame.subframes.iter().enumerate() {
+            match &subframe.subframe_type {
+                FlacSubframeType::Constant {..} => self.num_constant_subframes += 1,
+                FlacSubframeType::Verbatim | FlacSubframeType::None => self.num_verbatim_subframes += 1,
+                FlacSubframeType::Fixed {..} => self.num_fixed_subframes += 1,
+                FlacSubframeType::Lpc {order, precision, shift, ..} => {
+                    self.lpc_subframes.push(FlacLpcSubframeStats {
+                        frame_index,
+                        channel: channel as u8,
+                        order: *order,
+                        precision: *precision,
+                        shift: *shift,
+                    });
+                },
+            }
+        }
+    }
+}
+
+impl FlacStreamInfo {
+    /// Convert this STREAMINFO block into its 34-byte representation
+    /// 
+    /// The metadata block header is _not_ included.
+    pub fn build_bytes(&self) -> Vec <u8> {
+        let mut writer = BitstreamWriter::new();
+        writer.write_bits(self.min_block_size as u64, 16);
+        writer.write_bits(self.max_block_size as u64, 16);
+        writer.write_bits(self.min_frame_size as u64, 24);
+        writer.write_bits(self.max_frame_size as u64, 24);
+        writer.write_bits(self.sample_rate as u64, 20);
+        writer.write_bits(self.num_channels as u64 - 1, 3);
+        writer.write_bits(self.bit_depth as u64 - 1, 5);
+        writer.write_bits(self.total_samples, 36);
+        writer.write_bytes(&self.md5);
+        writer.into_bytes()
     }
 }
 
@@ -96,16 +932,126 @@ impl FlacFrame {
     /// 
     /// A FLAC audio frame contains a slice of an audio file. Each frame can
     /// be compressed preferably using most optimal compression scheme detected.
+    /// The frame is part of a fixed block size stream, so `frame_index` is the
+    /// frame number.
     pub fn new(block_size: u16, sample_rate: FlacFrameHeaderValueOption <u64>, num_channels: u8, bit_depth: FlacFrameHeaderValueOption <u8>, frame_index: u64) -> FlacFrame {
-        todo!()
+        FlacFrame {
+            is_variable_blocksize: false,
+            block_size,
+            sample_rate,
+            num_channels,
+            channel_assignment: FlacChannelAssignment::Independent,
+            bit_depth,
+            frame_index,
+            subframes: Vec::new(),
+        }
     }
 
     /// Convert this audio frame into a vector of bytes
     /// 
     /// An audio frame is ensured to be byte-aligned (i.e. necessary "0" padding
-    /// bits have been appended).
-    pub fn build_bytes(&mut self, sample_block: &Vec <Vec <i64>>) -> Vec <u8> {
-        todo!()
+    /// bits have been appended). The channel assignment of stereo frames is picked
+    /// according to `config.stereo_mode()`.
+    pub fn build_bytes(&mut self, sample_block: &[Vec <i64>], config: &EncoderConfig) -> Vec <u8> {
+        let bit_depth = *self.bit_depth.value();
+
+        // The samples are stored inter-channel, but subframes hold one channel each
+        let channels: Vec <Vec <i64>> = (0..self.num_channels as usize)
+            .map(|ch| sample_block.iter().map(|sample| sample[ch]).collect())
+            .collect();
+        let window_weights = FlacSubframe::window_weights(sample_block.len(), config);
+
+        let encoded_subframes = if self.num_channels == 2 {
+            self.build_stereo_subframes(&channels, bit_depth, &window_weights, config)
+        } else {
+            self.channel_assignment = FlacChannelAssignment::Independent;
+            channels.iter()
+                .map(|samples| FlacSubframe::new_best(bit_depth, self.block_size as u64, samples, &window_weights, config))
+                .collect()
+        };
+
+        let mut writer = BitstreamWriter::new();
+        writer.write_bytes(&self.build_header_bytes());
+
+        self.subframes.clear();
+        for (subframe, bytes, extra_bits_len) in encoded_subframes {
+            writer.write_partial_bytes(&bytes, extra_bits_len);
+            self.subframes.push(subframe);
+        }
+        writer.align();
+
+        let mut frame_bytes = writer.into_bytes();
+        let crc = CrcOptions::new(0b1000_0000_0000_0101u16, 16)
+            .build_crc16(&frame_bytes);
+        frame_bytes.extend_from_slice(&crc.to_be_bytes());
+
+        frame_bytes
+    }
+
+    /// Build the two subframes of a stereo audio frame and set its channel assignment
+    /// 
+    /// With `StereoMode::MidSide`, the best subframes of the left, right, side and mid
+    /// channels are built and the channel assignment with the smallest pair is kept.
+    /// With `StereoMode::AdaptiveMidSide`, the channel assignment is picked first from
+    /// the estimated sizes of FIXED subframes of each channel, and only its two
+    /// subframes are built. This method returns each subframe, its bytes and the
+    /// number of extra unused bits at the last byte of its stream, in channel order.
+    fn build_stereo_subframes(&mut self, channels: &[Vec <i64>], bit_depth: u8, window_weights: &[Vec <f64>], config: &EncoderConfig) -> Vec <(FlacSubframe, Vec <u8>, u8)> {
+        let (left, right) = (&channels[0], &channels[1]);
+        let side: Vec <i64> = left.iter().zip(right.iter()).map(|(l, r)| l - r).collect();
+        let mid: Vec <i64> = left.iter().zip(right.iter()).map(|(l, r)| (l + r) >> 1).collect();
+        // The signals are indexed the same way as in `FlacChannelAssignment::signals`
+        let signals = [(left, bit_depth), (right, bit_depth), (&side, bit_depth + 1), (&mid, bit_depth)];
+
+        let assignments = match config.stereo_mode() {
+            StereoMode::Independent => vec![FlacChannelAssignment::Independent],
+            StereoMode::Forced(assignment) => vec![assignment],
+            StereoMode::MidSide => vec![
+                FlacChannelAssignment::Independent,
+                FlacChannelAssignment::LeftSide,
+                FlacChannelAssignment::RightSide,
+                FlacChannelAssignment::MidSide,
+            ],
+            StereoMode::AdaptiveMidSide => {
+                let estimates: Vec <u64> = signals.iter()
+                    .map(|(samples, depth)| FlacSubframe::estimate_fixed_bits(*depth, samples, config))
+                    .collect();
+                let best = FlacChannelAssignment::all().into_iter()
+                    .min_by_key(|assignment| assignment.signals().iter().map(|i| estimates[*i]).sum::<u64>())
+                    .unwrap_or(FlacChannelAssignment::Independent);
+                vec![best]
+            },
+        };
+
+        // Each signal is encoded at most once even if it is shared by several assignments
+        let mut encoded: Vec <Option <(FlacSubframe, Vec <u8>, u8)>> = vec![None, None, None, None];
+        let mut best: Option <(u64, FlacChannelAssignment)> = None;
+        for assignment in assignments {
+            let mut bits = 0;
+            for i in assignment.signals() {
+                if encoded[i].is_none() {
+                    let (samples, depth) = signals[i];
+                    encoded[i] = Some(FlacSubframe::new_best(depth, self.block_size as u64, samples, window_weights, config));
+                }
+                if let Some((_, bytes, extra_bits_len)) = &encoded[i] {
+                    bits += 8 * bytes.len() as u64 - *extra_bits_len as u64;
+                }
+            }
+
+            let is_better = match best {
+                Some((best_bits, _)) => bits < best_bits,
+                None => true,
+            };
+            if is_better {
+                best = Some((bits, assignment));
+            }
+        }
+
+        self.channel_assignment = best.map_or(FlacChannelAssignment::Independent, |(_, assignment)| assignment);
+        self.channel_assignment.signals()
+            .into_iter()
+            .filter_map(|i| encoded[i].take())
+            .collect()
     }
 
     /// Determine the block size type of this audio frame
@@ -135,7 +1081,23 @@ impl FlacFrame {
     /// |               < 256 |             0110 |
     /// |             < 65536 |             0111 |
     fn block_size_type(&self) -> u8 {
-        todo!()
+        match self.block_size {
+            192 => 0b0001,
+            576 => 0b0010,
+            1152 => 0b0011,
+            2304 => 0b0100,
+            4608 => 0b0101,
+            256 => 0b1000,
+            512 => 0b1001,
+            1024 => 0b1010,
+            2048 => 0b1011,
+            4096 => 0b1100,
+            8192 => 0b1101,
+            16384 => 0b1110,
+            32768 => 0b1111,
+            size if size <= 256 => 0b0110,
+            _ => 0b0111,
+        }
     }
 
     /// Determine the sample rate type of this audio frame
@@ -164,7 +1126,28 @@ impl FlacFrame {
     /// |         < 655350    |             1110 |
     /// |            invalid  |             1111 |
     fn sample_rate_type(&self) -> u8 {
-        todo!()
+        let sample_rate = match self.sample_rate {
+            FlacFrameHeaderValueOption::Streaminfo(_) => return 0b0000,
+            FlacFrameHeaderValueOption::InFrame(sample_rate) => sample_rate,
+        };
+
+        match sample_rate {
+            88_200 => 0b0001,
+            176_400 => 0b0010,
+            192_000 => 0b0011,
+            8_000 => 0b0100,
+            16_000 => 0b0101,
+            22_050 => 0b0110,
+            24_000 => 0b0111,
+            32_000 => 0b1000,
+            44_100 => 0b1001,
+            48_000 => 0b1010,
+            96_000 => 0b1011,
+            rate if rate % 1000 == 0 && rate / 1000 < 256 => 0b1100,
+            rate if rate < 65_536 => 0b1101,
+            rate if rate % 10 == 0 && rate / 10 < 65_536 => 0b1110,
+            _ => 0b1111,
+        }
     }
 
     /// Determine the bit depth type of this audio frame
@@ -185,7 +1168,20 @@ impl FlacFrame {
     /// |                24  |        110 |
     /// |                32  |        111 |
     fn bit_depth_type(&self) -> u8 {
-        todo!()
+        let bit_depth = match self.bit_depth {
+            FlacFrameHeaderValueOption::Streaminfo(_) => return 0b000,
+            FlacFrameHeaderValueOption::InFrame(bit_depth) => bit_depth,
+        };
+
+        match bit_depth {
+            8 => 0b001,
+            12 => 0b010,
+            16 => 0b100,
+            20 => 0b101,
+            24 => 0b110,
+            32 => 0b111,
+            _ => 0b011,
+        }
     }
 
     /// Build the header bytes of this audio frame
@@ -193,51 +1189,337 @@ impl FlacFrame {
     /// An audio frame header is ensured to be byte-aligned (i.e. necessary "0" padding
     /// bits have been appended).
     fn build_header_bytes(&self) -> Vec <u8> {
-        todo!()
+        let block_size_type = self.block_size_type();
+        let sample_rate_type = self.sample_rate_type();
+
+        let mut writer = BitstreamWriter::new();
+        // 14-bit sync code, mandatory 0 bit and the blocking strategy
+        writer.write_bits(0b11_1111_1111_1110, 14);
+        writer.write_bits(0, 1);
+        writer.write_bits(self.is_variable_blocksize as u64, 1);
+        writer.write_bits(block_size_type as u64, 4);
+        writer.write_bits(sample_rate_type as u64, 4);
+        writer.write_bits(self.channel_assignment.channel_bits(self.num_channels) as u64, 4);
+        writer.write_bits(self.bit_depth_type() as u64, 3);
+        writer.write_bits(0, 1);
+
+        // Frame index for fixed block sizes, sample index for variable block sizes
+        writer.write_bytes(&Utf8Encoder::encode(self.frame_index));
+
+        match block_size_type {
+            0b0110 => writer.write_bits(self.block_size as u64 - 1, 8),
+            0b0111 => writer.write_bits(self.block_size as u64 - 1, 16),
+            _ => {},
+        }
+
+        let sample_rate = *self.sample_rate.value();
+        match sample_rate_type {
+            0b1100 => writer.write_bits(sample_rate / 1000, 8),
+            0b1101 => writer.write_bits(sample_rate, 16),
+            0b1110 => writer.write_bits(sample_rate / 10, 16),
+            _ => {},
+        }
+
+        let mut header_bytes = writer.into_bytes();
+        let crc = CrcOptions::new(0b0000_0111u8, 8)
+            .build_crc8(&header_bytes);
+        header_bytes.push(crc);
+
+        header_bytes
     }
 }
 
 impl FlacSubframe {
     /// Create a new VERBATIM audio frame
     pub fn new_verbatim(bit_depth: u8) -> Self {
-        todo!()
+        FlacSubframe {
+            subframe_type: FlacSubframeType::Verbatim,
+            bit_depth,
+            wasted_bits: 0,
+        }
     }
 
     /// Create a new CONSTANT audio frame
     pub fn new_constant(bit_depth: u8, sample_value: i64) -> Self {
-        todo!()
+        FlacSubframe {
+            subframe_type: FlacSubframeType::Constant {value: sample_value},
+            bit_depth,
+            wasted_bits: 0,
+        }
     }
 
     /// Create a new FIXED audio frame that autodetects the best
     /// predictor order for a given block of samples
-    pub fn new_fixed(bit_depth: u8, samples: &Vec <i64>) -> Option <Self> {
-        todo!()
+    pub fn new_fixed(bit_depth: u8, samples: &[i64]) -> Option <Self> {
+        let order = FixedPredictor::best_predictor_order(samples)?;
+        Some(Self::new_fixed_by_order(bit_depth, order))
     }
 
     /// Create a new FIXED audio frame from some predictor order
     pub fn new_fixed_by_order(bit_depth: u8, order: u8) -> Self {
-        todo!()
+        FlacSubframe {
+            subframe_type: FlacSubframeType::Fixed {order},
+            bit_depth,
+            wasted_bits: 0,
+        }
+    }
+
+    /// Estimate the number of bits of the smallest FIXED audio frame of a block of samples
+    /// 
+    /// A constant block is estimated as a CONSTANT audio frame instead, as `new_best`
+    /// codes it as such. This returns `u64::MAX` if the block is too short for any
+    /// FIXED predictor.
+    fn estimate_fixed_bits(bit_depth: u8, samples: &[i64], config: &EncoderConfig) -> u64 {
+        if let Some(value) = Self::get_constant_value(samples) {
+            return Self::new_constant(bit_depth, value).estimate_bits(samples, config).unwrap_or(u64::MAX);
+        }
+        (0..=4)
+            .filter_map(|order| Self::new_fixed_by_order(bit_depth, order).estimate_bits(samples, config))
+            .min()
+            .unwrap_or(u64::MAX)
+    }
+
+    /// Compute the weights of each apodization window in `config` over `len` samples
+    /// 
+    /// The weights only depend on the length of the block, so they are computed once
+    /// per frame and shared by the subframes of every channel.
+    pub fn window_weights(len: usize, config: &EncoderConfig) -> Vec <Vec <f64>> {
+        config.apodization().iter()
+            .map(|window| window.weights(len))
+            .collect()
     }
 
     /// Create a new LPC audio frame that autodetects the best
     /// predictor order for a given block of samples
-    pub fn new_variable(bit_depth: u8, block_size: u64, samples: &Vec <i64>) -> Self {
-        todo!()
+    /// 
+    /// Each order up to `config.max_lpc_order()` is tried with each apodization window,
+    /// whose weights over the block are given in `window_weights`, and the subframe
+    /// whose residuals have the smallest estimated Rice encoding, including the warm-up
+    /// samples and the coefficients, is kept. Blocks too short for any LPC order
+    /// become VERBATIM.
+    pub fn new_variable(bit_depth: u8, block_size: u64, samples: &[i64], window_weights: &[Vec <f64>], config: &EncoderConfig) -> Self {
+        let mut best: Option <(u64, Self)> = None;
+        for subframe in Self::new_variable_candidates(bit_depth, block_size, samples, window_weights, config) {
+            let bits = match subframe.estimate_bits(samples, config) {
+                Some(bits) => bits,
+                None => continue,
+            };
+            let is_better = match &best {
+                Some((best_bits, _)) => bits < *best_bits,
+                None => true,
+            };
+            if is_better {
+                best = Some((bits, subframe));
+            }
+        }
+
+        match best {
+            Some((_, subframe)) => subframe,
+            None => Self::new_verbatim(bit_depth),
+        }
+    }
+
+    /// Create a new LPC audio frame for each apodization window in `config` and each
+    /// predictor order up to `config.max_lpc_order()`
+    /// 
+    /// The orders are limited so that at least one residual is left in the block.
+    fn new_variable_candidates(bit_depth: u8, block_size: u64, samples: &[i64], window_weights: &[Vec <f64>], config: &EncoderConfig) -> Vec <Self> {
+        let max_order = (config.max_lpc_order() as usize).min(samples.len().saturating_sub(1)) as u8;
+        if max_order == 0 {
+            return Vec::new();
+        }
+
+        let precisions = Self::qlp_precisions(bit_depth, block_size, config);
+        let mut candidates = Vec::with_capacity(window_weights.len() * max_order as usize);
+        for weights in window_weights {
+            let autoc = VarPredictor::get_windowed_autocorrelation(samples, weights, max_order as u32);
+            candidates.extend((1..=max_order)
+                .map(|order| Self::new_lpc_from_autocorrelation(bit_depth, &precisions, order, &autoc, samples, config)));
+        }
+
+        candidates
     }
 
     /// Create a new LPC audio frame from some predictor order
-    pub fn new_variable_by_order(bit_depth: u8, block_size: u64, order: u8, samples: &Vec <i64>) -> Self {
-        todo!()
+    /// 
+    /// The first apodization window in `window_weights` is applied to the samples.
+    pub fn new_variable_by_order(bit_depth: u8, block_size: u64, order: u8, samples: &[i64], window_weights: &[Vec <f64>], config: &EncoderConfig) -> Self {
+        let precisions = Self::qlp_precisions(bit_depth, block_size, config);
+        let autoc = match window_weights.first() {
+            Some(weights) => VarPredictor::get_windowed_autocorrelation(samples, weights, order as u32),
+            None => VarPredictor::get_autocorrelation(samples, order as u32),
+        };
+        Self::new_lpc_from_autocorrelation(bit_depth, &precisions, order, &autoc, samples, config)
+    }
+
+    /// Get the QLP coefficient precisions to try
+    /// 
+    /// The precision set in `config`, or the best precision for the bit depth and block
+    /// size if none is set, is tried along with the precisions around it as allowed by
+    /// `config.qlp_precision_search()`.
+    fn qlp_precisions(bit_depth: u8, block_size: u64, config: &EncoderConfig) -> Vec <u32> {
+        let precision = match config.qlp_precision() {
+            Some(precision) => precision as u32,
+            None => VarPredictor::get_best_precision(bit_depth as u32, block_size as u32),
+        };
+
+        match config.qlp_precision_search() {
+            QlpPrecisionSearch::Off => vec![precision],
+            QlpPrecisionSearch::Around(radius) => {
+                let radius = radius as u32;
+                // The same range as `EncoderConfigBuilder::build` accepts
+                (precision.saturating_sub(radius).clamp(5, 15)..=(precision + radius).clamp(5, 15)).collect()
+            },
+            QlpPrecisionSearch::All => (5..=15).collect(),
+        }
+    }
+
+    /// Create a new LPC audio frame from precomputed autocorrelations
+    /// 
+    /// The coefficients are quantized with each of the `precisions`, and the precision
+    /// whose subframe has the smallest estimated size, including the coefficients, is kept.
+    fn new_lpc_from_autocorrelation(bit_depth: u8, precisions: &[u32], order: u8, autoc: &[f64], samples: &[i64], config: &EncoderConfig) -> Self {
+        let lpc_coefs = VarPredictor::get_predictor_coeffs(autoc, order as u32);
+        let new_lpc = |precision: u32| {
+            let (qlp_coefs, shift) = VarPredictor::quantize_coeffs(&lpc_coefs, precision);
+            FlacSubframe {
+                subframe_type: FlacSubframeType::Lpc {
+                    order,
+                    precision: precision as u8,
+                    shift: shift as u8,
+                    qlp_coefs: Some(qlp_coefs),
+                },
+                bit_depth,
+                wasted_bits: 0,
+            }
+        };
+
+        if let [precision] = precisions {
+            return new_lpc(*precision);
+        }
+        precisions.iter()
+            .map(|precision| new_lpc(*precision))
+            .filter_map(|subframe| Some((subframe.estimate_bits(samples, config)?, subframe)))
+            .min_by_key(|(bits, _)| *bits)
+            .map(|(_, subframe)| subframe)
+            .unwrap_or_else(|| new_lpc(precisions[0]))
+    }
+
+    /// Create the audio subframe that takes up the least amount of bits
+    /// 
+    /// A block of several samples that all have the same value, such as digital silence,
+    /// always becomes a CONSTANT subframe without any predictor analysis. Otherwise, the
+    /// VERBATIM, best FIXED and best LPC subframes are compared by their estimated sizes
+    /// by default. With an exhaustive model search, the VERBATIM subframe, the FIXED
+    /// subframes of orders 0 until 4 and the LPC subframes of every order are compared by
+    /// their exact sizes instead. Only the smallest subframe is built. The wasted bits of
+    /// the block are shifted out of the samples before any subframe is tried. This method
+    /// returns the subframe, its bytes and the number of extra unused bits at the last byte
+    /// of the stream, respectively. `window_weights` is passed on to `new_variable`.
+    fn new_best(bit_depth: u8, block_size: u64, samples: &[i64], window_weights: &[Vec <f64>], config: &EncoderConfig) -> (Self, Vec <u8>, u8) {
+        if let Some(value) = Self::get_constant_value(samples).filter(|_| samples.len() > 1) {
+            let constant = Self::new_constant(bit_depth, value);
+            let (bytes, extra_bits_len) = constant.build_bytes(samples, config);
+            return (constant, bytes, extra_bits_len);
+        }
+
+        let wasted_bits = Self::get_wasted_shift(samples);
+        let shifted_samples: Vec <i64>;
+        let (bit_depth, samples) = if wasted_bits > 0 {
+            shifted_samples = samples.iter().map(|sample| sample >> wasted_bits).collect();
+            (bit_depth - wasted_bits, shifted_samples.as_slice())
+        } else {
+            (bit_depth, samples)
+        };
+
+        let mut candidates = vec![Self::new_verbatim(bit_depth)];
+        if config.exhaustive_model_search() {
+            candidates.extend((0..=4).map(|order| Self::new_fixed_by_order(bit_depth, order)));
+            candidates.extend(Self::new_variable_candidates(bit_depth, block_size, samples, window_weights, config));
+        } else {
+            if let Some(subframe) = Self::new_fixed(bit_depth, samples) {
+                candidates.push(subframe);
+            }
+            candidates.push(Self::new_variable(bit_depth, block_size, samples, window_weights, config));
+        }
+
+        let mut best = candidates.into_iter()
+            .filter_map(|subframe| Some((subframe.estimate_bits(samples, config)?, subframe)))
+            .min_by_key(|(bits, _)| *bits)
+            .map(|(_, subframe)| subframe)
+            .unwrap_or(Self::new_verbatim(bit_depth));
+        best.wasted_bits = wasted_bits;
+        let (bytes, extra_bits_len) = best.build_bytes(samples, config);
+
+        (best, bytes, extra_bits_len)
+    }
+
+    /// Estimate the number of bits of this audio subframe without encoding it
+    /// 
+    /// The estimate is exact if `config.exhaustive_model_search()` is set.
+    /// 
+    /// # Errors
+    /// Returns `None` if the residuals of this subframe cannot be computed.
+    fn estimate_bits(&self, samples: &[i64], config: &EncoderConfig) -> Option <u64> {
+        let header_bits = 8 + self.wasted_bits as u64;
+        let bit_depth = self.bit_depth as u64;
+        match &self.subframe_type {
+            FlacSubframeType::Lpc {order, precision, qlp_coefs: Some(qlp_coefs), shift} => {
+                let residuals = VarPredictor::get_residuals(samples, qlp_coefs, *order as u32, *shift as u32)?;
+                let residual_bits = Self::rice_encoder(samples, *order, config)
+                    .estimate_bits(&residuals);
+                Some(header_bits + *order as u64 * (bit_depth + *precision as u64) + 4 + 5 + 2 + residual_bits)
+            },
+            FlacSubframeType::Fixed {order} => {
+                let residuals = FixedPredictor::get_residuals(samples, *order)?;
+                let residual_bits = Self::rice_encoder(samples, *order, config)
+                    .estimate_bits(&residuals);
+                Some(header_bits + *order as u64 * bit_depth + 2 + residual_bits)
+            },
+            FlacSubframeType::Constant {..} => Some(header_bits + bit_depth),
+            FlacSubframeType::Verbatim => Some(header_bits + samples.len() as u64 * bit_depth),
+            _ => None,
+        }
     }
 
     /// Get the residuals of a given block of samples and encode them into a Rice-encoded
     /// byte stream.
     /// 
     /// Note that the contents are _not_ ensured to be byte-aligned. Hence, this method returns
-    /// the Rice-encoded byte stream and the number of extra unused bits at the last byte
-    /// of the stream, respectively.
-    fn get_encoded_residuals(&self, samples: &Vec <i64>) -> Option <(Vec <RiceEncodedStream>, u8)> {
-        todo!()
+    /// the Rice-encoded partitions, each keeping its number of extra unused bits, the
+    /// partition order and the residual coding method, respectively.
+    fn get_encoded_residuals(&self, samples: &[i64], config: &EncoderConfig) -> Option <(Vec <RiceEncodedStream>, u8, RiceCodingMethod)> {
+        let (residuals, order) = match &self.subframe_type {
+            FlacSubframeType::Fixed {order} => {
+                (FixedPredictor::get_residuals(samples, *order)?, *order)
+            },
+            FlacSubframeType::Lpc {order, shift, qlp_coefs: Some(qlp_coefs), ..} => {
+                (VarPredictor::get_residuals(samples, qlp_coefs, *order as u32, *shift as u32)?, *order)
+            },
+            _ => return None,
+        };
+
+        Some(Self::rice_encoder(samples, order, config).encode_by_partition(&residuals))
+    }
+
+    /// Create the Rice encoder of the residuals of a block of samples, limited
+    /// to the partition orders set in `config` and exact for an exhaustive model search
+    fn rice_encoder(samples: &[i64], predictor_order: u8, config: &EncoderConfig) -> RiceEncoderOptions {
+        RiceEncoderOptions::new(samples.len() as u64, predictor_order)
+            .partition_orders(config.min_partition_order(), config.max_partition_order())
+            .exact(config.exhaustive_model_search())
+    }
+
+    /// Get the value of a block of samples if all of them have the same value
+    /// 
+    /// Returns `None` for an empty block.
+    fn get_constant_value(samples: &[i64]) -> Option <i64> {
+        let first = *samples.first()?;
+        if samples.iter().all(|sample| *sample == first) {
+            Some(first)
+        } else {
+            None
+        }
     }
 
     /// Compute the number of wasted bits in a block of samples
@@ -245,33 +1527,1300 @@ impl FlacSubframe {
     /// The "wasted bits" as defined in FLAC are the maximum number of
     /// LSBits whose values are 0 for all samples in a block. Instead of encoding samples
     /// as is, each sample can be shifted by the number of wasted bits to the right first
-    /// before being encoded through one of the four subframe types.
-    fn get_wasted_shift(&self, samples: &Vec <i64>) -> u64 {
-        todo!()
+    /// before being encoded through one of the four subframe types. A block of zeros
+    /// has no wasted bits.
+    fn get_wasted_shift(samples: &[i64]) -> u8 {
+        let all_bits = samples.iter().fold(0, |acc, sample| acc | sample);
+        if all_bits == 0 {
+            return 0;
+        }
+        all_bits.trailing_zeros() as u8
     }
 
     /// Build the header bytes of this audio subframe
     /// 
+    /// The header includes the unary-coded number of wasted bits, if any.
     /// An audio subframe header is _not_ ensured to be byte-aligned. Hence,
     /// this method returns the header bytes and the number of extra unused
     /// bits at the last byte of the stream, respectively.
-    fn build_header_bytes(&self, samples: &Vec <i64>) -> (Vec <u8>, u8) {
-        todo!()
+    fn build_header_bytes(&self, _samples: &[i64]) -> (Vec <u8>, u8) {
+        let type_bits = match &self.subframe_type {
+            FlacSubframeType::Constant {..} => 0b000000,
+            FlacSubframeType::Fixed {order} => 0b001000 | *order,
+            FlacSubframeType::Lpc {order, ..} => 0b100000 | (*order - 1),
+            FlacSubframeType::Verbatim | FlacSubframeType::None => 0b000001,
+        };
+
+        let mut writer = BitstreamWriter::new();
+        // Mandatory 0 bit, the subframe type and the wasted bits flag
+        writer.write_bits(0, 1);
+        writer.write_bits(type_bits as u64, 6);
+        if self.wasted_bits == 0 {
+            writer.write_bits(0, 1);
+        } else {
+            // The number of wasted bits k is written as k - 1 in unary
+            writer.write_bits(1, 1);
+            writer.write_unary(self.wasted_bits as u64 - 1);
+        }
+
+        writer.into_partial_bytes()
     }
 
     /// Convert this audio subframe into a vector of bytes. This includes the
     /// header and the contents defined by one of the four subframe types.
+    /// The `samples` should already be shifted by the wasted bits of this subframe.
     /// 
     /// An audio subframe is _not_ ensured to be byte-aligned. Hence,
     /// this method returns the bytes and the number of extra unused
     /// bits at the last byte of the stream, respectively.
-    pub fn build_bytes(&self, samples: &Vec <i64>) -> (Vec <u8>, u8) {
-        todo!()
+    pub fn build_bytes(&self, samples: &[i64], config: &EncoderConfig) -> (Vec <u8>, u8) {
+        let mut writer = BitstreamWriter::new();
+        let (header_bytes, header_extra_bits_len) = self.build_header_bytes(samples);
+        writer.write_partial_bytes(&header_bytes, header_extra_bits_len);
+
+        match &self.subframe_type {
+            FlacSubframeType::None => {},
+            FlacSubframeType::Constant {value} => {
+                writer.write_signed(*value, self.bit_depth);
+            },
+            FlacSubframeType::Verbatim => {
+                for sample in samples.iter() {
+                    writer.write_signed(*sample, self.bit_depth);
+                }
+            },
+            FlacSubframeType::Fixed {order} => {
+                for sample in samples.iter().take(*order as usize) {
+                    writer.write_signed(*sample, self.bit_depth);
+                }
+                self.write_residuals(&mut writer, samples, config);
+            },
+            FlacSubframeType::Lpc {order, precision, shift, qlp_coefs} => {
+                for sample in samples.iter().take(*order as usize) {
+                    writer.write_signed(*sample, self.bit_depth);
+                }
+                writer.write_bits(*precision as u64 - 1, 4);
+                writer.write_signed(*shift as i64, 5);
+                for coef in qlp_coefs.iter().flatten() {
+                    writer.write_signed(*coef, *precision);
+                }
+                self.write_residuals(&mut writer, samples, config);
+            },
+        }
+
+        writer.into_partial_bytes()
+    }
+
+    /// Write the residual section of a FIXED or LPC audio subframe
+    /// 
+    /// The section starts with the 2-bit residual coding method and the 4-bit
+    /// partition order, followed by each partition's Rice parameter and contents.
+    /// The parameters are 4 bits long, or 5 bits long for the RICE2 method.
+    /// Escaped partitions have the all-ones parameter followed by the 5-bit width
+    /// of their residuals.
+    fn write_residuals(&self, writer: &mut BitstreamWriter, samples: &[i64], config: &EncoderConfig) {
+        if let Some((streams, partition_order, method)) = self.get_encoded_residuals(samples, config) {
+            writer.write_bits(method.method_bits(), 2);
+            writer.write_bits(partition_order as u64, 4);
+            for stream in streams.iter() {
+                match stream.escape_bits {
+                    Some(escape_bits) => {
+                        writer.write_bits(method.escape_param(), method.param_len());
+                        writer.write_bits(escape_bits as u64, 5);
+                    },
+                    None => writer.write_bits(stream.param as u64, method.param_len()),
+                }
+                writer.write_partial_bytes(&stream.stream, stream.extra_bits_len);
+            }
+        }
+    }
+}
+
+impl FlacChannelAssignment {
+    /// Get every channel assignment available to stereo audio frames
+    pub fn all() -> [Self; 4] {
+        [
+            FlacChannelAssignment::Independent,
+            FlacChannelAssignment::LeftSide,
+            FlacChannelAssignment::RightSide,
+            FlacChannelAssignment::MidSide,
+        ]
+    }
+
+    /// Get the 4-bit channel assignment field of an audio frame header
+    /// 
+    /// Independent channels store the number of channels minus one, while the
+    /// stereo assignments left/side, right/side and mid/side are 0b1000 until 0b1010.
+    pub fn channel_bits(&self, num_channels: u8) -> u8 {
+        match self {
+            FlacChannelAssignment::Independent => num_channels - 1,
+            FlacChannelAssignment::LeftSide => 0b1000,
+            FlacChannelAssignment::RightSide => 0b1001,
+            FlacChannelAssignment::MidSide => 0b1010,
+        }
+    }
+
+    /// Get the signals stored in the two subframes of a stereo audio frame
+    /// 
+    /// The signals are indexed as 0 for left, 1 for right, 2 for side and 3 for mid.
+    fn signals(&self) -> [usize; 2] {
+        match self {
+            FlacChannelAssignment::Independent => [0, 1],
+            FlacChannelAssignment::LeftSide => [0, 2],
+            FlacChannelAssignment::RightSide => [2, 1],
+            FlacChannelAssignment::MidSide => [3, 2],
+        }
     }
 }
 
 impl <T> FlacFrameHeaderValueOption <T>  {
     pub fn value(&self) -> &T {
-        todo!()
+        match self {
+            FlacFrameHeaderValueOption::Streaminfo(value) => value,
+            FlacFrameHeaderValueOption::InFrame(value) => value,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::flac::decoder::{FlacReader, FlacStream};
+    use crate::flac::config::Padding;
+    use crate::flac::metadata::cuesheet::CueSheet;
+    use crate::flac::metadata::picture::{FlacPicture, PICTURE_TYPE_FRONT_COVER, PICTURE_TYPE_OTHER_FILE_ICON};
+    use crate::flac::metadata::seektable::SeekPointSpacing;
+    use crate::flac::ogg::{OggPage, HEADER_TYPE_BOS, HEADER_TYPE_CONTINUED, HEADER_TYPE_EOS};
+    use crate::wav::WaveReader;
+    use crate::wav::tests::TempPath;
+
+    fn read_wav_samples(file_path: &str) -> Vec <Vec <i64>> {
+        let wav = WaveReader::open_pcm(file_path).unwrap();
+        wav.data_chunks.into_iter().flatten().collect()
+    }
+
+    /// Write the first `num_samples` inter-channel samples of a PCM Wave file into
+    /// a temporary PCM Wave file, and get its path
+    fn create_excerpt_wav(name: &str, wav_path: &str, num_samples: usize) -> TempPath {
+        let wav = WaveReader::open_pcm(wav_path).unwrap();
+        let (sample_rate, bit_depth) = (wav.fmt_header.samp_rate, wav.fmt_header.bps);
+        let samples: Vec <Vec <i64>> = wav.data_chunks.into_iter().flatten().take(num_samples).collect();
+        create_temp_wav(name, sample_rate, bit_depth, &samples)
+    }
+
+    /// Get the last-block flag, block type and length of each metadata block header
+    /// of a FLAC stream, following the lengths up to the first frame
+    fn metadata_block_headers(bytes: &[u8]) -> Vec <(bool, u8, usize)> {
+        let mut headers = Vec::new();
+        let mut pos = 4;
+        while !bytes[pos..].starts_with(&[0xff, 0xf8]) {
+            let length = u32::from_be_bytes([0, bytes[pos + 1], bytes[pos + 2], bytes[pos + 3]]) as usize;
+            headers.push((bytes[pos] & 0x80 != 0, bytes[pos] & 0x7f, length));
+            pos += 4 + length;
+        }
+        headers
+    }
+
+    /// A file or directory in the temporary directory that is removed when dropped
+    /// Write inter-channel samples into a temporary PCM Wave file with a single
+    /// data chunk, and get its path
+    fn create_temp_wav(name: &str, sample_rate: u32, bit_depth: u16, samples: &[Vec <i64>]) -> TempPath {
+        let num_channels = samples.first().map_or(1, |sample| sample.len()) as u16;
+        let bytes_per_sample = (bit_depth as usize).div_ceil(8);
+        let block_align = num_channels * bytes_per_sample as u16;
+
+        let mut data = Vec::with_capacity(samples.len() * block_align as usize);
+        for sample in samples.iter() {
+            for channel_sample in sample.iter() {
+                // 8-bit samples are unsigned with 128 as the midpoint
+                let value = if bit_depth == 8 { channel_sample + 128 } else { *channel_sample };
+                data.extend_from_slice(&value.to_le_bytes()[..bytes_per_sample]);
+            }
+        }
+
+        let mut content = Vec::new();
+        content.extend_from_slice(b"RIFF");
+        content.extend_from_slice(&(36 + data.len() as u32).to_le_bytes());
+        content.extend_from_slice(b"WAVEfmt ");
+        content.extend_from_slice(&16u32.to_le_bytes());
+        content.extend_from_slice(&1u16.to_le_bytes());
+        content.extend_from_slice(&num_channels.to_le_bytes());
+        content.extend_from_slice(&sample_rate.to_le_bytes());
+        content.extend_from_slice(&(sample_rate * block_align as u32).to_le_bytes());
+        content.extend_from_slice(&block_align.to_le_bytes());
+        content.extend_from_slice(&bit_depth.to_le_bytes());
+        content.extend_from_slice(b"data");
+        content.extend_from_slice(&(data.len() as u32).to_le_bytes());
+        content.extend_from_slice(&data);
+
+        let path = TempPath::new(name);
+        std::fs::write(path.as_str(), content).unwrap();
+        path
+    }
+
+    /// Encode a PCM Wave file into memory with `write`, then decode the stream and
+    /// check that it holds the samples of the Wave file
+    /// 
+    /// This returns the writer summary, the bytes of the stream and the decoded
+    /// stream, respectively.
+    fn encode_and_decode_with <F> (wav_path: &str, write: F) -> (FlacWriterSummary, Vec <u8>, FlacStream)
+    where F: FnOnce(PCMWaveInfo, &mut Vec <u8>) -> Result <FlacWriterSummary, FlacWriterError> {
+        let wav = WaveReader::open_pcm(wav_path).unwrap();
+        let mut bytes = Vec::new();
+        let summary = write(wav, &mut bytes).unwrap();
+        let decoded = FlacReader::decode_bytes(&bytes).unwrap();
+
+        assert_eq!(decoded.samples, read_wav_samples(wav_path));
+        (summary, bytes, decoded)
+    }
+
+    /// Encode a PCM Wave file with `write_from_wave_to` like `encode_and_decode_with`
+    fn encode_and_decode(wav_path: &str, config: &EncoderConfig) -> (FlacWriterSummary, Vec <u8>, FlacStream) {
+        encode_and_decode_with(wav_path, |wav, out| FlacWriter::write_from_wave_to(wav, &mut io::Cursor::new(out), config))
+    }
+
+    macro_rules! round_trip_tests {
+        ($($name:ident: $value:expr,)*) => {
+        $(
+            #[test]
+            fn $name() {
+                let (wav_path, reference_path) = $value;
+                let (summary, _, decoded) = encode_and_decode(wav_path, &EncoderConfig::default());
+                let reference = FlacReader::open(reference_path).unwrap();
+
+                assert_eq!(decoded.streaminfo.total_samples, reference.streaminfo.total_samples);
+                assert_eq!(decoded.streaminfo.sample_rate, reference.streaminfo.sample_rate);
+                assert_eq!(decoded.streaminfo.num_channels, reference.streaminfo.num_channels);
+                assert_eq!(decoded.streaminfo.bit_depth, reference.streaminfo.bit_depth);
+                assert_eq!(decoded.streaminfo.md5, reference.streaminfo.md5);
+                assert_eq!(summary.md5, reference.streaminfo.md5);
+                assert!(decoded.streaminfo.min_frame_size > 0);
+                assert!(decoded.streaminfo.min_frame_size <= decoded.streaminfo.max_frame_size);
+            }
+        )*
+        }
+    }
+
+    round_trip_tests! {
+        round_trip_ietf_01: ("sample_audio/ietf/ietf_01.wav", "sample_audio/ietf/ietf_01.flac"),
+        round_trip_music_3s: ("sample_audio/m_3s/music_3s.wav", "sample_audio/m_3s/music_3s_flac_var.flac"),
+        round_trip_music_c_mono: ("sample_audio/c/music_c_mono.wav", "sample_audio/c/music_c_mono_flac_var.flac"),
+    }
+
+    #[test]
+    fn write_to_file() {
+        let wav_path = "sample_audio/ietf/ietf_01.wav";
+        let flac_file = TempPath::new("write_to_file.flac");
+        FlacWriter::write_from_wave(WaveReader::open_pcm(wav_path).unwrap(), flac_file.as_str(), &EncoderConfig::default()).unwrap();
+        let (_, bytes, _) = encode_and_decode(wav_path, &EncoderConfig::default());
+
+        assert_eq!(std::fs::read(flac_file.as_str()).unwrap(), bytes);
+    }
+
+    #[test]
+    fn write_to_cursor() {
+        let wav_path = "sample_audio/m_3s/music_3s.wav";
+        let wav = WaveReader::open_pcm(wav_path).unwrap();
+
+        // The stream is written after some existing bytes in the buffer
+        let mut out = io::Cursor::new(b"prefix".to_vec());
+        out.seek(SeekFrom::End(0)).unwrap();
+        FlacWriter::write_from_wave_to(wav, &mut out, &EncoderConfig::default()).unwrap();
+
+        assert_eq!(out.position(), out.get_ref().len() as u64);
+        let bytes = out.into_inner();
+        assert_eq!(&bytes[..6], b"prefix");
+
+        let decoded = FlacReader::decode_bytes(&bytes[6..]).unwrap();
+        assert_eq!(decoded.streaminfo.total_samples, 140928);
+        assert!(decoded.streaminfo.max_frame_size > 0);
+        assert_eq!(decoded.samples, read_wav_samples(wav_path));
+    }
+
+    macro_rules! stream_tests {
+        ($($name:ident: $value:expr,)*) => {
+        $(
+            #[test]
+            fn $name() {
+                // A plain `Vec` can be written to but not seeked
+                let (mode, expected_total_samples) = $value;
+                let (summary, _, decoded) = encode_and_decode_with("sample_audio/m_3s/music_3s.wav", |wav, out| {
+                    FlacWriter::write_from_wave_stream(wav, out, mode, &EncoderConfig::default())
+                });
+
+                assert_eq!(decoded.streaminfo, summary.streaminfo);
+                assert_eq!(decoded.streaminfo.total_samples, expected_total_samples);
+                assert_eq!(decoded.streaminfo.min_frame_size, 0);
+                assert_eq!(decoded.streaminfo.max_frame_size, 0);
+                assert_eq!(decoded.streaminfo.md5, [0; 16]);
+                assert_eq!(summary.unknown_fields.total_samples, expected_total_samples == 0);
+                assert!(summary.unknown_fields.frame_sizes);
+                assert!(summary.unknown_fields.md5);
+                assert_ne!(summary.md5, [0; 16]);
+            }
+        )*
+        }
+    }
+
+    stream_tests! {
+        stream_from_wave_sizes: (FlacStreamInfoMode::FromWaveSizes, 140928),
+        stream_unknown: (FlacStreamInfoMode::Unknown, 0),
+    }
+
+    macro_rules! seektable_tests {
+        ($($name:ident: $value:expr,)*) => {
+        $(
+            #[test]
+            fn $name() {
+                let (variable_block_size, spacing, num_points, num_placeholders) = $value;
+                let config = EncoderConfig::builder()
+                    .variable_block_size(variable_block_size)
+                    .seektable(spacing)
+                    .build()
+                    .unwrap();
+
+                let (_, bytes, _) = encode_and_decode("sample_audio/m_3s/music_3s.wav", &config);
+                let (streaminfo, metadata_blocks, first_frame_pos) = FlacReader::read_metadata(&bytes).unwrap();
+
+                assert_eq!(metadata_blocks.len(), 2);
+                assert_eq!(metadata_blocks[0].block_type, BLOCK_TYPE_SEEKTABLE);
+                assert_eq!(metadata_blocks[1].block_type, BLOCK_TYPE_PADDING);
+                let seektable = FlacSeekTable::from_bytes(&metadata_blocks[0].data).unwrap();
+                assert_eq!(seektable.points.len(), num_points);
+                assert_eq!(seektable.points.iter().filter(|point| point.is_placeholder()).count(), num_placeholders);
+
+                // Each seek point leads to the header of the frame starting at its sample number
+                let points = &seektable.points[..num_points - num_placeholders];
+                assert!(points.windows(2).all(|pair| pair[0].sample_number < pair[1].sample_number));
+                for point in points.iter() {
+                    let frame_pos = first_frame_pos + point.stream_offset as usize;
+                    let frame = FlacReader::decode_frame(&bytes[frame_pos..], &streaminfo).unwrap();
+                    let first_sample = if frame.is_variable_blocksize {
+                        frame.frame_index
+                    } else {
+                        frame.frame_index * streaminfo.max_block_size as u64
+                    };
+                    assert_eq!(first_sample, point.sample_number);
+                    assert_eq!(frame.samples[0].len(), point.frame_samples as usize);
+                }
+            }
+        )*
+        }
+    }
+
+    seektable_tests! {
+        seektable_seconds: (false, SeekPointSpacing::Seconds(1), 4, 0),
+        seektable_samples: (false, SeekPointSpacing::Samples(4096), 35, 0),
+        seektable_positions: (false, SeekPointSpacing::Positions(vec![0, 10, 5000, 200000]), 4, 2),
+        seektable_variable: (true, SeekPointSpacing::Seconds(1), 4, 0),
+    }
+
+    #[test]
+    fn seektable_skipped_in_stream() {
+        let wav = WaveReader::open_pcm("sample_audio/m_3s/music_3s.wav").unwrap();
+        let config = EncoderConfig::builder()
+            .seektable(SeekPointSpacing::Seconds(1))
+            .build()
+            .unwrap();
+
+        let mut out: Vec <u8> = Vec::new();
+        FlacWriter::write_from_wave_stream(wav, &mut out, FlacStreamInfoMode::Unknown, &config).unwrap();
+        let decoded = FlacReader::decode_bytes(&out).unwrap();
+
+        assert!(decoded.metadata_blocks.iter().all(|block| block.block_type != BLOCK_TYPE_SEEKTABLE));
+    }
+
+    #[test]
+    fn vorbis_comment_after_seektable() {
+        let wav_path = "sample_audio/m_3s/music_3s.wav";
+        let wav = WaveReader::open_pcm(wav_path).unwrap();
+        let vorbis_comment = VorbisComment::default()
+            .with_field("TITLE", "Music").unwrap()
+            .with_field("ARTIST", "Someone").unwrap();
+        let config = EncoderConfig::builder()
+            .seektable(SeekPointSpacing::Seconds(1))
+            .vorbis_comment(vorbis_comment.clone())
+            .build()
+            .unwrap();
+
+        let mut out = io::Cursor::new(Vec::new());
+        FlacWriter::write_from_wave_to(wav, &mut out, &config).unwrap();
+        let bytes = out.into_inner();
+        let decoded = FlacReader::decode_bytes(&bytes).unwrap();
+
+        let block_types: Vec <u8> = decoded.metadata_blocks.iter().map(|block| block.block_type).collect();
+        assert_eq!(block_types, vec![BLOCK_TYPE_SEEKTABLE, BLOCK_TYPE_VORBIS_COMMENT, BLOCK_TYPE_PADDING]);
+        assert_eq!(metadata_block_headers(&bytes), vec![
+            (false, BLOCK_TYPE_STREAMINFO, 34),
+            (false, BLOCK_TYPE_SEEKTABLE, 4 * 18),
+            (false, BLOCK_TYPE_VORBIS_COMMENT, decoded.metadata_blocks[1].data.len()),
+            (true, BLOCK_TYPE_PADDING, 8192),
+        ]);
+        assert_eq!(VorbisComment::from_bytes(&decoded.metadata_blocks[1].data), Some(vorbis_comment));
+        assert_eq!(decoded.samples, read_wav_samples(wav_path));
+    }
+
+    #[test]
+    fn vorbis_comment_in_stream() {
+        let wav = WaveReader::open_pcm("sample_audio/m_3s/music_3s.wav").unwrap();
+        let vorbis_comment = VorbisComment::new("vendor")
+            .with_field("GENRE", "Test").unwrap();
+        let config = EncoderConfig::builder()
+            .vorbis_comment(vorbis_comment.clone())
+            .padding(Padding::None)
+            .build()
+            .unwrap();
+
+        let mut out: Vec <u8> = Vec::new();
+        FlacWriter::write_from_wave_stream(wav, &mut out, FlacStreamInfoMode::Unknown, &config).unwrap();
+        let decoded = FlacReader::decode_bytes(&out).unwrap();
+
+        assert_eq!(metadata_block_headers(&out), vec![
+            (false, BLOCK_TYPE_STREAMINFO, 34),
+            (true, BLOCK_TYPE_VORBIS_COMMENT, decoded.metadata_blocks[0].data.len()),
+        ]);
+        assert_eq!(decoded.metadata_blocks[0].block_type, BLOCK_TYPE_VORBIS_COMMENT);
+        assert_eq!(VorbisComment::from_bytes(&decoded.metadata_blocks[0].data), Some(vorbis_comment));
+    }
+
+    #[test]
+    fn cuesheet_embedded() {
+        // 200 CD sectors of 588 samples, so the second track starts at 1.5 seconds
+        let wav_file = create_excerpt_wav("cuesheet_embedded.wav", "sample_audio/m_3s/music_3s.wav", 200 * 588);
+        let sheet = CueSheet::parse("FILE music_3s.wav WAVE\nTRACK 01 AUDIO\nINDEX 01 00:00:00\nTRACK 02 AUDIO\nINDEX 01 00:01:37\n").unwrap();
+        let config = EncoderConfig::builder()
+            .vorbis_comment(VorbisComment::default())
+            .cuesheet(sheet)
+            .picture(FlacPicture::from_image(PICTURE_TYPE_FRONT_COVER, "", b"GIF89a\x01\0\x01\0\0\0\0".to_vec()).unwrap())
+            .build()
+            .unwrap();
+
+        let (_, _, decoded) = encode_and_decode(wav_file.as_str(), &config);
+
+        let block_types: Vec <u8> = decoded.metadata_blocks.iter().map(|block| block.block_type).collect();
+        assert_eq!(block_types, vec![BLOCK_TYPE_VORBIS_COMMENT, BLOCK_TYPE_CUESHEET, BLOCK_TYPE_PICTURE, BLOCK_TYPE_PADDING]);
+        let cuesheet = FlacCueSheet::from_bytes(&decoded.metadata_blocks[1].data).unwrap();
+        assert!(cuesheet.is_cd);
+        let offsets: Vec <(u8, u64)> = cuesheet.tracks.iter().map(|track| (track.number, track.offset)).collect();
+        assert_eq!(offsets, vec![(1, 0), (2, 112 * 588), (170, 200 * 588)]);
+
+        // The whole file has the format of a CD but does not end on a CD sector
+        let wav = WaveReader::open_pcm("sample_audio/m_3s/music_3s.wav").unwrap();
+        let result = FlacWriter::write_from_wave_to(wav, &mut io::Cursor::new(Vec::new()), &config);
+        assert!(matches!(result, Err(FlacWriterError::InvalidFormatError)));
+    }
+
+    #[test]
+    fn cuesheet_past_end() {
+        let sheet = CueSheet::parse("FILE music_3s.wav WAVE\nTRACK 01 AUDIO\nINDEX 01 00:00:00\nTRACK 02 AUDIO\nINDEX 01 00:03:15\n").unwrap();
+        let config = EncoderConfig::builder()
+            .cuesheet(sheet)
+            .build()
+            .unwrap();
+
+        let wav = WaveReader::open_pcm("sample_audio/m_3s/music_3s.wav").unwrap();
+        let result = FlacWriter::write_from_wave_to(wav, &mut io::Cursor::new(Vec::new()), &config);
+        assert!(matches!(result, Err(FlacWriterError::InvalidCueSheetError {line: 5})));
+
+        let wav = WaveReader::open_pcm("sample_audio/m_3s/music_3s.wav").unwrap();
+        let result = FlacWriter::write_from_wave_stream(wav, &mut Vec::new(), FlacStreamInfoMode::Unknown, &config);
+        assert!(matches!(result, Err(FlacWriterError::InvalidCueSheetError {line: 5})));
+    }
+
+    #[test]
+    fn foreign_metadata_restored() {
+        let wav_file = TempPath::new("foreign_metadata_restored.wav");
+        let original = std::fs::read("sample_audio/m_3s/music_3s.wav").unwrap();
+        let (fmt, data) = original[12..].split_at(24);
+        let list = b"LIST\x0d\0\0\0INFOISFT\x01\0\0\0x\0";
+        let smpl = b"smpl\x04\0\0\0\x01\x02\x03\x04";
+        let mut content = b"RIFF\0\0\0\0WAVE".to_vec();
+        for chunk in [fmt, list, data, smpl] {
+            content.extend_from_slice(chunk);
+        }
+        let riff_size = content.len() as u32 - 8;
+        content[4..8].copy_from_slice(&riff_size.to_le_bytes());
+        std::fs::write(&wav_file.0, &content).unwrap();
+
+        let config = EncoderConfig::builder()
+            .keep_foreign_metadata(true)
+            .build()
+            .unwrap();
+        let mut out = io::Cursor::new(Vec::new());
+        FlacWriter::write_from_wave_to(WaveReader::open_pcm(wav_file.as_str()).unwrap(), &mut out, &config).unwrap();
+        let mut plain_out = io::Cursor::new(Vec::new());
+        FlacWriter::write_from_wave_to(WaveReader::open_pcm(wav_file.as_str()).unwrap(), &mut plain_out, &EncoderConfig::default()).unwrap();
+
+        let stream = FlacReader::decode_bytes(&out.into_inner()).unwrap();
+        let riff_blocks: Vec <&[u8]> = stream.metadata_blocks.iter()
+            .filter(|block| block.block_type == BLOCK_TYPE_APPLICATION)
+            .map(|block| &block.data[..])
+            .collect();
+        assert_eq!(riff_blocks, vec![
+            [b"riff".as_slice(), &content[..12]].concat(),
+            [b"riff".as_slice(), fmt].concat(),
+            [b"riff".as_slice(), list].concat(),
+            [b"riff".as_slice(), &data[..8]].concat(),
+            [b"riff".as_slice(), smpl].concat(),
+        ]);
+        assert_eq!(stream.metadata_blocks.last().unwrap().block_type, BLOCK_TYPE_PADDING);
+
+        let mut restored = Vec::new();
+        FlacReader::write_wave(&stream, &mut restored, true).unwrap();
+        assert_eq!(restored, content);
+
+        let mut canonical = Vec::new();
+        FlacReader::write_wave(&stream, &mut canonical, false).unwrap();
+        assert_eq!(canonical, original);
+
+        let plain_stream = FlacReader::decode_bytes(&plain_out.into_inner()).unwrap();
+        assert!(plain_stream.metadata_blocks.iter().all(|block| block.block_type != BLOCK_TYPE_APPLICATION));
+    }
+
+    #[test]
+    fn ogg_matches_native() {
+        let wav_path = "sample_audio/m_3s/music_3s.wav";
+        let config = EncoderConfig::builder()
+            .vorbis_comment(VorbisComment::default())
+            .padding(Padding::Bytes(100_000))
+            .build()
+            .unwrap();
+
+        let mut native = io::Cursor::new(Vec::new());
+        FlacWriter::write_from_wave_to(WaveReader::open_pcm(wav_path).unwrap(), &mut native, &config).unwrap();
+        let mut out = io::Cursor::new(Vec::new());
+        let summary = FlacWriter::write_from_wave_ogg(WaveReader::open_pcm(wav_path).unwrap(), &mut out, 7, &config).unwrap();
+        let out = out.into_inner();
+
+        assert_eq!(FlacReader::demux_ogg(&out).unwrap(), native.into_inner());
+        assert_eq!(FlacReader::decode_ogg_bytes(&out).unwrap().samples, read_wav_samples(wav_path));
+
+        let mut pages = Vec::new();
+        let mut pos = 0;
+        while pos < out.len() {
+            let (page, page_len) = OggPage::from_bytes(&out[pos..]).unwrap();
+            pages.push(page);
+            pos += page_len;
+        }
+
+        // The mapping header packet announces the VORBIS_COMMENT and PADDING packets
+        assert_eq!(pages[0].header_type, HEADER_TYPE_BOS);
+        assert_eq!(pages[0].serial_number, 7);
+        assert_eq!(pages[0].lacing_values, vec![51]);
+        assert_eq!(&pages[0].body[..13], b"\x7fFLAC\x01\0\0\x02fLaC");
+
+        // The PADDING packet is too long for the rest of the second page
+        assert_eq!((pages[1].header_type, pages[1].granule_position), (0, 0));
+        assert_eq!(pages[1].lacing_values.len(), 255);
+        assert_eq!((pages[2].header_type, pages[2].granule_position), (HEADER_TYPE_CONTINUED, 0));
+        assert_eq!(pages[1].body.len() + pages[2].body.len(), 4 + VorbisComment::default().build_bytes().len() + 4 + 100_000);
+
+        // Frames start on a new page, and the last page ends with the last sample
+        assert_eq!(pages[3].header_type, 0);
+        assert_eq!(pages[3].granule_position % 4096, 0);
+        let last_page = pages.last().unwrap();
+        assert_eq!(last_page.header_type, HEADER_TYPE_EOS);
+        assert_eq!(last_page.granule_position, summary.streaminfo.total_samples);
+        assert_eq!(summary.streaminfo.total_samples, 140928);
+    }
+
+    #[test]
+    fn ogg_vorbis_comment_first() {
+        // The mapping requires a VORBIS_COMMENT packet even if none is configured
+        let wav_path = "sample_audio/m_3s/music_3s.wav";
+        let mut out = io::Cursor::new(Vec::new());
+        FlacWriter::write_from_wave_ogg(WaveReader::open_pcm(wav_path).unwrap(), &mut out, 7, &EncoderConfig::default()).unwrap();
+        let out = out.into_inner();
+
+        let packets = ogg::read_packets(&out).unwrap();
+        let (num_header_packets, streaminfo_block) = ogg::read_mapping_packet(&packets[0].data).unwrap();
+        assert_eq!(num_header_packets, 2);
+        assert_eq!(streaminfo_block[0], BLOCK_TYPE_STREAMINFO);
+        assert_eq!(packets[1].data[0], BLOCK_TYPE_VORBIS_COMMENT);
+        assert_eq!(VorbisComment::from_bytes(&packets[1].data[4..]), Some(VorbisComment::default()));
+        assert_eq!(packets[2].data[0], 0x80 | BLOCK_TYPE_PADDING);
+        assert_eq!(FlacReader::decode_ogg_bytes(&out).unwrap().samples, read_wav_samples(wav_path));
+    }
+
+    macro_rules! padding_tests {
+        ($($name:ident: $value:expr,)*) => {
+        $(
+            #[test]
+            fn $name() {
+                let (padding, vorbis_comment, expected_types, expected_padding_len): (Padding, Option <VorbisComment>, Vec <u8>, Option <usize>) = $value;
+                let mut builder = EncoderConfig::builder().padding(padding);
+                if let Some(vorbis_comment) = vorbis_comment {
+                    builder = builder.vorbis_comment(vorbis_comment);
+                }
+
+                let (_, bytes, decoded) = encode_and_decode("sample_audio/m_3s/music_3s.wav", &builder.build().unwrap());
+
+                // Exactly the last metadata block header has the last-block flag set
+                let headers = metadata_block_headers(&bytes);
+                let flags: Vec <bool> = headers.iter().map(|(is_last, _, _)| *is_last).collect();
+                let types: Vec <u8> = headers.iter().map(|(_, block_type, _)| *block_type).collect();
+                assert_eq!(flags.iter().filter(|is_last| **is_last).count(), 1);
+                assert_eq!(flags.last(), Some(&true));
+                assert_eq!(types, expected_types);
+
+                let padding_block = decoded.metadata_blocks.iter().find(|block| block.block_type == BLOCK_TYPE_PADDING);
+                assert_eq!(padding_block.map(|block| block.data.len()), expected_padding_len);
+                assert!(padding_block.map_or(true, |block| block.data.iter().all(|byte| *byte == 0)));
+            }
+        )*
+        }
+    }
+
+    padding_tests! {
+        padding_default: (Padding::Bytes(8192), None, vec![BLOCK_TYPE_STREAMINFO, BLOCK_TYPE_PADDING], Some(8192)),
+        padding_none: (Padding::None, None, vec![BLOCK_TYPE_STREAMINFO], None),
+        padding_empty: (Padding::Bytes(0), None, vec![BLOCK_TYPE_STREAMINFO, BLOCK_TYPE_PADDING], Some(0)),
+        padding_expected_tags: (
+            Padding::ExpectedTags(1000),
+            Some(VorbisComment::new("vendor").with_field("TITLE", "Music").unwrap()),
+            vec![BLOCK_TYPE_STREAMINFO, BLOCK_TYPE_VORBIS_COMMENT, BLOCK_TYPE_PADDING],
+            Some(1000 - (4 + 4 + 6 + 4 + 4 + 11)),
+        ),
+        padding_expected_tags_exceeded: (
+            Padding::ExpectedTags(20),
+            Some(VorbisComment::new("vendor").with_field("TITLE", "Music").unwrap()),
+            vec![BLOCK_TYPE_STREAMINFO, BLOCK_TYPE_VORBIS_COMMENT],
+            None,
+        ),
+    }
+
+    #[test]
+    fn pictures_extracted() {
+        let mut png = b"\x89PNG\r\n\x1a\n\0\0\0\x0dIHDR\0\0\x02\x58\0\0\x02\x58\x08\x02\0\0\0".to_vec();
+        png.extend_from_slice(&[0x12, 0x34, 0x56, 0x78]);
+        let gif = b"GIF89a\x20\0\x20\0\x80\0\0\0\0\0\xff\xff\xff".to_vec();
+        let front_cover = FlacPicture::from_image(PICTURE_TYPE_FRONT_COVER, "Front", png).unwrap();
+        let icon = FlacPicture::from_image(PICTURE_TYPE_OTHER_FILE_ICON, "Icon", gif).unwrap();
+        let config = EncoderConfig::builder()
+            .vorbis_comment(VorbisComment::default())
+            .picture(front_cover.clone())
+            .picture(icon.clone())
+            .build()
+            .unwrap();
+        let (_, bytes, decoded) = encode_and_decode("sample_audio/ietf/ietf_01.wav", &config);
+
+        let block_types: Vec <u8> = decoded.metadata_blocks.iter().map(|block| block.block_type).collect();
+        assert_eq!(block_types, vec![BLOCK_TYPE_VORBIS_COMMENT, BLOCK_TYPE_PICTURE, BLOCK_TYPE_PICTURE, BLOCK_TYPE_PADDING]);
+        assert_eq!(FlacReader::read_pictures(&bytes).unwrap(), vec![front_cover.clone(), icon.clone()]);
+
+        // Only extracting the pictures goes through files
+        let flac_file = TempPath::new("pictures_extracted.flac");
+        let out_dir = TempPath::new("pictures_extracted");
+        std::fs::write(flac_file.as_str(), &bytes).unwrap();
+        std::fs::create_dir_all(out_dir.as_str()).unwrap();
+        let paths = FlacReader::extract_pictures(flac_file.as_str(), out_dir.as_str()).unwrap();
+        let extracted: Vec <Vec <u8>> = paths.iter()
+            .map(|path| std::fs::read(path).unwrap())
+            .collect();
+
+        assert!(paths[0].ends_with("picture_1_type_3.png"));
+        assert!(paths[1].ends_with("picture_2_type_2.gif"));
+        assert_eq!(extracted, vec![front_cover.data, icon.data]);
+    }
+
+    macro_rules! md5_layout_tests {
+        ($($name:ident: $value:expr,)*) => {
+        $(
+            #[test]
+            fn $name() {
+                let (bit_depth, bytes_per_sample) = $value;
+                let max = (1i64 << (bit_depth - 1)) - 1;
+                let samples: Vec <Vec <i64>> = (0..1000)
+                    .map(|i| vec![(i * 37) % max - max / 2, -max - 1 + i])
+                    .collect();
+                let wav_file = create_temp_wav(&format!("{}.wav", stringify!($name)), 44100, bit_depth, &samples);
+
+                // Signed little-endian samples, channels interleaved
+                let mut expected = Vec::new();
+                for sample in samples.iter() {
+                    for channel_sample in sample.iter() {
+                        expected.extend_from_slice(&channel_sample.to_le_bytes()[..bytes_per_sample]);
+                    }
+                }
+
+                let (summary, _, decoded) = encode_and_decode(wav_file.as_str(), &EncoderConfig::default());
+                assert_eq!(summary.md5, md5::compute(&expected).0);
+                assert_eq!(summary.streaminfo.md5, summary.md5);
+                assert_eq!(decoded.streaminfo.md5, summary.md5);
+            }
+        )*
+        }
     }
-}
\ No newline at end of file
+
+    md5_layout_tests! {
+        md5_layout_8_bit: (8, 1),
+        md5_layout_16_bit: (16, 2),
+        md5_layout_24_bit: (24, 3),
+    }
+
+    #[test]
+    fn md5_hex() {
+        let wav = WaveReader::open_pcm("sample_audio/ietf/ietf_01.wav").unwrap();
+        let mut out = io::Cursor::new(Vec::new());
+        let summary = FlacWriter::write_from_wave_to(wav, &mut out, &EncoderConfig::default()).unwrap();
+        let reference = FlacReader::open("sample_audio/ietf/ietf_01.flac").unwrap();
+
+        let expected: String = reference.streaminfo.md5.iter().map(|byte| format!("{:02x}", byte)).collect();
+        assert_eq!(summary.md5_hex(), expected);
+        assert_eq!(summary.md5_hex().len(), 32);
+    }
+
+    macro_rules! config_tests {
+        ($($name:ident: $value:expr,)*) => {
+        $(
+            #[test]
+            fn $name() {
+                let config: EncoderConfig = $value;
+                let (_, _, decoded) = encode_and_decode("sample_audio/m_3s/music_3s.wav", &config);
+
+                assert_eq!(decoded.streaminfo.max_block_size, config.block_size());
+            }
+        )*
+        }
+    }
+
+    config_tests! {
+        config_preset_0: EncoderConfig::preset(0).unwrap(),
+        config_preset_3: EncoderConfig::preset(3).unwrap(),
+        config_preset_8: EncoderConfig::preset(8).unwrap(),
+        config_overrides: EncoderConfig::builder()
+            .block_size(1000)
+            .max_lpc_order(4)
+            .qlp_precision(9)
+            .min_partition_order(2)
+            .max_partition_order(3)
+            .exhaustive_model_search(true)
+            .build()
+            .unwrap(),
+    }
+
+    macro_rules! stereo_mode_tests {
+        ($($name:ident: $value:expr,)*) => {
+        $(
+            #[test]
+            fn $name() {
+                let config = EncoderConfig::builder().stereo_mode($value).build().unwrap();
+                encode_and_decode("sample_audio/m_3s/music_3s.wav", &config);
+            }
+        )*
+        }
+    }
+
+    stereo_mode_tests! {
+        stereo_independent: StereoMode::Independent,
+        stereo_adaptive_mid_side: StereoMode::AdaptiveMidSide,
+        stereo_forced_left_side: StereoMode::Forced(FlacChannelAssignment::LeftSide),
+        stereo_forced_right_side: StereoMode::Forced(FlacChannelAssignment::RightSide),
+        stereo_forced_mid_side: StereoMode::Forced(FlacChannelAssignment::MidSide),
+    }
+
+    macro_rules! square_wave_32_bit_tests {
+        ($($name:ident: $value:expr,)*) => {
+        $(
+            #[test]
+            fn $name() {
+                // Full-scale square waves of opposite phases, whose side channel needs 33 bits
+                // and whose residuals overflow the 32-bit range allowed by FLAC
+                let (half_period, config): (i64, EncoderConfig) = $value;
+                let samples: Vec <Vec <i64>> = (0..4096 * 3 + 100)
+                    .map(|i: i64| {
+                        let value = if (i / half_period) % 2 == 0 { i32::MAX as i64 } else { i32::MIN as i64 };
+                        vec![value, -1 - value]
+                    })
+                    .collect();
+                let wav_file = create_temp_wav(&format!("{}.wav", stringify!($name)), 96000, 32, &samples);
+                assert_eq!(read_wav_samples(wav_file.as_str()), samples);
+
+                let (_, _, decoded) = encode_and_decode(wav_file.as_str(), &config);
+                assert_eq!(decoded.streaminfo.bit_depth, 32);
+            }
+        )*
+        }
+    }
+
+    square_wave_32_bit_tests! {
+        square_wave_32_bit_default: (37, EncoderConfig::default()),
+        square_wave_32_bit_independent: (37, EncoderConfig::builder().stereo_mode(StereoMode::Independent).build().unwrap()),
+        square_wave_32_bit_left_side: (37, EncoderConfig::builder().stereo_mode(StereoMode::Forced(FlacChannelAssignment::LeftSide)).build().unwrap()),
+        square_wave_32_bit_right_side: (37, EncoderConfig::builder().stereo_mode(StereoMode::Forced(FlacChannelAssignment::RightSide)).build().unwrap()),
+        square_wave_32_bit_mid_side: (37, EncoderConfig::builder().stereo_mode(StereoMode::Forced(FlacChannelAssignment::MidSide)).build().unwrap()),
+        square_wave_32_bit_short_period: (1, EncoderConfig::default()),
+        square_wave_32_bit_exhaustive: (512, EncoderConfig::builder().preset(8).exhaustive_model_search(true).build().unwrap()),
+    }
+
+    #[test]
+    fn stereo_mid_side_is_smallest() {
+        let wav_path = "sample_audio/m_3s/music_3s.wav";
+        let mut sizes = Vec::new();
+        for stereo_mode in [StereoMode::Independent, StereoMode::AdaptiveMidSide, StereoMode::MidSide] {
+            let config = EncoderConfig::builder().stereo_mode(stereo_mode).build().unwrap();
+            let wav = WaveReader::open_pcm(wav_path).unwrap();
+            let mut out = io::Cursor::new(Vec::new());
+            FlacWriter::write_from_wave_to(wav, &mut out, &config).unwrap();
+            sizes.push(out.into_inner().len());
+        }
+
+        assert!(sizes[2] < sizes[0]);
+        assert!(sizes[2] <= sizes[1]);
+    }
+
+    #[test]
+    fn stereo_identical_channels() {
+        // Identical channels leave a silent side channel
+        let sample_block: Vec <Vec <i64>> = (0..256)
+            .map(|i| vec![(i * 37 % 101) - 50; 2])
+            .collect();
+        let mut frame = FlacFrame::new(
+            256,
+            FlacFrameHeaderValueOption::InFrame(44100),
+            2,
+            FlacFrameHeaderValueOption::InFrame(16),
+            0,
+        );
+        frame.build_bytes(&sample_block, &EncoderConfig::default());
+
+        assert_ne!(frame.channel_assignment, FlacChannelAssignment::Independent);
+        assert_eq!(frame.subframes.len(), 2);
+        assert!(frame.subframes.iter().any(|subframe| subframe.bit_depth == 17
+            && matches!(subframe.subframe_type, FlacSubframeType::Constant {value: 0})));
+    }
+
+    macro_rules! constant_subframe_tests {
+        ($($name:ident: $value:expr,)*) => {
+        $(
+            #[test]
+            fn $name() {
+                // Digital silence, then a DC offset, then music, each 4 blocks long
+                let config: EncoderConfig = $value;
+                let music = read_wav_samples("sample_audio/c/music_c_mono.wav");
+                let samples: Vec <Vec <i64>> = std::iter::repeat(vec![0]).take(4 * 4096)
+                    .chain(std::iter::repeat(vec![-5]).take(4 * 4096))
+                    .chain(music.into_iter().skip(44100).take(4 * 4096))
+                    .collect();
+                let wav_file = create_temp_wav(&format!("{}.wav", stringify!($name)), 44100, 16, &samples);
+
+                let (summary, _, _) = encode_and_decode(wav_file.as_str(), &config);
+                assert_eq!(summary.stats.num_constant_subframes, 8);
+            }
+        )*
+        }
+    }
+
+    constant_subframe_tests! {
+        constant_subframes_default: EncoderConfig::default(),
+        constant_subframes_exhaustive: EncoderConfig::builder().exhaustive_model_search(true).build().unwrap(),
+    }
+
+    macro_rules! precision_search_tests {
+        ($($name:ident: $value:expr,)*) => {
+        $(
+            #[test]
+            fn $name() {
+                let (builder, expected_precisions): (config::EncoderConfigBuilder, std::ops::RangeInclusive <u8>) = $value;
+                let (summary, _, _) = encode_and_decode("sample_audio/m_3s/music_3s.wav", &builder.build().unwrap());
+
+                assert!(!summary.stats.lpc_subframes.is_empty());
+                for lpc_stats in summary.stats.lpc_subframes.iter() {
+                    assert!(expected_precisions.contains(&lpc_stats.precision));
+                    assert!(lpc_stats.shift <= 15);
+                    assert!(lpc_stats.order >= 1 && lpc_stats.order <= 8);
+                }
+            }
+        )*
+        }
+    }
+
+    precision_search_tests! {
+        precision_fixed: (EncoderConfig::builder().qlp_precision(9), 9..=9),
+        precision_around: (EncoderConfig::builder().qlp_precision(9).qlp_precision_search(QlpPrecisionSearch::Around(2)), 7..=11),
+        precision_all: (EncoderConfig::builder().qlp_precision_search(QlpPrecisionSearch::All), 5..=15),
+    }
+
+    #[test]
+    fn precision_around_clamped() {
+        let config = EncoderConfig::builder()
+            .qlp_precision(6)
+            .qlp_precision_search(QlpPrecisionSearch::Around(4))
+            .build()
+            .unwrap();
+        assert_eq!(FlacSubframe::qlp_precisions(16, 4096, &config), (5..=10).collect::<Vec <u32>>());
+
+        // Low bit depths get a best precision below the allowed range
+        let config = EncoderConfig::builder().qlp_precision_search(QlpPrecisionSearch::Around(0)).build().unwrap();
+        assert_eq!(FlacSubframe::qlp_precisions(4, 4096, &config), vec![5]);
+    }
+
+    #[test]
+    fn precision_search_is_smaller() {
+        let wav_file = create_excerpt_wav("precision_search_is_smaller.wav", "sample_audio/m_3s/music_3s.wav", 4 * 4096);
+        let mut sizes = Vec::new();
+        for qlp_precision_search in [QlpPrecisionSearch::Off, QlpPrecisionSearch::All] {
+            let config = EncoderConfig::builder()
+                .qlp_precision_search(qlp_precision_search)
+                .exhaustive_model_search(true)
+                .build()
+                .unwrap();
+            let (summary, bytes, _) = encode_and_decode(wav_file.as_str(), &config);
+
+            assert_eq!(summary.stats.frame_block_sizes, vec![4096; 4]);
+            sizes.push(bytes.len());
+        }
+
+        assert!(sizes[1] <= sizes[0]);
+    }
+
+    #[test]
+    fn apodization_windows() {
+        // With an exact search, more windows can only give more candidates
+        let wav_file = create_excerpt_wav("apodization_windows.wav", "sample_audio/m_3s/music_3s.wav", 4 * 4096);
+        let mut sizes = Vec::new();
+        for spec in ["rectangle", "tukey(0.5)", "rectangle;tukey(0.5);partial_tukey(2);punchout_tukey(3)"] {
+            let config = EncoderConfig::builder()
+                .apodization(spec)
+                .exhaustive_model_search(true)
+                .build()
+                .unwrap();
+            let (_, bytes, _) = encode_and_decode(wav_file.as_str(), &config);
+            sizes.push(bytes.len());
+        }
+
+        assert!(sizes[2] <= sizes[0]);
+        assert!(sizes[2] <= sizes[1]);
+    }
+
+    #[test]
+    fn exhaustive_estimate_is_exact() {
+        let config = EncoderConfig::builder().exhaustive_model_search(true).build().unwrap();
+        let samples: Vec <i64> = read_wav_samples("sample_audio/m_3s/music_3s.wav")
+            .into_iter()
+            .skip(20000)
+            .take(4096)
+            .map(|sample| sample[0])
+            .collect();
+
+        let mut candidates = vec![FlacSubframe::new_verbatim(16), FlacSubframe::new_constant(16, samples[0])];
+        candidates.extend((0..=4).map(|order| FlacSubframe::new_fixed_by_order(16, order)));
+        let window_weights = FlacSubframe::window_weights(samples.len(), &config);
+        candidates.extend(FlacSubframe::new_variable_candidates(16, 4096, &samples, &window_weights, &config));
+        for subframe in candidates {
+            let (bytes, extra_bits_len) = subframe.build_bytes(&samples, &config);
+            let bits = 8 * bytes.len() as u64 - extra_bits_len as u64;
+            assert_eq!(subframe.estimate_bits(&samples, &config), Some(bits));
+        }
+    }
+
+    #[test]
+    fn exhaustive_model_search_is_smaller() {
+        let wav_path = "sample_audio/m_3s/music_3s.wav";
+        let mut sizes = Vec::new();
+        for exhaustive_model_search in [false, true] {
+            let config = EncoderConfig::builder().exhaustive_model_search(exhaustive_model_search).build().unwrap();
+            let (_, bytes, _) = encode_and_decode(wav_path, &config);
+            sizes.push(bytes.len());
+        }
+
+        assert!(sizes[1] <= sizes[0]);
+    }
+
+    #[test]
+    fn variable_block_size_transients() {
+        // Short clicks over silence favor frames much shorter than 4096 samples
+        let samples: Vec <Vec <i64>> = (0..4096 * 8)
+            .map(|i: i64| {
+                let value = if i % 3000 < 64 { ((i * 7919) % 20001) - 10000 } else { 0 };
+                vec![value]
+            })
+            .collect();
+        let wav_file = create_temp_wav("variable_block_size_transients.wav", 44100, 16, &samples);
+
+        let mut sizes = Vec::new();
+        let mut streams = Vec::new();
+        for variable_block_size in [false, true] {
+            let config = EncoderConfig::builder().variable_block_size(variable_block_size).build().unwrap();
+            let (_, bytes, decoded) = encode_and_decode(wav_file.as_str(), &config);
+            sizes.push(bytes.len());
+            streams.push(decoded);
+        }
+
+        assert!(sizes[1] < sizes[0] * 9 / 10);
+        let streaminfo = &streams[1].streaminfo;
+        assert!(streaminfo.min_block_size < streaminfo.max_block_size);
+        assert!(streaminfo.min_block_size >= 4096 >> MAX_BLOCK_SPLITS);
+    }
+
+    #[test]
+    fn variable_block_size_in_stream() {
+        // The last block is short enough to be split as well
+        let samples: Vec <Vec <i64>> = (0..4096 * 8 + 1000)
+            .map(|i: i64| {
+                let value = if i % 3000 < 64 { ((i * 7919) % 20001) - 10000 } else { 0 };
+                vec![value]
+            })
+            .collect();
+        let wav_file = create_temp_wav("variable_block_size_in_stream.wav", 44100, 16, &samples);
+
+        let config = EncoderConfig::builder().variable_block_size(true).build().unwrap();
+        let (summary, _, decoded) = encode_and_decode_with(wav_file.as_str(), |wav, out| {
+            FlacWriter::write_from_wave_stream(wav, out, FlacStreamInfoMode::Unknown, &config)
+        });
+
+        let streaminfo = &decoded.streaminfo;
+        assert_eq!(streaminfo.min_block_size, 125);
+        assert_eq!(summary.streaminfo.min_block_size, streaminfo.min_block_size);
+        let (_, frame_block_sizes) = summary.stats.frame_block_sizes.split_last().unwrap();
+        assert!(frame_block_sizes.iter().all(|&size| size >= streaminfo.min_block_size));
+        assert!(frame_block_sizes.iter().any(|&size| size < streaminfo.max_block_size));
+    }
+
+    #[test]
+    fn escape_noise_burst() {
+        // A 12-bit noise burst between quiet passages is stored in escaped partitions
+        let mut seed: u64 = 164;
+        let samples: Vec <Vec <i64>> = (0..4096 * 4)
+            .map(|i: i64| {
+                seed = seed.wrapping_mul(6364136223846793005).wrapping_add(1442695040888963407);
+                let value = if (4096..8192).contains(&i) { (seed >> 52) as i64 - 2048 } else { (i % 64) - 32 };
+                vec![value]
+            })
+            .collect();
+        let wav_file = create_temp_wav("escape_noise_burst.wav", 44100, 16, &samples);
+
+        let (_, _, decoded) = encode_and_decode(wav_file.as_str(), &EncoderConfig::default());
+        assert!(decoded.streaminfo.max_frame_size < 4096 * 13 / 8);
+    }
+
+    #[test]
+    fn rice2_24_bit_noise() {
+        // Noise of mixed scales up to 2^20 needs Rice parameters beyond 14
+        let mut seed: u64 = 164;
+        let samples: Vec <Vec <i64>> = (0..4096 * 2)
+            .map(|_| {
+                seed = seed.wrapping_mul(6364136223846793005).wrapping_add(1442695040888963407);
+                let magnitude = ((seed >> 44) as i64) >> ((seed >> 8) % 4);
+                vec![if seed & 1 == 0 { magnitude } else { -magnitude }]
+            })
+            .collect();
+        let wav_file = create_temp_wav("rice2_24_bit_noise.wav", 48000, 24, &samples);
+
+        let config = EncoderConfig::default();
+        let channel: Vec <i64> = samples.iter().take(4096).map(|sample| sample[0]).collect();
+        let (_, _, method) = FlacSubframe::new_fixed_by_order(24, 0)
+            .get_encoded_residuals(&channel, &config)
+            .unwrap();
+        assert_eq!(method, RiceCodingMethod::Rice2);
+
+        let (_, _, decoded) = encode_and_decode(wav_file.as_str(), &config);
+        assert!(decoded.streaminfo.max_frame_size < 4096 * 21 / 8);
+    }
+
+    #[test]
+    fn wasted_bits_ietf_01() {
+        // The left sample has 2 wasted bits and the right sample has 4 wasted bits
+        let config = EncoderConfig::default();
+        let (left, left_bytes, left_extra_bits_len) = FlacSubframe::new_best(16, 1, &[0x63f4], &FlacSubframe::window_weights(1, &config), &config);
+        let (right, right_bytes, right_extra_bits_len) = FlacSubframe::new_best(16, 1, &[0x28b0], &FlacSubframe::window_weights(1, &config), &config);
+
+        assert_eq!((left.wasted_bits, left.bit_depth), (2, 14));
+        assert_eq!((left_bytes, left_extra_bits_len), (vec![0x03, 0x58, 0xfd], 0));
+        assert_eq!((right.wasted_bits, right.bit_depth), (4, 12));
+        assert_eq!((right_bytes, right_extra_bits_len), (vec![0x03, 0x12, 0x8b], 0));
+    }
+
+    #[test]
+    fn wasted_bits_frame_ietf_01() {
+        let mut frame = FlacFrame::new(
+            1,
+            FlacFrameHeaderValueOption::Streaminfo(44100),
+            2,
+            FlacFrameHeaderValueOption::Streaminfo(16),
+            0,
+        );
+        let bytes = frame.build_bytes(&[vec![0x63f4, 0x28b0]], &EncoderConfig::default());
+        let reference = std::fs::read("sample_audio/ietf/ietf_01.flac").unwrap();
+
+        assert_eq!(frame.channel_assignment, FlacChannelAssignment::Independent);
+        assert_eq!(bytes, reference[0x2a..]);
+    }
+
+    #[test]
+    fn wasted_bits_padded_24_bit() {
+        // 16-bit content padded with zeros into 24 bits takes no more space than the 16-bit content
+        let samples: Vec <Vec <i64>> = read_wav_samples("sample_audio/c/music_c_mono.wav")
+            .into_iter()
+            .take(4 * 4096)
+            .collect();
+        let padded: Vec <Vec <i64>> = samples.iter()
+            .map(|sample| sample.iter().map(|value| value << 8).collect())
+            .collect();
+        let mut sizes = Vec::new();
+        for (bit_depth, samples) in [(16, &samples), (24, &padded)] {
+            let wav_file = create_temp_wav(&format!("wasted_bits_padded_{}_bit.wav", bit_depth), 44100, bit_depth, samples);
+            let (_, bytes, _) = encode_and_decode(wav_file.as_str(), &EncoderConfig::default());
+            sizes.push(bytes.len());
+        }
+
+        // Each subframe header takes up 8 more bits for the unary-coded shift
+        assert!(sizes[1] <= sizes[0] + 4);
+    }
+
+    #[test]
+    fn reduce_bit_depth_padded_24_bit() {
+        // 16-bit content padded with zeros into 24 bits encodes the same as the 16-bit content
+        let samples: Vec <Vec <i64>> = read_wav_samples("sample_audio/m_3s/music_3s.wav")
+            .into_iter()
+            .take(2 * 4096 + 100)
+            .collect();
+        let padded: Vec <Vec <i64>> = samples.iter()
+            .map(|sample| sample.iter().map(|value| value << 8).collect())
+            .collect();
+        let config = EncoderConfig::builder().reduce_bit_depth(true).build().unwrap();
+        let mut streams = Vec::new();
+        for (bit_depth, samples) in [(16, &samples), (24, &padded)] {
+            let wav_file = create_temp_wav(&format!("reduce_bit_depth_padded_{}_bit.wav", bit_depth), 44100, bit_depth, samples);
+            let wav = WaveReader::open_pcm(wav_file.as_str()).unwrap();
+            let mut out = io::Cursor::new(Vec::new());
+            let summary = FlacWriter::write_from_wave_to(wav, &mut out, &config).unwrap();
+
+            assert_eq!(summary.streaminfo.bit_depth, 16);
+            streams.push(out.into_inner());
+        }
+
+        assert_eq!(streams[0], streams[1]);
+        assert_eq!(FlacReader::decode_bytes(&streams[1]).unwrap().samples, samples);
+    }
+
+    #[test]
+    fn reduce_bit_depth_opt_in() {
+        let wav_path = "sample_audio/ietf/ietf_01.wav";
+        let mut bit_depths = Vec::new();
+        for reduce_bit_depth in [false, true] {
+            let config = EncoderConfig::builder().reduce_bit_depth(reduce_bit_depth).build().unwrap();
+            let wav = WaveReader::open_pcm(wav_path).unwrap();
+            let mut out = io::Cursor::new(Vec::new());
+            let summary = FlacWriter::write_from_wave_to(wav, &mut out, &config).unwrap();
+            let decoded = FlacReader::decode_bytes(out.get_ref()).unwrap();
+
+            assert_eq!(decoded.streaminfo.bit_depth, summary.streaminfo.bit_depth);
+            bit_depths.push(summary.streaminfo.bit_depth);
+        }
+
+        assert_eq!(bit_depths, vec![16, 14]);
+    }
+
+    macro_rules! threads_tests {
+        ($($name:ident: $value:expr,)*) => {
+        $(
+            #[test]
+            fn $name() {
+                // Every number of threads gives the same output as a single thread
+                let builder: config::EncoderConfigBuilder = $value;
+                let wav_file = create_excerpt_wav(&format!("{}.wav", stringify!($name)), "sample_audio/m_3s/music_3s.wav", 9 * 4096 + 100);
+
+                let mut streams = Vec::new();
+                let mut summaries = Vec::new();
+                for (threads, max_frames_in_flight) in [(1, 2), (2, 1), (2, 4), (4, 8), (7, 3)] {
+                    let config = builder.clone().threads(threads).max_frames_in_flight(max_frames_in_flight).build().unwrap();
+                    let (summary, bytes, _) = encode_and_decode(wav_file.as_str(), &config);
+                    summaries.push(summary);
+                    streams.push(bytes);
+                }
+
+                for (stream, summary) in streams.iter().zip(summaries.iter()).skip(1) {
+                    assert_eq!(stream, &streams[0]);
+                    assert_eq!(summary.streaminfo, summaries[0].streaminfo);
+                    assert_eq!(summary.stats, summaries[0].stats);
+                }
+            }
+        )*
+        }
+    }
+
+    threads_tests! {
+        threads_fixed_block_size: EncoderConfig::builder(),
+        threads_variable_block_size: EncoderConfig::builder().variable_block_size(true),
+        threads_preset_0: EncoderConfig::builder().preset(0).block_size(1000),
+    }
+
+    #[test]
+    fn threads_stream_output() {
+        let wav_path = "sample_audio/c/music_c_mono.wav";
+        let mut streams = Vec::new();
+        for threads in [1, 3] {
+            let config = EncoderConfig::builder().threads(threads).build().unwrap();
+            let wav = WaveReader::open_pcm(wav_path).unwrap();
+            let mut out = Vec::new();
+            FlacWriter::write_from_wave_stream(wav, &mut out, FlacStreamInfoMode::FromWaveSizes, &config).unwrap();
+            streams.push(out);
+        }
+
+        assert_eq!(streams[0], streams[1]);
+        assert_eq!(FlacReader::decode_bytes(&streams[1]).unwrap().samples, read_wav_samples(wav_path));
+    }
+
+    macro_rules! verify_tests {
+        ($($name:ident: $value:expr,)*) => {
+        $(
+            #[test]
+            fn $name() {
+                let builder: config::EncoderConfigBuilder = $value;
+                encode_and_decode("sample_audio/m_3s/music_3s.wav", &builder.verify(true).build().unwrap());
+            }
+        )*
+        }
+    }
+
+    verify_tests! {
+        verify_default: EncoderConfig::builder(),
+        verify_variable_block_size: EncoderConfig::builder().preset(1).variable_block_size(true),
+        verify_threads: EncoderConfig::builder().preset(3).threads(3),
+    }
+
+    #[test]
+    fn verify_frame_mismatch() {
+        let block: Vec <Vec <i64>> = (0..256)
+            .map(|i: i64| vec![(i * 37 % 101) - 50, (i * 53 % 89) - 44])
+            .collect();
+        let streaminfo = FlacStreamInfo {
+            min_block_size: 256,
+            max_block_size: 256,
+            min_frame_size: 0,
+            max_frame_size: 0,
+            sample_rate: 44100,
+            num_channels: 2,
+            bit_depth: 16,
+            total_samples: 0,
+            md5: [0; 16],
+        };
+        let (frame_bytes, frame) = FlacWriter::build_frame(&block, 5, false, &streaminfo, &EncoderConfig::default());
+        assert!(FlacWriter::verify_frame(&frame_bytes, frame.frame_index, &block, &streaminfo).is_ok());
+
+        let mut changed_block = block.clone();
+        changed_block[37][1] += 1;
+        changed_block[200][0] += 1;
+        let result = FlacWriter::verify_frame(&frame_bytes, frame.frame_index, &changed_block, &streaminfo);
+        assert!(matches!(result, Err(FlacWriterError::VerifyError {frame_index: 5, channel: 1, sample_index: 37})));
+
+        // A corrupted frame fails its CRC check and cannot be decoded
+        let mut corrupted_bytes = frame_bytes.clone();
+        corrupted_bytes[20] ^= 0x10;
+        let result = FlacWriter::verify_frame(&corrupted_bytes, frame.frame_index, &block, &streaminfo);
+        assert!(matches!(result, Err(FlacWriterError::VerifyDecodeError {frame_index: 5, error: FlacReaderError::CrcError})));
+    }
+
+    #[test]
+    fn frame_header_ietf_01() {
+        let frame = FlacFrame::new(
+            1,
+            FlacFrameHeaderValueOption::InFrame(44100),
+            2,
+            FlacFrameHeaderValueOption::InFrame(16),
+            0,
+        );
+
+        assert_eq!(frame.build_header_bytes(), vec![0xff, 0xf8, 0x69, 0x18, 0x00, 0x00, 0xbf]);
+    }
+}
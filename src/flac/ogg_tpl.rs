@@ -0,0 +1,489 @@
+use std::io::{self, Write};
+use std::mem;
+
+use crate::flac::encoder::crc::CrcOptions;
+
+/// Flag of a page whose first packet continues from the previous page
+pub const HEADER_TYPE_CONTINUED: u8 = 0x01;
+
+/// Flag of the first page of a logical stream
+pub const HEADER_TYPE_BOS: u8 = 0x02;
+
+/// Flag of the last page of a logical stream
+pub const HEADER_TYPE_EOS: u8 = 0x04;
+
+/// Granule position of a page on which no packet ends
+pub const NO_GRANULE_POSITION: u64 = u64::MAX;
+
+/// First byte of the mapping header packet of Ogg FLAC
+const MAPPING_PACKET_TYPE: u8 = 0x7f;
+
+/// Major and minor version of the FLAC-in-Ogg mapping
+const MAPPING_VERSION: [u8; 2] = [1, 0];
+
+/// Length of the mapping header packet, which holds the STREAMINFO block with its header
+const MAPPING_PACKET_LEN: usize = 51;
+
+/// Largest number of lacing values of a page
+const MAX_SEGMENTS: usize = 255;
+
+/// Length of a page body from which the page is written out
+const TARGET_BODY_LEN: usize = 4096;
+
+/// Length of a page header without its lacing values
+const PAGE_HEADER_LEN: usize = 27;
+
+/// Represents a page of an Ogg stream
+///
+/// A packet is split into segments of 255 bytes followed by one shorter segment,
+/// which may be empty, and each segment has a lacing value holding its length. A
+/// packet whose last lacing value on a page is 255 continues on the next page. The
+/// granule position is the one of the last packet that ends on the page.
+#[derive(Clone, Debug, PartialEq)]
+pub struct OggPage {
+    pub header_type: u8,
+    pub granule_position: u64,
+    pub serial_number: u32,
+    pub sequence_number: u32,
+    pub lacing_values: Vec <u8>,
+    pub body: Vec <u8>,
+}
+
+/// Represents a packet read from an Ogg stream
+///
+/// The granule position is the one of the page on which the packet ends.
+#[derive(Clone, Debug, PartialEq)]
+pub struct OggPacket {
+    pub data: Vec <u8>,
+    pub granule_position: u64,
+}
+
+/// Represents a writer of the packets of a single logical Ogg stream
+///
+/// A page is written out once its body reaches 4 KiB or it runs out of lacing
+/// values, or when `flush` is called. The last page is held back until the next
+/// one is written so that `finish` can mark it as the end of the stream.
+pub struct OggWriter <W: Write> {
+    out: W,
+    serial_number: u32,
+    next_sequence_number: u32,
+    page: OggPage,
+    held_page: Option <OggPage>,
+}
+
+impl OggPage {
+    /// Create an empty page of a logical stream
+    fn new(header_type: u8, serial_number: u32) -> Self {
+        OggPage {
+            header_type,
+            granule_position: NO_GRANULE_POSITION,
+            serial_number,
+            sequence_number: 0,
+            lacing_values: Vec::new(),
+            body: Vec::new(),
+        }
+    }
+
+    /// Check whether the first packet of this page continues from the previous page
+    pub fn is_continued(&self) -> bool {
+        self.header_type & HEADER_TYPE_CONTINUED != 0
+    }
+
+    /// Check whether this page is the last page of its logical stream
+    pub fn is_eos(&self) -> bool {
+        self.header_type & HEADER_TYPE_EOS != 0
+    }
+
+    /// Convert this page into its byte representation
+    ///
+    /// The CRC-32 of the page is computed over the whole page with the CRC field
+    /// set to zero, and stored as a little-endian number like the other fields.
+    pub fn build_bytes(&self) -> Vec <u8> {
+        let mut bytes = Vec::with_capacity(PAGE_HEADER_LEN + self.lacing_values.len() + self.body.len());
+        bytes.extend_from_slice(b"OggS");
+        bytes.push(0);
+        bytes.push(self.header_type);
+        bytes.extend_from_slice(&self.granule_position.to_le_bytes());
+        bytes.extend_from_slice(&self.serial_number.to_le_bytes());
+        bytes.extend_from_slice(&self.sequence_number.to_le_bytes());
+        bytes.extend_from_slice(&[0; 4]);
+        bytes.push(self.lacing_values.len() as u8);
+        bytes.extend_from_slice(&self.lacing_values);
+        bytes.extend_from_slice(&self.body);
+
+        let crc = CrcOptions::new(0x04c1_1db7u32, 32).build_crc32(&bytes);
+        bytes[22..26].copy_from_slice(&crc.to_le_bytes());
+        bytes
+    }
+
+    /// Read a page from the start of `data`
+    ///
+    /// This method returns the page and its length in bytes, respectively.
+    ///
+    /// # Errors
+    /// Returns `None` if `data` does not start with the `OggS` capture pattern of
+    /// version 0, the page is truncated, or its CRC-32 does not match.
+    pub fn from_bytes(data: &[u8]) -> Option <(Self, usize)> {
+        let header = data.get(..PAGE_HEADER_LEN)?;
+        if &header[..4] != b"OggS" || header[4] != 0 {
+            return None;
+        }
+
+        let num_segments = header[26] as usize;
+        let lacing_values = data.get(PAGE_HEADER_LEN..PAGE_HEADER_LEN + num_segments)?.to_vec();
+        let body_start = PAGE_HEADER_LEN + num_segments;
+        let body_len: usize = lacing_values.iter().map(|&lacing_value| lacing_value as usize).sum();
+        let body = data.get(body_start..body_start + body_len)?.to_vec();
+
+        let page = OggPage {
+            header_type: header[5],
+            granule_position: u64::from_le_bytes(header[6..14].try_into().unwrap()),
+            serial_number: u32::from_le_bytes(header[14..18].try_into().unwrap()),
+            sequence_number: u32::from_le_bytes(header[18..22].try_into().unwrap()),
+            lacing_values,
+            body,
+        };
+        if page.build_bytes()[22..26] != header[22..26] {
+            return None;
+        }
+
+        Some((page, body_start + body_len))
+    }
+}
+
+impl <W: Write> OggWriter <W> {
+    /// Create a writer of the logical stream `serial_number`
+    pub fn new(out: W, serial_number: u32) -> Self {
+        OggWriter {
+            out,
+            serial_number,
+            next_sequence_number: 0,
+            page: OggPage::new(HEADER_TYPE_BOS, serial_number),
+            held_page: None,
+        }
+    }
+
+    /// Add a packet ending at `granule_position`
+    ///
+    /// The packet is continued on as many pages as needed.
+    ///
+    /// # Errors
+    /// Returns the error of the output if a page cannot be written.
+    pub fn write_packet(&mut self, packet: &[u8], granule_position: u64) -> io::Result <()> {
+        let mut segments = packet.chunks(255);
+        loop {
+            let segment = segments.next().unwrap_or(&[]);
+            self.page.lacing_values.push(segment.len() as u8);
+            self.page.body.extend_from_slice(segment);
+
+            // A packet ends with a segment shorter than 255 bytes
+            let is_packet_end = segment.len() < 255;
+            if is_packet_end {
+                self.page.granule_position = granule_position;
+            }
+            if self.page.lacing_values.len() == MAX_SEGMENTS {
+                self.write_page(!is_packet_end)?;
+            }
+            if is_packet_end {
+                break;
+            }
+        }
+
+        if self.page.body.len() >= TARGET_BODY_LEN {
+            self.write_page(false)?;
+        }
+        Ok(())
+    }
+
+    /// End the current page so that the next packet starts on a new page
+    ///
+    /// # Errors
+    /// Returns the error of the output if a page cannot be written.
+    pub fn flush(&mut self) -> io::Result <()> {
+        self.write_page(false)
+    }
+
+    /// Write the remaining pages, marking the last one as the end of the stream,
+    /// and get the output back
+    ///
+    /// # Errors
+    /// Returns the error of the output if a page cannot be written.
+    pub fn finish(mut self) -> io::Result <W> {
+        self.write_page(false)?;
+        if let Some(mut page) = self.held_page.take() {
+            page.header_type |= HEADER_TYPE_EOS;
+            self.out.write_all(&page.build_bytes())?;
+        }
+        Ok(self.out)
+    }
+
+    /// Close the current page unless it is empty, and write out the page held before it
+    ///
+    /// The new current page continues a packet from the closed page if `is_continued`.
+    fn write_page(&mut self, is_continued: bool) -> io::Result <()> {
+        if self.page.lacing_values.is_empty() {
+            return Ok(());
+        }
+
+        let header_type = if is_continued { HEADER_TYPE_CONTINUED } else { 0 };
+        let mut page = mem::replace(&mut self.page, OggPage::new(header_type, self.serial_number));
+        page.sequence_number = self.next_sequence_number;
+        self.next_sequence_number += 1;
+
+        if let Some(held_page) = self.held_page.replace(page) {
+            self.out.write_all(&held_page.build_bytes())?;
+        }
+        Ok(())
+    }
+}
+
+/// Build the first page of a logical stream holding only `packet`
+///
+/// This is the page that `OggWriter` writes when the first packet is shorter than
+/// 255 bytes and is flushed right away. Rebuilding it with a packet of the same
+/// length gives a page that can overwrite it, such as once STREAMINFO is complete.
+pub fn build_first_page(serial_number: u32, packet: &[u8]) -> Vec <u8> {
+    let mut page = OggPage::new(HEADER_TYPE_BOS, serial_number);
+    page.granule_position = 0;
+    page.lacing_values.push(packet.len() as u8);
+    page.body.extend_from_slice(packet);
+    page.build_bytes()
+}
+
+/// Build the mapping header packet that starts an Ogg FLAC stream
+///
+/// The packet holds the `0x7F` packet type, the `FLAC` signature, the mapping
+/// version 1.0, the number of header packets that follow it, the `fLaC` marker
+/// and the STREAMINFO block with its header, in that order. A number of header
+/// packets of zero means that the number is unknown.
+pub fn build_mapping_packet(streaminfo_block: &[u8], num_header_packets: u16) -> Vec <u8> {
+    let mut packet = Vec::with_capacity(MAPPING_PACKET_LEN);
+    packet.push(MAPPING_PACKET_TYPE);
+    packet.extend_from_slice(b"FLAC");
+    packet.extend_from_slice(&MAPPING_VERSION);
+    packet.extend_from_slice(&num_header_packets.to_be_bytes());
+    packet.extend_from_slice(b"fLaC");
+    packet.extend_from_slice(streaminfo_block);
+    packet
+}
+
+/// Read the mapping header packet of an Ogg FLAC stream
+///
+/// This method returns the number of header packets that follow and the STREAMINFO
+/// block with its header, respectively.
+///
+/// # Errors
+/// Returns `None` if the packet is not a mapping header packet of major version 1.
+pub fn read_mapping_packet(packet: &[u8]) -> Option <(u16, &[u8])> {
+    if packet.len() != MAPPING_PACKET_LEN || packet[0] != MAPPING_PACKET_TYPE
+        || &packet[1..5] != b"FLAC" || packet[5] != MAPPING_VERSION[0] || &packet[9..13] != b"fLaC" {
+        return None;
+    }
+    Some((u16::from_be_bytes([packet[7], packet[8]]), &packet[13..]))
+}
+
+/// Read the packets of the first logical stream of an Ogg file, in order
+///
+/// Pages of other logical streams are skipped, and reading stops at the last page
+/// of the stream.
+///
+/// # Errors
+/// Returns `None` if a page is malformed, a page of the stream is missing, the
+/// continuation flags do not match the packets, or the last packet is cut short.
+pub fn read_packets(data: &[u8]) -> Option <Vec <OggPacket>> {
+    let mut packets = Vec::new();
+    let mut serial_number = None;
+    let mut next_sequence_number = 0;
+    let mut partial_packet: Option <Vec <u8>> = None;
+
+    let mut pos = 0;
+    while pos < data.len() {
+        let (page, page_len) = OggPage::from_bytes(&data[pos..])?;
+        pos += page_len;
+        if *serial_number.get_or_insert(page.serial_number) != page.serial_number {
+            continue;
+        }
+        if page.sequence_number != next_sequence_number || page.is_continued() != partial_packet.is_some() {
+            return None;
+        }
+        next_sequence_number += 1;
+
+        let mut packet = partial_packet.take().unwrap_or_default();
+        let mut body_pos = 0;
+        for &lacing_value in page.lacing_values.iter() {
+            packet.extend_from_slice(&page.body[body_pos..body_pos + lacing_value as usize]);
+            body_pos += lacing_value as usize;
+            if lacing_value < 255 {
+                packets.push(OggPacket {
+                    data: mem::take(&mut packet),
+                    granule_position: page.granule_position,
+                });
+            }
+        }
+        // A page without lacing values only carries on the packet it continues
+        if page.lacing_values.last().map_or(page.is_continued(), |&lacing_value| lacing_value == 255) {
+            partial_packet = Some(packet);
+        }
+
+        if page.is_eos() {
+            break;
+        }
+    }
+
+    match partial_packet {
+        Some(_) => None,
+        None => Some(packets),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Write packets into pages, ending the page after the packet `flush_after`, and
+    /// check that the packets read back are the same
+    fn write_pages(packets: &[(Vec <u8>, u64)], flush_after: Option <usize>) -> Vec <OggPage> {
+        let mut writer = OggWriter::new(Vec::new(), 0x1234_5678);
+        for (i, (packet, granule_position)) in packets.iter().enumerate() {
+            writer.write_packet(packet, *granule_position).unwrap();
+            if flush_after == Some(i) {
+                writer.flush().unwrap();
+            }
+        }
+        let bytes = writer.finish().unwrap();
+
+        let read_back: Vec <Vec <u8>> = read_packets(&bytes).unwrap().into_iter().map(|packet| packet.data).collect();
+        let expected: Vec <Vec <u8>> = packets.iter().map(|(packet, _)| packet.clone()).collect();
+        assert_eq!(read_back, expected);
+
+        let mut pages = Vec::new();
+        let mut pos = 0;
+        while pos < bytes.len() {
+            let (page, page_len) = OggPage::from_bytes(&bytes[pos..]).unwrap();
+            pages.push(page);
+            pos += page_len;
+        }
+        pages
+    }
+
+    #[test]
+    fn mapping_packet() {
+        let streaminfo_block = [[0x80, 0, 0, 34].as_slice(), &[0x11; 34]].concat();
+        let packet = build_mapping_packet(&streaminfo_block, 2);
+
+        assert_eq!(&packet[..13], b"\x7fFLAC\x01\0\0\x02fLaC");
+        assert_eq!(read_mapping_packet(&packet), Some((2, streaminfo_block.as_slice())));
+        assert_eq!(read_mapping_packet(&packet[..50]), None);
+        assert_eq!(read_mapping_packet(&[[0x7f].as_slice(), b"FLAC\x02", &packet[6..]].concat()), None);
+    }
+
+    #[test]
+    fn first_page() {
+        let mapping_packet = build_mapping_packet(&[0x11; 38], 0);
+        let mut writer = OggWriter::new(Vec::new(), 0x1234_5678);
+        writer.write_packet(&mapping_packet, 0).unwrap();
+        writer.flush().unwrap();
+        writer.write_packet(&[0x22; 300], 4096).unwrap();
+        let bytes = writer.finish().unwrap();
+
+        let first_page = build_first_page(0x1234_5678, &mapping_packet);
+        assert_eq!(first_page.len(), PAGE_HEADER_LEN + 1 + MAPPING_PACKET_LEN);
+        assert_eq!(&bytes[..first_page.len()], first_page.as_slice());
+    }
+
+    #[test]
+    fn page_bytes_round_trip() {
+        let page = OggPage {
+            header_type: HEADER_TYPE_BOS,
+            granule_position: 0,
+            serial_number: 1,
+            sequence_number: 0,
+            lacing_values: vec![3],
+            body: b"abc".to_vec(),
+        };
+        let mut bytes = page.build_bytes();
+
+        assert_eq!(bytes.len(), 31);
+        assert_eq!(&bytes[..6], b"OggS\0\x02");
+        assert_eq!(OggPage::from_bytes(&bytes), Some((page, 31)));
+        assert_eq!(OggPage::from_bytes(&bytes[..30]), None);
+        bytes[28] ^= 1;
+        assert_eq!(OggPage::from_bytes(&bytes), None);
+    }
+
+    #[test]
+    fn lacing_values() {
+        let packets = vec![(vec![1; 254], 1), (vec![2; 255], 2), (Vec::new(), 3), (vec![3; 510], 4)];
+        let pages = write_pages(&packets, None);
+
+        assert_eq!(pages.len(), 1);
+        assert_eq!(pages[0].header_type, HEADER_TYPE_BOS | HEADER_TYPE_EOS);
+        assert_eq!(pages[0].granule_position, 4);
+        assert_eq!(pages[0].lacing_values, vec![254, 255, 0, 0, 255, 255, 0]);
+    }
+
+    #[test]
+    fn page_boundaries() {
+        // Pages are written out once their body reaches 4096 bytes
+        let packets: Vec <(Vec <u8>, u64)> = (0..5).map(|i| (vec![i as u8; 1500], i + 1)).collect();
+        let pages = write_pages(&packets, Some(0));
+
+        let page_info: Vec <(u8, u32, u64, usize)> = pages.iter()
+            .map(|page| (page.header_type, page.sequence_number, page.granule_position, page.body.len()))
+            .collect();
+        assert_eq!(page_info, vec![
+            (HEADER_TYPE_BOS, 0, 1, 1500),
+            (0, 1, 4, 4500),
+            (HEADER_TYPE_EOS, 2, 5, 1500),
+        ]);
+    }
+
+    #[test]
+    fn continued_packets() {
+        // 300 segments of 255 bytes and an empty one do not fit in the 255 lacing
+        // values of a page, so no packet ends on the first page
+        let packets = vec![(vec![7; 300 * 255], 10), (vec![8; 100], 20)];
+        let pages = write_pages(&packets, None);
+
+        assert_eq!(pages.len(), 3);
+        assert_eq!(pages[0].header_type, HEADER_TYPE_BOS);
+        assert_eq!(pages[0].lacing_values, vec![255; 255]);
+        assert_eq!(pages[0].granule_position, NO_GRANULE_POSITION);
+        assert_eq!(pages[1].header_type, HEADER_TYPE_CONTINUED);
+        assert_eq!(pages[1].lacing_values, [vec![255; 45], vec![0]].concat());
+        assert_eq!(pages[1].granule_position, 10);
+        assert_eq!(pages[2].header_type, HEADER_TYPE_EOS);
+        assert_eq!(pages[2].lacing_values, vec![100]);
+        assert_eq!(pages[2].granule_position, 20);
+    }
+
+    #[test]
+    fn missing_continuation() {
+        let mut writer = OggWriter::new(Vec::new(), 1);
+        writer.write_packet(&vec![7; 300 * 255], 10).unwrap();
+        let bytes = writer.finish().unwrap();
+        let (_, first_page_len) = OggPage::from_bytes(&bytes).unwrap();
+
+        // The packet is cut short without its second page, and the second page
+        // alone continues a packet that never started
+        assert_eq!(read_packets(&bytes[..first_page_len]), None);
+        assert_eq!(read_packets(&bytes[first_page_len..]), None);
+        assert_eq!(read_packets(&bytes).unwrap().len(), 1);
+    }
+
+    #[test]
+    fn other_streams_skipped() {
+        let mut writer = OggWriter::new(Vec::new(), 1);
+        writer.write_packet(&vec![7; 300 * 255], 10).unwrap();
+        let mut bytes = writer.finish().unwrap();
+        let (_, first_page_len) = OggPage::from_bytes(&bytes).unwrap();
+
+        // A page of another logical stream sits between the two pages of the packet
+        let mut other_writer = OggWriter::new(Vec::new(), 2);
+        other_writer.write_packet(b"other", 1).unwrap();
+        bytes.splice(first_page_len..first_page_len, other_writer.finish().unwrap());
+
+        let packets = read_packets(&bytes).unwrap();
+        assert_eq!(packets, vec![OggPacket {data: vec![7; 300 * 255], granule_position: 10}]);
+    }
+}
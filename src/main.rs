@@ -2,9 +2,16 @@ pub mod wav;
 pub mod flac;
 
 fn main() {
-    let file_path = String::from("sample_audio/music_a.wav");
+    let args: Vec <String> = std::env::args().collect();
+    if args.len() < 3 {
+        println!("Usage: {} <input.wav> <output.flac>", args[0]);
+        return;
+    }
+
+    let file_path = &args[1];
+    let out_path = &args[2];
 
-    let wave_obj = wav::WaveReader::open_pcm(&file_path);
+    let wave_obj = wav::WaveReader::open_pcm(file_path);
 
     if let Err(e) = wave_obj {
         println!("Error while reading file {}: {}", file_path, e);
@@ -13,10 +20,13 @@ fn main() {
         println!("File {} opened successfully!", file_path);
         println!("{}", wave_obj_safe);
 
-        for each_chunk in wave_obj_safe.data_chunks {
-            for (i, each_sample) in each_chunk.byte_rate().enumerate() {
-                println!("each sample index {} len {}", i, each_sample.len());
-            }
+        let config = flac::config::EncoderConfig::default();
+        match flac::FlacWriter::write_from_wave(wave_obj_safe, out_path, &config) {
+            Ok(summary) => {
+                println!("File {} written successfully!", out_path);
+                println!("MD5 signature: {}", summary.md5_hex());
+            },
+            Err(e) => println!("Error while writing file {}: {}", out_path, e),
         }
     }
 }
@@ -1,16 +1,39 @@
 use core::fmt;
 use std::fs::File;
-use std::path::Path;
 use std::error;
 use std::io::{self, Read, Seek, SeekFrom};
 
 use byteorder::{ByteOrder, LittleEndian, ReadBytesExt, BigEndian};
 
+/// Format tag of a format chunk that names its sample format by a sub-format GUID
+const WAVE_FORMAT_EXTENSIBLE: u16 = 0xfffe;
+
+/// Sub-format GUID of integer PCM samples, as stored in a format chunk
+const KSDATAFORMAT_SUBTYPE_PCM: [u8; 16] = [
+    0x01, 0x00, 0x00, 0x00, 0x00, 0x00, 0x10, 0x00,
+    0x80, 0x00, 0x00, 0xaa, 0x00, 0x38, 0x9b, 0x71,
+];
+
 /// Represents a PCM WAV file
+/// 
+/// The `foreign_chunks` locate everything in the file except the samples, in file
+/// order: the 12-byte RIFF header, then each chunk including its padding byte,
+/// except that a data chunk only contributes its 8-byte header. Writing their bytes
+/// back in order with the samples after each data chunk header gives back the
+/// original file. The bytes are only read by `read_foreign_chunks`.
 pub struct PCMWaveInfo {
     pub riff_header: RiffChunk,
     pub fmt_header: PCMWaveFormatChunk,
     pub data_chunks: Vec <PCMWaveDataChunk>,
+    pub foreign_chunks: Vec <PCMWaveForeignChunk>,
+    file_path: String,
+}
+
+/// Represents the location of a part of a WAV file other than the samples
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct PCMWaveForeignChunk {
+    pub offset: u64,
+    pub len: u64,
 }
 
 /// Represents a RIFF chnk from a WAV file
@@ -43,6 +66,7 @@ pub struct PCMWaveDataChunk {
     pub size_bytes: u32,
     pub format: PCMWaveFormatChunk,
     pub data_buf: io::BufReader<File>,
+    bytes_left: u32,
 }
 
 /// Represents an iterator to a data chunk from a WAV file
@@ -80,21 +104,68 @@ impl WaveReader {
     /// happens.
     pub fn open_pcm(file_path: &str) -> Result <PCMWaveInfo, WaveReaderError> {
         let mut fh = File::open(file_path)?;
+        let file_len = fh.metadata()?.len();
         let riff_header = Self::read_riff_chunk(&mut fh)?;
-        let fmt_header = Self::read_fmt_chunk(&mut fh)?;
+        let mut fmt_header = None;
         let mut data_chunks = Vec::new();
-        
-        while let Ok(data_chunk) = Self::read_data_chunk(fh.seek(SeekFrom::Current(0))?, &fmt_header, fh.try_clone()?) {
-            data_chunks.push(data_chunk);
+        let mut foreign_chunks = vec![PCMWaveForeignChunk {offset: 0, len: 12}];
+
+        // Walk through the chunks, as other chunks such as `bext` may come before
+        // the format chunk
+        let mut start_pos = 12;
+        loop {
+            fh.seek(SeekFrom::Start(start_pos))?;
+            let mut chunk_header = [0u8; 8];
+            if fh.read_exact(&mut chunk_header).is_err() {
+                break;
+            }
+            let chunk_size = (&chunk_header[4..]).read_u32::<LittleEndian>()? as u64;
+            // Chunks are padded to an even number of bytes
+            let padded_len = 8 + chunk_size + (chunk_size & 1);
+
+            if &chunk_header[..4] == b"fmt " && fmt_header.is_none() {
+                fh.seek(SeekFrom::Start(start_pos))?;
+                fmt_header = Some(Self::read_fmt_chunk(&mut fh)?);
+            }
+
+            if &chunk_header[..4] == b"data" {
+                // The samples can only be read after the format chunk
+                let fmt_header = fmt_header.as_ref().ok_or(WaveReaderError::ChunkTypeError)?;
+                // Each data chunk gets its own file handle so that its reads
+                // do not move the cursor of the other chunks
+                let data_chunk = Self::read_data_chunk(start_pos, fmt_header, File::open(file_path)?)?;
+                data_chunks.push(data_chunk);
+                foreign_chunks.push(PCMWaveForeignChunk {offset: start_pos, len: 8});
+            }
+            else {
+                // The last chunk may be cut short by the end of the file
+                let len = padded_len.min(file_len - start_pos);
+                foreign_chunks.push(PCMWaveForeignChunk {offset: start_pos, len});
+            }
+
+            start_pos += padded_len;
         }
 
         Ok(PCMWaveInfo {
             riff_header,
-            fmt_header,
+            fmt_header: fmt_header.ok_or(WaveReaderError::ChunkTypeError)?,
             data_chunks,
+            foreign_chunks,
+            file_path: file_path.to_string(),
         })
     }
 
+    /// Read `len` bytes of a file starting at `start_pos`
+    /// 
+    /// # Errors
+    /// Returns `WaveReaderError::ReadError` if the file has fewer bytes.
+    fn read_raw_bytes(fh: &mut File, start_pos: u64, len: u64) -> Result <Vec <u8>, WaveReaderError> {
+        fh.seek(SeekFrom::Start(start_pos))?;
+        let mut bytes = vec![0u8; len as usize];
+        fh.read_exact(&mut bytes)?;
+        Ok(bytes)
+    }
+
     /// Read the RIFF header from a PCM WAV file
     /// 
     /// The RIFF header is the first twelve bytes of a PCM WAV
@@ -135,6 +206,8 @@ impl WaveReader {
     /// Read the format chunk from a PCM WAV file
     /// 
     /// The format chunk usually appears immediately after the RIFF header and consists of 24 bytes of metadata.
+    /// A WAVE_FORMAT_EXTENSIBLE format chunk is also accepted if its sub-format is PCM and all
+    /// of its bits per sample are valid.
     /// Note that the file handle `fh` should point to the start of a format chunk.
     /// 
     /// # Errors
@@ -149,12 +222,12 @@ impl WaveReader {
 
         let mut buffer = [0u8; 4];
         fh.read_exact(&mut buffer)?;
-        let _fmt_size = (&buffer[..]).read_u32::<LittleEndian>()?;
+        let fmt_size = (&buffer[..]).read_u32::<LittleEndian>()?;
 
         let mut buffer = [0u8; 2];
         fh.read_exact(&mut buffer)?;
         let audio_format = (&buffer[..]).read_u16::<LittleEndian>()?;
-        if audio_format != 1 {
+        if audio_format != 1 && audio_format != WAVE_FORMAT_EXTENSIBLE {
             return Err(WaveReaderError::NotPCMError);
         }
 
@@ -178,6 +251,27 @@ impl WaveReader {
         fh.read_exact(&mut buffer)?;
         let bps = (&buffer[..]).read_u16::<LittleEndian>()?;
 
+        // The extension holds its size, the valid bits per sample, the channel mask
+        // and the sub-format GUID
+        let mut extension_len = 16;
+        if audio_format == WAVE_FORMAT_EXTENSIBLE {
+            if fmt_size < 40 {
+                return Err(WaveReaderError::NotPCMError);
+            }
+            let mut extension = [0u8; 24];
+            fh.read_exact(&mut extension)?;
+            let valid_bps = LittleEndian::read_u16(&extension[2..4]);
+            if extension[8..] != KSDATAFORMAT_SUBTYPE_PCM || valid_bps != bps {
+                return Err(WaveReaderError::NotPCMError);
+            }
+            extension_len = 40;
+        }
+
+        // Skip any format extension bytes after those read so far
+        if fmt_size > extension_len {
+            fh.seek(SeekFrom::Current((fmt_size - extension_len + (fmt_size & 1)) as i64))?;
+        }
+
         let fmt_chunk = PCMWaveFormatChunk {num_channels, samp_rate, bps};
 
         if byte_rate != fmt_chunk.byte_rate() {
@@ -213,13 +307,12 @@ impl WaveReader {
         fh.read_exact(&mut buffer)?;
         let size_bytes = (&buffer[..]).read_u32::<LittleEndian>()?;
 
-        // // Print the remaining contents of the file
-        // let mut remaining_contents = Vec::new();
-        // fh.read_to_end(&mut remaining_contents)?;
-        // println!("Remaining contents: {:?}", remaining_contents);
-    
-        // Seek back to the start position
-        fh.seek(SeekFrom::Start(start_pos + 4))?; // Adjust the seek position based on your file format
+        // A truncated file holds fewer bytes than the chunk size says
+        let available = fh.metadata()?.len().saturating_sub(start_pos + 8);
+        let bytes_left = (size_bytes as u64).min(available) as u32;
+
+        // Seek to the first sample, right after the chunk ID and size
+        fh.seek(SeekFrom::Start(start_pos + 8))?;
 
         let data_buf = io::BufReader::new(fh);
 
@@ -227,6 +320,7 @@ impl WaveReader {
             size_bytes,
             format: *fmt_info,
             data_buf,
+            bytes_left,
         })
     }
 }
@@ -263,10 +357,45 @@ impl fmt::Display for PCMWaveInfo {
     }
 }
 
+impl PCMWaveInfo {
+    /// Read the bytes of each of the `foreign_chunks` from the file
+    /// 
+    /// # Errors
+    /// Returns `WaveReaderError::ReadError` if the file cannot be read.
+    pub fn read_foreign_chunks(&self) -> Result <Vec <Vec <u8>>, WaveReaderError> {
+        let mut fh = File::open(&self.file_path)?;
+        self.foreign_chunks.iter()
+            .map(|chunk| WaveReader::read_raw_bytes(&mut fh, chunk.offset, chunk.len))
+            .collect()
+    }
+
+    /// Find the bit depth actually used by the samples of all data chunks
+    /// 
+    /// The data chunks are scanned for low-order bits that are zero in every sample
+    /// of every channel, and the effective bit depth is `bps` less the number of such
+    /// bits. A file of digital silence keeps its bit depth. The data chunks are left
+    /// at the same position, so they can still be read afterwards.
+    /// 
+    /// # Errors
+    /// Returns `WaveReaderError::ReadError` if reading or seeking the file fails.
+    pub fn effective_bit_depth(&mut self) -> Result <u16, WaveReaderError> {
+        let mut all_bits = 0;
+        for data_chunk in self.data_chunks.iter_mut() {
+            all_bits |= data_chunk.scan_sample_bits()?;
+        }
+
+        if all_bits == 0 {
+            return Ok(self.fmt_header.bps);
+        }
+        let unused_bits = (all_bits.trailing_zeros() as u16).min(self.fmt_header.bps - 1);
+        Ok(self.fmt_header.bps - unused_bits)
+    }
+}
+
 impl PCMWaveFormatChunk {
     /// Get or calculate the byte rate of this PCM WAV file
     fn byte_rate(&self) -> u32 {
-        self.samp_rate as u32 * self.num_channels as u32 * self.bps as u32 / 8
+        self.samp_rate * self.num_channels as u32 * self.bps as u32 / 8
     }
 
     /// Get or calculate the block alignment of this PCM WAV file
@@ -275,7 +404,7 @@ impl PCMWaveFormatChunk {
     /// in bytes. An *inter-channel sample* is a sample with all of its
     /// channels collated together.
     fn block_align(&self) -> u16 {
-        self.num_channels as u16 * self.bps as u16 / 8
+        self.num_channels * self.bps / 8
     }
 }
 
@@ -283,11 +412,19 @@ impl Iterator for PCMWaveDataChunk {
     type Item = Vec <i64>;
 
     fn next(&mut self) -> Option <Self::Item> {
+        // Stop at the end of the chunk even if the file has more bytes
+        let block_align = self.format.block_align() as u32;
+        if self.bytes_left < block_align {
+            return None;
+        }
+        self.bytes_left -= block_align;
+
         let mut sample = vec![0; self.format.num_channels as usize];
         for i in 0..self.format.num_channels {
             match self.format.bps {
                 8 => {
-                    sample[i as usize] = self.data_buf.read_u8().ok()? as i64;
+                    // 8-bit samples are unsigned with 128 as the midpoint
+                    sample[i as usize] = self.data_buf.read_u8().ok()? as i64 - 128;
                 }
                 16 => {
                     sample[i as usize] = self.data_buf.read_i16::<LittleEndian>().ok()? as i64;
@@ -300,6 +437,9 @@ impl Iterator for PCMWaveDataChunk {
                     ];
                     sample[i as usize] = LittleEndian::read_i24(&bytes) as i64;
                 }
+                32 => {
+                    sample[i as usize] = self.data_buf.read_i32::<LittleEndian>().ok()? as i64;
+                }
                 _ => return None,
             }
             // // Print the value that was just appended
@@ -333,6 +473,34 @@ impl Iterator for PCMWaveDataChunkWindow {
 
 
 impl PCMWaveDataChunk {
+    /// Get the bitwise OR of all samples left in this data chunk
+    /// 
+    /// The chunk is rewound to where it was before the scan.
+    fn scan_sample_bits(&mut self) -> Result <i64, WaveReaderError> {
+        let start_pos = self.data_buf.stream_position()?;
+        let bytes_left = self.bytes_left;
+
+        let mut all_bits = 0;
+        for sample in self.by_ref() {
+            all_bits = sample.iter().fold(all_bits, |acc, value| acc | value);
+        }
+
+        self.data_buf.seek(SeekFrom::Start(start_pos))?;
+        self.bytes_left = bytes_left;
+
+        Ok(all_bits)
+    }
+
+    /// Get the number of inter-channel samples left in this data chunk
+    /// 
+    /// This is computed from the chunk size, bounded by the actual size of the file.
+    pub fn num_samples(&self) -> u64 {
+        match self.format.block_align() {
+            0 => 0,
+            block_align => (self.bytes_left / block_align as u32) as u64,
+        }
+    }
+
     /// Consume a data chunk and get an iterator
     /// 
     /// This method is used to get a *single* inter-channel
@@ -361,9 +529,33 @@ impl PCMWaveDataChunk {
 
 // TODO: Add more tests here!
 #[cfg(test)]
-mod tests {
+pub(crate) mod tests {
     use super::*;
 
+    /// A path in the temporary directory that is removed when dropped
+    pub(crate) struct TempPath(pub(crate) std::path::PathBuf);
+
+    impl TempPath {
+        /// Reserve a path named after `name` that other test runs do not use
+        pub(crate) fn new(name: &str) -> Self {
+            TempPath(std::env::temp_dir().join(format!("midp_{}_{}", std::process::id(), name)))
+        }
+
+        pub(crate) fn as_str(&self) -> &str {
+            self.0.to_str().unwrap()
+        }
+    }
+
+    impl Drop for TempPath {
+        fn drop(&mut self) {
+            if self.0.is_dir() {
+                let _ = std::fs::remove_dir_all(&self.0);
+            } else {
+                let _ = std::fs::remove_file(&self.0);
+            }
+        }
+    }
+
     #[cfg(test)]
     mod read_riff {
         use super::*;
@@ -604,6 +796,72 @@ mod tests {
                         bps: 16,
                     },
                 )),    
+            it_valid_extensible: (
+                &[
+                    0x66, 0x6d, 0x74, 0x20,
+                    0x28, 0x0, 0x0, 0x0,
+                    0xfe, 0xff,
+                    0x02, 0x0,
+                    0x44, 0xac, 0x0, 0x0,
+                    0x98, 0x09, 0x04, 0x0,
+                    0x06, 0x00, 0x18, 0x0,
+                    0x16, 0x0, 0x18, 0x0,
+                    0x03, 0x0, 0x0, 0x0,
+                    0x01, 0x0, 0x0, 0x0, 0x0, 0x0, 0x10, 0x0,
+                    0x80, 0x0, 0x0, 0xaa, 0x0, 0x38, 0x9b, 0x71,
+                ],
+                (
+                    false,
+                    PCMWaveFormatChunk {
+                        num_channels: 2,
+                        samp_rate: 44100,
+                        bps: 24,
+                    },
+                )),
+            it_invalid_extensible_float: (
+                &[
+                    0x66, 0x6d, 0x74, 0x20,
+                    0x28, 0x0, 0x0, 0x0,
+                    0xfe, 0xff,
+                    0x02, 0x0,
+                    0x44, 0xac, 0x0, 0x0,
+                    0x20, 0x62, 0x05, 0x0,
+                    0x08, 0x00, 0x20, 0x0,
+                    0x16, 0x0, 0x20, 0x0,
+                    0x03, 0x0, 0x0, 0x0,
+                    0x03, 0x0, 0x0, 0x0, 0x0, 0x0, 0x10, 0x0,
+                    0x80, 0x0, 0x0, 0xaa, 0x0, 0x38, 0x9b, 0x71,
+                ],
+                (
+                    true,
+                    PCMWaveFormatChunk {
+                        num_channels: 2,
+                        samp_rate: 44100,
+                        bps: 32,
+                    },
+                )),
+            it_invalid_extensible_valid_bits: (
+                &[
+                    0x66, 0x6d, 0x74, 0x20,
+                    0x28, 0x0, 0x0, 0x0,
+                    0xfe, 0xff,
+                    0x02, 0x0,
+                    0x44, 0xac, 0x0, 0x0,
+                    0x20, 0x62, 0x05, 0x0,
+                    0x08, 0x00, 0x20, 0x0,
+                    0x16, 0x0, 0x18, 0x0,
+                    0x03, 0x0, 0x0, 0x0,
+                    0x01, 0x0, 0x0, 0x0, 0x0, 0x0, 0x10, 0x0,
+                    0x80, 0x0, 0x0, 0xaa, 0x0, 0x38, 0x9b, 0x71,
+                ],
+                (
+                    true,
+                    PCMWaveFormatChunk {
+                        num_channels: 2,
+                        samp_rate: 44100,
+                        bps: 32,
+                    },
+                )),
         }
     }
     #[cfg(test)]
@@ -630,9 +888,9 @@ mod tests {
             let res_2 = samp_2.byte_rate();
             let res_3 = samp_3.byte_rate();
 
-            assert_eq!(res_1, 88200 as u32);
-            assert_eq!(res_2, 64000 as u32);
-            assert_eq!(res_3, 6000 as u32);
+            assert_eq!(res_1, 88200);
+            assert_eq!(res_2, 64000);
+            assert_eq!(res_3, 6000);
         }
     }
     #[cfg(test)] 
@@ -668,4 +926,102 @@ mod tests {
     mod read_data_fmt {
         // TODO
     }
+
+    mod foreign_chunks {
+        use super::*;
+        use std::io::Write;
+
+        #[test]
+        fn chunks_in_order() -> Result <(), WaveReaderError> {
+            let riff = b"RIFF\x40\0\0\0WAVE".to_vec();
+            let fmt = b"fmt \x10\0\0\0\x01\0\x01\0\x44\xac\0\0\x44\xac\0\0\x01\0\x08\0".to_vec();
+            let list = b"LIST\x05\0\0\0INFOa\0".to_vec();
+            let data = b"data\x03\0\0\0".to_vec();
+            let smpl = b"smpl\x02\0\0\0\x01\x02".to_vec();
+            let wav_file = TempPath::new("foreign_chunks.wav");
+            {
+                let mut file = File::create(&wav_file.0)?;
+                for chunk in [&riff, &fmt, &list, &data] {
+                    file.write_all(chunk)?;
+                }
+                file.write_all(&[0x80, 0x81, 0x7f, 0])?;
+                file.write_all(&smpl)?;
+            }
+            let wav = WaveReader::open_pcm(wav_file.as_str())?;
+            let foreign_chunks = wav.read_foreign_chunks()?;
+            assert_eq!(foreign_chunks, vec![riff, fmt, list, data, smpl]);
+            let samples: Vec <Vec <i64>> = wav.data_chunks.into_iter().flatten().collect();
+            assert_eq!(samples, vec![vec![0], vec![1], vec![-1]]);
+            Ok(())
+        }
+
+        #[test]
+        fn bext_before_fmt() -> Result <(), WaveReaderError> {
+            // Broadcast Wave files put their `bext` chunk before the format chunk
+            let riff = b"RIFF\x32\0\0\0WAVE".to_vec();
+            let bext = b"bext\x03\0\0\0abc\0".to_vec();
+            let fmt = b"fmt \x10\0\0\0\x01\0\x01\0\x44\xac\0\0\x44\xac\0\0\x01\0\x08\0".to_vec();
+            let data = b"data\x02\0\0\0".to_vec();
+            let wav_file = TempPath::new("bext_before_fmt.wav");
+            {
+                let mut file = File::create(&wav_file.0)?;
+                for chunk in [&riff, &bext, &fmt, &data] {
+                    file.write_all(chunk)?;
+                }
+                file.write_all(&[0x80, 0x7f])?;
+            }
+            let wav = WaveReader::open_pcm(wav_file.as_str())?;
+            let foreign_chunks = wav.read_foreign_chunks()?;
+            assert_eq!((wav.fmt_header.num_channels, wav.fmt_header.samp_rate, wav.fmt_header.bps), (1, 44100, 8));
+            assert_eq!(wav.foreign_chunks, vec![
+                PCMWaveForeignChunk {offset: 0, len: 12},
+                PCMWaveForeignChunk {offset: 12, len: 12},
+                PCMWaveForeignChunk {offset: 24, len: 24},
+                PCMWaveForeignChunk {offset: 48, len: 8},
+            ]);
+            assert_eq!(foreign_chunks, vec![riff, bext, fmt, data]);
+            let samples: Vec <Vec <i64>> = wav.data_chunks.into_iter().flatten().collect();
+            assert_eq!(samples, vec![vec![0], vec![-1]]);
+            Ok(())
+        }
+
+        #[test]
+        fn truncated_data() -> Result <(), WaveReaderError> {
+            let wav = WaveReader::open_pcm("sample_audio/ietf/ietf_01.wav")?;
+
+            assert_eq!(wav.foreign_chunks, vec![
+                PCMWaveForeignChunk {offset: 0, len: 12},
+                PCMWaveForeignChunk {offset: 12, len: 24},
+                PCMWaveForeignChunk {offset: 36, len: 8},
+            ]);
+            let foreign_chunks = wav.read_foreign_chunks()?;
+            assert_eq!(foreign_chunks[0], b"RIFF\x28\0\0\0WAVE");
+            assert_eq!(foreign_chunks[2], b"data\x40\0\0\0");
+            Ok(())
+        }
+    }
+
+    mod effective_bit_depth {
+        use super::*;
+
+        #[test]
+        fn ietf_01() -> Result <(), WaveReaderError> {
+            // 0x63f4 has 2 trailing zero bits and 0x28b0 has 4
+            let mut wav = WaveReader::open_pcm("sample_audio/ietf/ietf_01.wav")?;
+
+            assert_eq!(wav.effective_bit_depth()?, 14);
+            let samples: Vec <Vec <i64>> = wav.data_chunks.into_iter().flatten().collect();
+            assert_eq!(samples, vec![vec![0x63f4, 0x28b0]]);
+            Ok(())
+        }
+
+        #[test]
+        fn full_bit_depth() -> Result <(), WaveReaderError> {
+            let mut wav = WaveReader::open_pcm("sample_audio/m_3s/music_3s.wav")?;
+
+            assert_eq!(wav.effective_bit_depth()?, 16);
+            assert_eq!(wav.data_chunks.remove(0).count(), 140928);
+            Ok(())
+        }
+    }
 }